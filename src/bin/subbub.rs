@@ -2,26 +2,56 @@
 
 use itertools::Itertools;
 use rayon::prelude::*;
-use std::io::Write;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{IsTerminal, Write};
 use std::iter::zip;
 use std::path::{Path, PathBuf};
 use std::process::exit;
-use std::{fs, hash};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
 
 use anyhow::{anyhow, Error};
 use anyhow::{Context, Result};
 use clap::{Args, Parser, Subcommand};
+use encoding_rs::Encoding;
 use log::LevelFilter;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
 use srtlib::Subtitles as SrtSubtitles;
-use subbub::core::data::{hash_subtitles, is_video_file, SyncTool};
-use subbub::core::data::{list_subtitles_files, list_video_files, TMP_DIRECTORY};
-use subbub::core::data::{ShiftDirection, SubtitleSource};
-use subbub::core::ffmpeg::read_subtitles_file;
+use subbub::core::ass::{to_ass, PlayRes};
+use subbub::core::assign::best_assignment;
+use subbub::core::check::{check_against_video, CheckReport};
+use subbub::core::data::{
+    detect_encoding, detect_line_endings, encode_text, extract_archive, hash_path, hash_subtitles,
+    is_archive_file, is_subtitle_file, is_url, is_video_file, parse_srt_file,
+    read_languages_sidecar, read_track_manifest, sniff_subtitles_format, SubtitlesFormat, SyncTool,
+};
+use subbub::core::data::{
+    list_subtitles_files, list_video_files, render_name_template, NameFields, EXTRA_TOOL_ARGS,
+    TMP_DIRECTORY,
+};
+use subbub::core::data::{
+    Alignment, Container, DialogueStyle, DualMode, LineEndings, MatchBy, MergeStyle, Muxer,
+    OutputFormat, ScaleAnchor, ShiftDirection, SubtitleSource, SubtitlesTrack, TrackMetadata,
+    TrackOrder, TrackPosition, TrackSelector,
+};
+use subbub::core::diff::{diff, drift, DiffReport, DriftReport};
+use subbub::core::doctor;
+use subbub::core::export::{to_transcript, TranscriptOptions};
+use subbub::core::ffmpeg::{read_subtitles_file, StreamSelection};
+use subbub::core::fonts::Fonts;
 use subbub::core::log::initialize_logging;
-use subbub::core::merge::merge;
-use subbub::core::modify::{self, strip_html};
-use subbub::core::sync::sync;
-use subbub::core::{ffmpeg, mkvmerge};
+use subbub::core::merge::{concat, merge, merge_many, split};
+use subbub::core::modify::{self, find_overlaps, strip_html, NormalizeOptions, OcrRule};
+use subbub::core::search::grep;
+use subbub::core::sync::{quick_sync, sync, sync_score, sync_to_video, SyncReport};
+use subbub::core::time::{
+    millis_to_timestamp, parse_duration, parse_timestamp, timestamp_to_millis,
+};
+use subbub::core::{ffmpeg, lang, mkvmerge, mux};
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -33,6 +63,46 @@ struct Cli {
     /// when specified, keeps temporary files around
     #[arg(short = 'k', long, default_value = "false", verbatim_doc_comment)]
     keep_tmp_files: bool,
+    /// when specified, replaces output files that already exist
+    /// otherwise, existing output files are skipped with a warning
+    #[arg(short = 'w', long, default_value = "false", verbatim_doc_comment)]
+    overwrite: bool,
+    /// the text encoding of written subtitles files, e.g., windows-1251
+    /// writing fails if a character can't be represented in the encoding
+    #[arg(short = 'e', long, default_value = "utf-8", value_parser = parse_encoding, verbatim_doc_comment)]
+    encoding_out: &'static Encoding,
+    /// the line endings of written subtitles files
+    /// preserve uses the line endings of each input file, or lf if they can't be detected
+    #[arg(long, default_value = "lf", verbatim_doc_comment)]
+    line_endings: LineEndings,
+    /// the format of the information printed by commands that report on subtitles (e.g., diff)
+    #[arg(short = 'f', long, default_value = "text", verbatim_doc_comment)]
+    output_format: OutputFormat,
+    /// the resolution recorded in written ass files (PlayResX/PlayResY), formatted as WIDTHxHEIGHT, e.g., 1920x1080
+    /// this is usually the video's resolution; srt files have no equivalent (nor a framerate), so this only applies to ass output
+    #[arg(long, value_name = "WIDTHxHEIGHT", verbatim_doc_comment)]
+    play_res: Option<PlayRes>,
+    /// when specified, writes a json summary of the items processed by a batch command to the given path
+    /// (the numbers of items processed, skipped, and failed, the total time, and the output directories)
+    #[arg(long, verbatim_doc_comment)]
+    report: Option<PathBuf>,
+    /// extra arguments appended to every ffsubsync command, for options subbub doesn't support, e.g., "--gss --max-offset-seconds 120"
+    /// arguments are split like a shell would, so quote arguments that contain spaces
+    #[arg(long, value_parser = parse_tool_args, allow_hyphen_values = true, verbatim_doc_comment)]
+    ffsubsync_args: Option<ToolArgs>,
+    /// extra arguments appended to every mkvmerge command that adds subtitles, e.g., "--disable-track-statistics-tags"
+    /// arguments are split like a shell would, so quote arguments that contain spaces
+    #[arg(long, value_parser = parse_tool_args, allow_hyphen_values = true, verbatim_doc_comment)]
+    mkvmerge_args: Option<ToolArgs>,
+    /// when specified, input files that can't be read are skipped with a warning, and the command continues with the rest
+    /// otherwise, the command fails before processing anything if any input can't be read
+    /// skipped inputs are counted as failed, so the command exits with code 2
+    #[arg(long, default_value = "false", verbatim_doc_comment)]
+    skip_errors: bool,
+    /// when specified, batches are processed one file at a time, in order, rather than in parallel
+    /// slower, but the log output of each file isn't interleaved with the others, which helps when debugging a failure
+    #[arg(long, default_value = "false", verbatim_doc_comment)]
+    no_parallel: bool,
     #[clap(subcommand)]
     command: Commands,
 }
@@ -42,6 +112,11 @@ enum Commands {
     /// commands to modify subtitles
     Subtitles(Subtitles),
     CompoundOperations(CompoundOperations),
+    /// commands to inspect videos
+    Video(Video),
+    /// checks that the external tools subbub uses are installed, and that the temporary directory is writable
+    #[clap(verbatim_doc_comment)]
+    Doctor,
     /// command for testing
     #[cfg(debug_assertions)]
     Debug,
@@ -51,16 +126,40 @@ enum Commands {
 #[clap(alias = "subs")]
 struct Subtitles {
     /// the subtitles used as input
-    /// this may be a subtitles file, a video file, or a directory containing either subtitles files or video files
+    /// this may be a subtitles file, a video file, a zip archive of subtitles files, or a directory containing either subtitles files or video files
+    /// when built with the url feature, it may also be the http(s) url of a subtitles file, which is downloaded
     #[arg(short = 'i', long, verbatim_doc_comment)]
     input: PathBuf,
     /// the subtitles track to use if the input is a video
+    /// either the track's index among the video's subtitles tracks (e.g., 2), or its language (e.g., lang=eng)
     #[arg(short = 't', long, verbatim_doc_comment)]
-    track: Option<u32>,
+    track: Option<TrackSelector>,
     /// the location to output the modified subtitles
     /// if the input contains multiple subtitles, this will be considered a directory, otherwise, a filename
+    /// required by every command except those that only report information (e.g., diff), unless --in-place is specified
     #[arg(short = 'o', long, verbatim_doc_comment)]
-    output: PathBuf,
+    output: Option<PathBuf>,
+    /// when specified, the modified subtitles are written back to the input files instead of to an output path
+    /// only srt files can be modified in place, since subtitles are always written in srt format
+    #[arg(
+        short = 'p',
+        long,
+        default_value = "false",
+        conflicts_with = "output",
+        verbatim_doc_comment
+    )]
+    in_place: bool,
+    /// the template used to name output files when the output is a directory, e.g., "{stem}.{lang}.forced.srt"
+    /// defaults to the input's filename, or "{stem}{suffix}.srt" for match-videos
+    /// placeholders:
+    ///     {stem}    the file stem of the input (for match-videos, of the video the subtitles are matched with)
+    ///     {lang}    the language of the input from a languages.toml sidecar, or empty if it has none
+    ///     {index}   the position of the input in order of filename, starting from 1
+    ///     {suffix}  the suffix given to match-videos, or empty for other commands
+    ///     {ext}     the extension of the input
+    /// an empty {lang} is removed along with its separating dot, e.g., "{stem}.{lang}.srt" becomes "{stem}.srt"
+    #[arg(short = 'n', long, value_parser = parse_name_template, verbatim_doc_comment)]
+    name_template: Option<String>,
     #[clap(subcommand)]
     command: SubtitlesCommand,
 }
@@ -69,45 +168,355 @@ struct Subtitles {
 #[clap(verbatim_doc_comment)]
 enum SubtitlesCommand {
     /// converts the given subtitle file(s) to srt format
+    /// if the output is a single file ending in .ass or .ssa, it's converted to ass format instead
     #[clap(verbatim_doc_comment)]
     ConvertSubtitles,
+    /// extracts the given subtitles track (-t/--track) from the given video(s), saving each as an srt file named after its video
+    /// videos that don't have the track are skipped with a warning
+    #[clap(verbatim_doc_comment)]
+    Extract,
+    /// repairs malformed timestamps in the given srt file(s) so that they can be parsed
+    /// e.g., '.' instead of ',' before milliseconds, missing leading zeros, or irregular spacing around -->
+    /// unlike other commands, the input is read as-is rather than first being converted by ffmpeg
+    #[clap(verbatim_doc_comment)]
+    Repair,
     /// strips html from the given subtitle file(s)
     #[clap(verbatim_doc_comment)]
-    StripHtml,
-    /// shifts the timing of the given subtitle(s) earlier or later by the given value in seconds
+    StripHtml {
+        /// keeps italics (<i> tags), which often mark narration or off-screen speech
+        #[arg(long, default_value = "false", verbatim_doc_comment)]
+        keep_italics: bool,
+        /// keeps bold text (<b> tags)
+        #[arg(long, default_value = "false", verbatim_doc_comment)]
+        keep_bold: bool,
+    },
+    /// strips html and removes bracketed information such as [music] or (laughs) from the given subtitle file(s)
+    #[clap(verbatim_doc_comment)]
+    Clean,
+    /// shifts the timing of the given subtitle(s) earlier or later by the given amount of time
     #[clap(verbatim_doc_comment)]
     ShiftTiming {
         /// the number of seconds to shift the subtitle(s)
-        #[arg(short = 's', long)]
-        seconds: f32,
+        #[arg(
+            short = 's',
+            long,
+            required_unless_present = "time",
+            conflicts_with = "time"
+        )]
+        seconds: Option<f32>,
+        /// the amount of time to shift the subtitle(s), formatted as HH:MM:SS.mmm
+        #[arg(short = 'm', long, value_parser = parse_timestamp)]
+        time: Option<i64>,
         /// the direction to shift the subtitles
         #[arg(short = 'd', long)]
         direction: ShiftDirection,
     },
+    /// shifts the timing of the given subtitle(s) so that the given subtitle starts at the given time
+    /// e.g., when it's known that subtitle 42 should appear at 00:05:03, all subtitles are shifted by the same amount
+    #[clap(verbatim_doc_comment)]
+    Anchor {
+        /// the number of the subtitle to anchor
+        #[arg(short = 'c', long, verbatim_doc_comment)]
+        cue: usize,
+        /// the time the subtitle should start at, formatted as HH:MM:SS.mmm
+        #[arg(short = 'a', long, value_parser = parse_timestamp, verbatim_doc_comment)]
+        at: i64,
+    },
+    /// shifts the timing of only the secondary (top) subtitles of dual subtitles created by combine, leaving the primary subtitles as they are
+    /// the secondary subtitles are identified by their {\an8} tag
+    #[clap(verbatim_doc_comment)]
+    ShiftSecondary {
+        /// the number of seconds to shift the secondary subtitles
+        #[arg(short = 's', long)]
+        seconds: f32,
+        /// the direction to shift the secondary subtitles
+        #[arg(short = 'd', long, default_value = "later")]
+        direction: ShiftDirection,
+    },
+    /// shifts the timing of the given subtitle(s) earlier so that the first subtitle starts at the beginning of the video
+    /// useful for subtitles with a constant lead-in
+    #[clap(verbatim_doc_comment)]
+    ZeroBase {
+        /// the time to leave before the first subtitle, e.g., 500ms, 1.5s, or 00:00:01.5
+        #[arg(short = 'k', long, default_value = "0ms", value_parser = parse_duration, verbatim_doc_comment)]
+        keep_lead: i64,
+    },
+    /// stretches or compresses the timeline of the given subtitle(s) by the given factor, e.g., 1.002 to make them 0.2% slower
+    /// this corrects subtitles that gradually drift out of sync, which a constant shift can't
+    #[clap(verbatim_doc_comment)]
+    Scale {
+        /// the factor to multiply the time of each subtitle by, measured from the anchor
+        #[arg(short = 'x', long, verbatim_doc_comment)]
+        factor: f64,
+        /// the time that stays fixed, either the start of the video or the start of the first subtitle
+        #[arg(short = 'a', long, default_value = "zero", verbatim_doc_comment)]
+        anchor: ScaleAnchor,
+        /// when specified, records the conversion next to each output, so that it can be reproduced
+        /// since srt files can't record a framerate, the log of e.g., episode.srt is written to episode.srt.scale.json
+        #[arg(long, default_value = "false", verbatim_doc_comment)]
+        scale_log: bool,
+    },
+    /// keeps only the subtitles displayed within the given time window, e.g., for creating clips
+    /// subtitles partially within the window are clipped to it, and the start of the window becomes 00:00:00
+    #[clap(verbatim_doc_comment)]
+    Trim {
+        /// the start of the window, e.g., 00:10:00 or 600s
+        #[arg(short = 's', long, value_parser = parse_duration, verbatim_doc_comment)]
+        start: i64,
+        /// the end of the window, e.g., 00:12:30 or 750s
+        #[arg(short = 'e', long, value_parser = parse_duration, verbatim_doc_comment)]
+        end: i64,
+        /// when specified, the subtitles keep their original timing instead of being shifted to the start of the window
+        #[arg(short = 'n', long, default_value = "false", verbatim_doc_comment)]
+        no_rebase: bool,
+    },
+    /// converts the given subtitle(s) to a plain text transcript, without timing, html, or bracketed information (e.g., [music])
+    /// pauses between subtitles separate paragraphs; when converting several files, the outputs are named with a .txt extension
+    #[clap(verbatim_doc_comment)]
+    Transcript {
+        /// prefixes each line with the time its first subtitle starts, e.g., [01:23]
+        #[arg(long, default_value = "false", verbatim_doc_comment)]
+        timestamps: bool,
+        /// continues a line with the following subtitles until its sentence ends, rather than placing each subtitle on its own line
+        #[arg(short = 'j', long, default_value = "false", verbatim_doc_comment)]
+        join_sentences: bool,
+    },
+    /// fixes mojibake left by text that was encoded as utf-8 twice, e.g., "Ã©" in place of "é"
+    /// subtitles are only changed when the fixed text is more plausible than the original
+    #[clap(verbatim_doc_comment)]
+    FixMojibake,
+    /// fixes common OCR errors, such as "l" in place of "I" or "0" in place of "O"
+    /// substitutions only apply in the context of a word, see --rules for the rule format
+    #[clap(verbatim_doc_comment)]
+    OcrFix {
+        /// a json file containing additional substitution rules, e.g.,
+        /// [{"find": "rnore", "replace": "more", "context": "word"}]
+        /// context may be one of: word, uppercase_word_start, inside_uppercase_word
+        #[arg(short = 'r', long, verbatim_doc_comment)]
+        rules: Option<PathBuf>,
+        /// when specified, only the rules given with --rules are applied
+        #[arg(short = 'n', long, default_value = "false", requires = "rules")]
+        no_default_rules: bool,
+    },
+    /// removes credits (e.g., "Subtitles by ...", urls, or group tags) from the start and end of the given subtitle(s)
+    /// only cues at the very start or end of the subtitles are removed, so that dialogue is left intact
+    #[clap(verbatim_doc_comment)]
+    StripCredits {
+        /// a file containing additional patterns (regular expressions) that identify credits, one per line
+        /// patterns are case-insensitive, and empty lines and lines starting with # are ignored
+        #[arg(short = 'p', long, verbatim_doc_comment)]
+        patterns: Option<PathBuf>,
+        /// when specified, only the patterns given with --patterns are used
+        #[arg(short = 'n', long, default_value = "false", requires = "patterns")]
+        no_default_patterns: bool,
+    },
+    /// filters out subtitles that are displayed for too short a time to be read
+    /// and/or shortens subtitles that are displayed for too long
+    #[clap(verbatim_doc_comment)]
+    Filter {
+        /// the minimum duration in milliseconds that a subtitle must be displayed for
+        #[arg(short = 'm', long, required_unless_present = "max_duration")]
+        min_duration: Option<i64>,
+        /// the maximum duration in milliseconds that a subtitle may be displayed for
+        /// longer subtitles are shortened to this duration, or to the start of the next subtitle, whichever is earlier
+        #[arg(short = 'x', long, verbatim_doc_comment)]
+        max_duration: Option<i64>,
+        /// when specified, subtitles shorter than the minimum duration are extended instead of removed
+        /// subtitles are never extended past the start of the next subtitle
+        #[arg(short = 'e', long, default_value = "false", verbatim_doc_comment)]
+        extend: bool,
+    },
+    /// extends each subtitle until the next subtitle starts, so that there are no blank gaps between them
+    /// the last subtitle is left unchanged
+    #[clap(verbatim_doc_comment)]
+    FillGaps {
+        /// the maximum duration in milliseconds that a subtitle may be extended by
+        /// gaps longer than this are only partially filled
+        #[arg(short = 'x', long, default_value = "2000", verbatim_doc_comment)]
+        max_extend: i64,
+    },
+    /// normalizes how dialogue between several speakers is formatted
+    /// a cue is dialogue when its first line starts with a dash, and at least one other line does too
+    /// the two styles are reversible, i.e., converting to one style and back gives the original subtitles
+    #[clap(verbatim_doc_comment)]
+    Dialogue {
+        /// dashes joins cues displayed at exactly the same time into one cue, each line starting with "- "
+        /// separate splits dialogue cues into one cue per speaker, without dashes, all displayed at the same time
+        #[arg(short = 's', long, default_value = "dashes", verbatim_doc_comment)]
+        style: DialogueStyle,
+    },
+    /// extends subtitles that are read faster than the given number of characters per second, until they can be read comfortably
+    /// subtitles are never extended past the start of the next subtitle, so some may remain too fast; these are reported
+    #[clap(verbatim_doc_comment)]
+    EnforceCps {
+        /// the maximum reading speed, in characters per second
+        #[arg(short = 'x', long = "max", default_value = "20", verbatim_doc_comment)]
+        max_cps: f64,
+        /// the minimum duration of an extended subtitle, e.g., 500ms, 1.5s, or 00:00:01.5
+        #[arg(short = 'm', long, default_value = "1s", value_parser = parse_duration, verbatim_doc_comment)]
+        min_duration: i64,
+    },
+    /// reports subtitles that overlap the next subtitle displayed at the same position, and truncates them to remove the overlap
+    /// subtitles at different positions (e.g., the top and bottom of dual subtitles) may overlap, and are left unchanged
+    #[clap(verbatim_doc_comment)]
+    FixOverlaps {
+        /// when specified, overlapping subtitles are only reported, and no output is written
+        #[arg(short = 'n', long, default_value = "false", verbatim_doc_comment)]
+        report_only: bool,
+    },
+    /// normalizes the text of the given subtitle(s)
+    /// each transformation must be enabled individually, so that e.g., CJK-only files aren't altered unintentionally
+    #[clap(verbatim_doc_comment)]
+    #[group(required = true, multiple = true)]
+    Normalize {
+        /// converts full-width ASCII characters (e.g., ＡＢＣ！) to their half-width forms
+        #[arg(short = 'f', long, default_value = "false")]
+        full_width: bool,
+        /// converts curly quotes to straight quotes
+        #[arg(short = 'q', long, default_value = "false")]
+        smart_quotes: bool,
+        /// collapses repeated whitespace within each line
+        #[arg(short = 'w', long, default_value = "false")]
+        whitespace: bool,
+    },
+    /// shifts the timing of each of the given subtitles by its own offset, as listed in a csv manifest
+    /// the manifest has a header row, and a row for each file with its filename and offset in seconds, e.g.,
+    ///     filename,seconds
+    ///     episode01.srt,1.5
+    ///     episode02.srt,-0.25
+    /// negative offsets shift the subtitles earlier, and input files not listed in the manifest are skipped
+    #[clap(verbatim_doc_comment)]
+    ShiftBatch {
+        /// the csv file mapping filenames to offsets
+        #[arg(short = 'm', long)]
+        manifest: PathBuf,
+    },
     /// syncs the timing of the given subtitles(s) to the secondary subtitle(s)
     #[clap(verbatim_doc_comment)]
     Sync {
-        /// the secondary subtitles to add to the given subtitles
-        #[arg(short = 'r', long, visible_alias = "reference")]
+        /// the subtitles to sync the given subtitles to
+        /// if this is a video (or directory of videos) and no track (-y) is given, the subtitles are synced to the video's audio instead
+        /// which is useful when there are no good reference subtitles, but is slower
+        #[arg(short = 'r', long, visible_alias = "reference", verbatim_doc_comment)]
         reference_subtitles: PathBuf,
-        /// the subtitles track, if the secondary subtitles are contained in a video
+        /// the subtitles track, if the reference subtitles are contained in a video
         #[arg(short = 'y', long, visible_alias = "track2")]
-        reference_track: Option<u32>,
-        /// the tool to use to sync the subs
-        #[arg(short = 't', long, visible_alias = "tool", default_value = "ffsubsync")]
-        sync_tool: SyncTool,
+        reference_track: Option<TrackSelector>,
+        /// the tools to use to sync the subs, separated by commas
+        /// each tool is tried in order until one succeeds
+        #[arg(
+            short = 't',
+            long = "tools",
+            visible_aliases = ["tool", "sync-tool"],
+            value_delimiter = ',',
+            default_value = "ffsubsync",
+            verbatim_doc_comment
+        )]
+        sync_tools: Vec<SyncTool>,
+        /// when specified, syncs using only the subtitles in the first part of each file (5 minutes, unless a duration is given)
+        /// the offset found is then applied to the whole file, which is much faster, but assumes the subtitles are off by a constant offset
+        /// this won't correct subtitles that drift out of sync (e.g., due to a different framerate)
+        #[arg(
+            short = 'q',
+            long,
+            value_name = "SAMPLE_DURATION",
+            num_args = 0..=1,
+            default_missing_value = "05:00",
+            value_parser = parse_duration,
+            verbatim_doc_comment
+        )]
+        quick: Option<i64>,
+        /// when specified, writes the details of each sync (the tool used, offset, score, and duration) next to its output
+        /// the log of e.g., episode.srt is written to episode.srt.sync.json
+        #[arg(long, default_value = "false", verbatim_doc_comment)]
+        sync_log: bool,
     },
     /// combines the given subtitles with another set of subtitles, creating dual subtitles (displaying both at the same time)
     /// primary subtitles will be displayed below the video
     /// secondary subtitles will be displayed above the video
+    /// more than one set of secondary subtitles may be given (e.g., for triple subtitles)
     #[clap(verbatim_doc_comment)]
     Combine {
         /// the secondary subtitles to add to the given subtitles
-        #[arg(short = 's', long, visible_alias = "secondary")]
-        secondary_subtitles: PathBuf,
+        /// may be repeated to add several sets of subtitles
+        #[arg(
+            short = 's',
+            long,
+            visible_alias = "secondary",
+            required_unless_present = "languages",
+            verbatim_doc_comment
+        )]
+        secondary_subtitles: Vec<PathBuf>,
         /// the subtitles track, if the secondary subtitles are contained in a video
         #[arg(short = 'y', long, visible_alias = "track2")]
-        secondary_track: Option<u32>,
+        secondary_track: Option<TrackSelector>,
+        /// instead of separate directories, combines the subtitles in the input directory that are named after the same file with these language suffixes
+        /// separated by commas, primary language first, e.g., en,ja combines episode01.en.srt (primary) with episode01.ja.srt (secondary)
+        /// files with other language suffixes are ignored
+        #[arg(
+            long,
+            value_delimiter = ',',
+            conflicts_with_all = ["secondary_subtitles", "secondary_track"],
+            verbatim_doc_comment
+        )]
+        languages: Vec<String>,
+        /// where to display each set of secondary subtitles, in the order they were given
+        /// defaults to top for the first set, and middle for the second
+        #[arg(short = 'a', long, verbatim_doc_comment)]
+        alignment: Vec<Alignment>,
+        /// how the combined subtitles are displayed
+        /// top-bottom displays each set of subtitles at its own alignment, using tags such as {\an8}
+        /// stacked combines subtitles displayed at the same time into one cue, one line per set of subtitles, for players that display alignment tags as text
+        #[arg(long, default_value = "top-bottom", verbatim_doc_comment)]
+        style: MergeStyle,
+    },
+    /// compares the given subtitles with another set of subtitles, reporting differences in cue count, timing, and text
+    /// cues are matched by nearest start time; a median offset that holds for most cues suggests a constant shift
+    #[clap(verbatim_doc_comment)]
+    Diff {
+        /// the subtitles to compare against
+        #[arg(short = 's', long)]
+        other: PathBuf,
+        /// the subtitles track, if the other subtitles are contained in a video
+        #[arg(short = 'y', long, visible_alias = "track2")]
+        other_track: Option<TrackSelector>,
+    },
+    /// measures how the offset between the given subtitles and the reference subtitles changes over time, recommending a correction
+    /// a constant offset can be corrected with shift-timing, while an offset that grows (drift, e.g., from a different framerate) needs scale
+    /// cues are matched by nearest start time, so an offset larger than the gaps between cues should be roughly corrected first
+    #[clap(verbatim_doc_comment)]
+    Drift {
+        /// the subtitles to compare against
+        #[arg(short = 'r', long, visible_alias = "reference")]
+        reference_subtitles: PathBuf,
+        /// the subtitles track, if the reference subtitles are contained in a video
+        #[arg(short = 'y', long, visible_alias = "track2")]
+        reference_track: Option<TrackSelector>,
+    },
+    /// checks that the given subtitle(s) plausibly belong to the given video(s), reporting why they don't
+    /// i.e., that the subtitles don't extend past the end of the video, cover most of it, and are about as dense as dialogue
+    /// when both are directories, subtitles and videos are paired in order of their filenames
+    #[clap(verbatim_doc_comment)]
+    Check {
+        /// the video file, or directory of videos, to check the subtitles against
+        #[arg(short = 'v', long, verbatim_doc_comment)]
+        video: PathBuf,
+    },
+    /// lists the given subtitles file(s), along with their format (detected from their content, rather than their extension), text encoding, and number of subtitles
+    /// useful for finding out why a file can't be read
+    #[clap(verbatim_doc_comment)]
+    Ls,
+    /// prints the subtitles whose text matches the given pattern, along with their file, number, and timing
+    #[clap(verbatim_doc_comment)]
+    Grep {
+        /// the text to search for
+        pattern: String,
+        /// when specified, the search ignores case
+        #[arg(short = 'c', long, default_value = "false")]
+        ignore_case: bool,
+        /// when specified, the pattern is treated as a regular expression rather than plain text
+        #[arg(short = 'r', long, default_value = "false")]
+        regex: bool,
     },
     /// takes the subtitles from their current directory and places them alongside the videos present in the output directory
     /// also renames them to match the videos
@@ -115,8 +524,26 @@ enum SubtitlesCommand {
     #[clap(verbatim_doc_comment)]
     MatchVideos {
         /// the suffix to place at the end of the subtitles file to distinguish it from other subtitle files in the same directory
-        #[arg(short = 's', long)]
+        /// if not specified, the subtitles' language from a languages.toml sidecar in the input directory is used, if any
+        /// otherwise, the name of the input directory is used
+        #[arg(short = 's', long, verbatim_doc_comment)]
         suffix: Option<String>,
+        /// how the subtitles are paired with the videos
+        /// order pairs them in order of their filenames
+        /// content syncs every subtitles file against every video's audio with ffsubsync, and picks the pairing that fits best overall
+        /// content is slow (one sync per subtitles file and video), but works when the filenames don't line up (e.g., episode titles and numbers)
+        #[arg(short = 'm', long, default_value = "order", verbatim_doc_comment)]
+        match_by: MatchBy,
+    },
+    /// concatenates subtitles that were split into multiple parts (e.g., CD1/CD2) into a single file
+    /// the parts are taken from the input directory in order of their filenames
+    #[clap(verbatim_doc_comment)]
+    Concat {
+        /// how far to shift each part
+        /// "auto" places each part immediately after the end of the previous part
+        /// otherwise, a comma-separated list of HH:MM:SS.mmm offsets, one for each part
+        #[arg(short = 'f', long, default_value = "auto", verbatim_doc_comment)]
+        offsets: String,
     },
     /// adds given subtitle(s) (-i/--input) to the given video(s) (-v/--video_path)
     #[clap(verbatim_doc_comment)]
@@ -125,8 +552,89 @@ enum SubtitlesCommand {
         #[arg(short = 'v', long)]
         video_path: PathBuf,
         /// the language code that will be assigned to the newly added subtitle track
-        #[arg(short = 'c', long)]
-        language_code: String,
+        /// if the input directory contains a languages.toml sidecar mapping filenames to language codes, e.g.,
+        ///     "episode01.srt" = "eng"
+        /// the sidecar's language is used for the files it lists
+        /// if neither is given, the language is detected from the text of the subtitles
+        #[arg(short = 'c', long, verbatim_doc_comment)]
+        language_code: Option<String>,
+        /// when specified, removes any existing subtitle tracks with the same language code from the video(s)
+        /// this avoids players choosing between multiple tracks of the same language
+        #[arg(short = 'r', long, default_value = "false", verbatim_doc_comment)]
+        replace_language: bool,
+        /// when specified, flags the newly added subtitle track as forced
+        /// players display forced tracks automatically, e.g., for subtitles that only cover foreign dialogue
+        #[arg(short = 'f', long, default_value = "false", verbatim_doc_comment)]
+        forced: bool,
+        /// when specified, flags the newly added subtitle track as default
+        /// players select the default track when the viewer hasn't chosen one
+        #[arg(long = "default", default_value = "false", verbatim_doc_comment)]
+        default_track: bool,
+        /// where the newly added subtitle track is placed among the video's existing subtitle tracks
+        /// players list tracks in this order
+        #[arg(long, default_value = "last", verbatim_doc_comment)]
+        track_order: TrackPosition,
+        /// when specified, checks each output video with ffprobe after muxing, failing if it doesn't contain the expected subtitle tracks
+        /// the muxer may silently drop a track, e.g., for malformed srt files
+        #[arg(long, default_value = "false", verbatim_doc_comment)]
+        verify: bool,
+        /// the tool used to add the subtitle tracks to the videos
+        /// ffmpeg can be used when mkvtoolnix isn't installed; both support each track's language, name, and default and forced flags
+        /// --mkvmerge-args only applies to mkvmerge
+        #[arg(long, default_value = "mkvmerge", verbatim_doc_comment)]
+        muxer: Muxer,
+        /// a directory of font files (.ttf, .otf, .ttc, .otc) to attach to the output video(s)
+        /// ass subtitles are displayed with the fonts their styles name, so those fonts must be attached (or installed) for them to look right
+        /// fonts used by the subtitles that aren't in the directory are reported; only supported with --muxer mkvmerge
+        #[arg(long, verbatim_doc_comment)]
+        fonts: Option<PathBuf>,
+    },
+}
+
+#[derive(Args, Debug)]
+struct Video {
+    /// the video file to inspect
+    /// commands that check videos in bulk (e.g., verify) also accept a directory of videos
+    #[arg(short = 'i', long, visible_short_alias = 'v', verbatim_doc_comment)]
+    input: PathBuf,
+    #[clap(subcommand)]
+    command: VideoCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum VideoCommand {
+    /// lists the chapters of the given video
+    #[clap(verbatim_doc_comment)]
+    Chapters,
+    /// checks that every subtitles track in the given video(s) can be extracted and parsed, reporting any that are corrupt or empty
+    /// image-based tracks are skipped, since reading them requires OCR
+    #[clap(verbatim_doc_comment)]
+    Verify,
+    /// makes the given subtitles track the default subtitles track of the given mkv video, clearing the flag on its other subtitles tracks
+    /// uses mkvpropedit to change the flags without remuxing if it's installed, otherwise remuxes the video with mkvmerge
+    #[clap(verbatim_doc_comment)]
+    SetDefault {
+        /// the subtitles track to make the default, as its index among the video's subtitles tracks (e.g., 2)
+        #[arg(
+            short = 't',
+            long,
+            visible_alias = "track",
+            required_unless_present = "clear",
+            verbatim_doc_comment
+        )]
+        subtitles_track: Option<u32>,
+        /// when specified, clears the default flag of every subtitles track instead
+        #[arg(
+            long,
+            default_value = "false",
+            conflicts_with = "subtitles_track",
+            verbatim_doc_comment
+        )]
+        clear: bool,
+        /// the path to write the edited video to
+        /// if not specified, the video is edited in place
+        #[arg(short = 'o', long, verbatim_doc_comment)]
+        output: Option<PathBuf>,
     },
 }
 
@@ -151,9 +659,18 @@ enum CompoundOperationsCommand {
         #[arg(short = 'v', long)]
         videos_path: PathBuf,
         /// the subtitles track in the video to use as a timing reference
+        /// either the track's index among the video's subtitles tracks (e.g., 2), or its language (e.g., lang=eng)
+        /// required unless every video is listed in --track-manifest
         #[clap(verbatim_doc_comment)]
         #[arg(short = 't', long, visible_alias = "track")]
-        subtitles_track: u32,
+        subtitles_track: Option<TrackSelector>,
+        /// a toml file mapping video filenames to the subtitles track to use as a timing reference for that video, e.g.,
+        ///     "episode01.mkv" = 2
+        ///     "episode02.mkv" = "lang=eng"
+        /// useful when the reference track differs between videos; videos that aren't listed use --subtitles-track
+        #[clap(verbatim_doc_comment)]
+        #[arg(short = 'm', long)]
+        track_manifest: Option<PathBuf>,
         /// the directory containing the subtitles files
         #[clap(verbatim_doc_comment)]
         #[arg(short = 's', long)]
@@ -167,6 +684,80 @@ enum CompoundOperationsCommand {
         #[clap(verbatim_doc_comment)]
         #[arg(short = 'c', long, visible_alias = "lang")]
         language_code: String,
+        /// the name of the newly added subtitles track
+        /// defaults to the language code
+        #[clap(verbatim_doc_comment)]
+        #[arg(long)]
+        single_track_name: Option<String>,
+        /// the name of the newly added dual subtitles track
+        /// defaults to dual-<language code>
+        #[clap(verbatim_doc_comment)]
+        #[arg(long, visible_alias = "secondary-track-name")]
+        dual_track_name: Option<String>,
+        /// the language code of the newly added dual subtitles track
+        /// if not specified, the track has no language code
+        #[clap(verbatim_doc_comment)]
+        #[arg(long)]
+        dual_language_code: Option<String>,
+        /// how the two sets of subtitles are added to each video
+        /// merged adds the given subtitles, along with a dual track that displays both sets of subtitles at once
+        /// separate adds the given subtitles aligned at the top of the video, along with the reference subtitles at the bottom, as two tracks
+        /// for players that can display two subtitle tracks at once (e.g., mpv's secondary subtitles)
+        /// in separate mode, the dual track name and language code apply to the reference subtitles track
+        #[clap(verbatim_doc_comment)]
+        #[arg(long, default_value = "merged")]
+        dual_mode: DualMode,
+        /// when specified, the reference subtitles extracted from each video are saved to this directory
+        /// useful for comparing against the given subtitles when a sync looks wrong
+        #[clap(verbatim_doc_comment)]
+        #[arg(short = 'd', long)]
+        dump_reference: Option<PathBuf>,
+        /// the order of the newly added subtitle tracks, which players list them in
+        /// both are placed after the video's existing subtitle tracks
+        #[clap(verbatim_doc_comment)]
+        #[arg(long, default_value = "single-first")]
+        track_order: TrackOrder,
+        /// when specified, checks each output video with ffprobe after muxing, failing if it doesn't contain the expected subtitle tracks
+        /// the muxer may silently drop a track, e.g., for malformed srt files
+        #[clap(verbatim_doc_comment)]
+        #[arg(long, default_value = "false")]
+        verify: bool,
+        /// the tool used to add the subtitle tracks to the videos
+        /// ffmpeg can be used when mkvtoolnix isn't installed; both support each track's language, name, and default and forced flags
+        /// --mkvmerge-args only applies to mkvmerge
+        #[clap(verbatim_doc_comment)]
+        #[arg(long, default_value = "mkvmerge")]
+        muxer: Muxer,
+        /// the container of the output videos
+        /// mp4 videos are always muxed with ffmpeg (mkvmerge can't write them), and their subtitles are stored as mov_text
+        /// which doesn't support styling or positioning, so dual subtitles can't be placed at the top of the video
+        /// the videos' existing subtitles are converted to mov_text too, which fails for image-based (e.g., PGS) tracks; use --drop-original-subs for these
+        /// mp4 videos can't contain attachments either, so the videos' attachments (e.g., fonts) are always left out, as with --drop-attachments
+        #[clap(verbatim_doc_comment)]
+        #[arg(long, default_value = "mkv")]
+        container: Container,
+        /// when specified, the videos' existing subtitle tracks are left out of the output videos
+        /// so that they only contain the single and dual subtitle tracks added by subbub
+        #[clap(verbatim_doc_comment)]
+        #[arg(long, default_value = "false")]
+        drop_original_subs: bool,
+        /// when specified, only the audio tracks with these language codes are kept in the output videos, separated by commas, e.g., jpn,eng
+        /// a video without any audio tracks in these languages fails, rather than being left without audio
+        #[clap(verbatim_doc_comment)]
+        #[arg(long, value_delimiter = ',')]
+        keep_audio: Vec<String>,
+        /// when specified, attachments (e.g., fonts) are left out of the output videos
+        #[clap(verbatim_doc_comment)]
+        #[arg(long, default_value = "false")]
+        drop_attachments: bool,
+        /// when specified, records each finished video in a .subbub-progress file in the output directory
+        /// so that when an interrupted run is re-run, the finished videos are skipped, and any others are redone
+        /// (including outputs that an interrupted run left partially written, which are otherwise skipped because they exist)
+        /// when there is no progress file yet, existing outputs are skipped as usual
+        /// with --overwrite, the progress file is cleared and every video is redone
+        #[clap(verbatim_doc_comment)]
+        #[arg(long, default_value = "false")]
+        progress: bool,
     },
 }
 
@@ -175,9 +766,39 @@ fn main() {
 
     initialize_logging(cli.log_level);
 
+    let output_options = OutputOptions {
+        overwrite: cli.overwrite,
+        encoding: cli.encoding_out,
+        line_endings: cli.line_endings,
+        format: cli.output_format,
+        play_res: cli.play_res,
+    };
+
+    let extra_tool_args = [
+        ("ffsubsync", &cli.ffsubsync_args),
+        ("mkvmerge", &cli.mkvmerge_args),
+    ]
+    .into_iter()
+    .filter_map(|(tool, args)| Some((tool.to_string(), args.clone()?.0)))
+    .collect();
+    EXTRA_TOOL_ARGS
+        .set(extra_tool_args)
+        .expect("extra tool arguments were already set");
+    SKIP_UNREADABLE_INPUTS.store(cli.skip_errors, Ordering::Relaxed);
+    if cli.no_parallel {
+        // with a single thread, rayon processes the items of each batch in order
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build_global()
+            .expect("the thread pool was already initialized");
+    }
+
+    let start = Instant::now();
     let result = match &cli.command {
-        Commands::Subtitles(subtitles) => subtitles_command(&cli.command, subtitles),
-        Commands::CompoundOperations(operations) => operations_command(&cli.command, operations),
+        Commands::Subtitles(subtitles) => subtitles_command(&output_options, subtitles),
+        Commands::CompoundOperations(operations) => operations_command(&output_options, operations),
+        Commands::Video(video) => video_command(&output_options, video),
+        Commands::Doctor => doctor(),
         #[cfg(debug_assertions)]
         Commands::Debug => debug(),
     };
@@ -191,9 +812,39 @@ fn main() {
         }
     }
 
+    let summary = BatchSummary::collect(start);
+    if summary.total() > 0 {
+        if let OutputFormat::TEXT = cli.output_format {
+            print!("{summary}");
+        }
+    }
+    if let Some(report) = &cli.report {
+        if let Err(e) = summary.write_report(report) {
+            println!("could not write report to {report:#?}: {e:#}");
+        }
+    }
+
     match result {
-        Ok(_) => println!("done!"),
+        // json output must be the only thing printed, so that it can be parsed
+        Ok(_) => {
+            if let OutputFormat::TEXT = cli.output_format {
+                println!("done!")
+            }
+            // inputs may have been skipped with --skip-errors
+            if summary.failed > 0 {
+                exit(2);
+            }
+        }
         Err(e) => {
+            // exit code 2 signals that a batch command partially succeeded
+            if let Some(batch) = e.downcast_ref::<BatchError>() {
+                if batch.succeeded > 0 {
+                    println!("command execution partially failed:\n{batch}");
+                    exit(2);
+                }
+                println!("command execution failed:\n{batch}");
+                exit(1);
+            }
             println!("command execution failed:\nerror: {0}\nsource: {1:#?}\nroot cause: {2}\nbacktrace: {3}", e, e.source(), e.root_cause(), e.backtrace());
             exit(1);
         }
@@ -206,45 +857,571 @@ struct SubtitlesIO {
     output_path: PathBuf,
 }
 
-impl SubtitlesIO {}
+impl SubtitlesIO {
+    // writes the given subtitles to this unit's output path
+    fn write_to_output(&self, subtitles: &SrtSubtitles, options: &OutputOptions) -> Result<()> {
+        options.write_from_input(subtitles, Some(&self.input_path), &self.output_path)
+    }
+}
+
+// settings that apply to every file written and every report printed by a command
+#[derive(Clone)]
+struct OutputOptions {
+    overwrite: bool,
+    encoding: &'static Encoding,
+    line_endings: LineEndings,
+    format: OutputFormat,
+    play_res: Option<PlayRes>,
+}
+
+impl OutputOptions {
+    // writes the given subtitles to the given path, respecting the overwrite policy, output encoding, and line endings
+    fn write(&self, subtitles: &SrtSubtitles, output_path: &Path) -> Result<()> {
+        self.write_from_input(subtitles, None, output_path)
+    }
+
+    // as write, but the line endings of the given input file are used if they're to be preserved
+    fn write_from_input(
+        &self,
+        subtitles: &SrtSubtitles,
+        input_path: Option<&Path>,
+        output_path: &Path,
+    ) -> Result<()> {
+        if !self.should_write(output_path) {
+            return Ok(());
+        }
+        // the input may have been numbered with gaps or duplicates, which some players reject
+        let mut renumbered = subtitles.clone().to_vec();
+        modify::renumber(&mut renumbered);
+        let subtitles = SrtSubtitles::new_from_vec(renumbered);
+        // subtitles are written as srt, unless the output is explicitly an ass file
+        let is_ass = output_path.extension().is_some_and(|ext| {
+            ["ass", "ssa"].contains(&ext.to_string_lossy().to_lowercase().as_str())
+        });
+        let text = if is_ass {
+            to_ass(&subtitles, self.play_res)
+        } else {
+            subtitles.to_string()
+        };
+        self.write_text(&text, input_path, output_path)
+    }
+
+    // writes the given text in the output encoding and line endings, e.g., for subtitles exported to another format
+    // the caller must check should_write first
+    fn write_text(&self, text: &str, input_path: Option<&Path>, output_path: &Path) -> Result<()> {
+        // the output directory may not exist yet, e.g., when writing several files into a new directory
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("could not create output directory {parent:#?}"))?;
+        }
+        let line_endings = match self.line_endings {
+            LineEndings::PRESERVE => input_path
+                .and_then(detect_line_endings)
+                .unwrap_or(LineEndings::LF),
+            line_endings => line_endings,
+        };
+        let bytes = encode_text(text, self.encoding, line_endings)
+            .with_context(|| format!("could not encode text for {output_path:#?}"))?;
+        fs::write(output_path, bytes)?;
+        Ok(())
+    }
+
+    // returns false (and warns) if the given output already exists and may not be overwritten
+    fn should_write(&self, output_path: &Path) -> bool {
+        if let Some(parent) = output_path.parent() {
+            OUTPUT_DIRECTORIES
+                .lock()
+                .unwrap()
+                .push(parent.to_path_buf());
+        }
+        if output_path.exists() && !self.overwrite {
+            log::warn!(
+                "output {output_path:#?} already exists, skipping (use --overwrite to replace it)"
+            );
+            ITEMS_SKIPPED.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+}
+
+// the error returned by batch commands when some of their items failed
+// main uses it to distinguish partial failure from total failure in the exit code
+#[derive(Debug)]
+struct BatchError {
+    succeeded: usize,
+    total: usize,
+    errors: Vec<Error>,
+}
+
+impl std::fmt::Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.succeeded == 0 {
+            writeln!(f, "all {0} items failed:", self.total)?;
+        } else {
+            writeln!(
+                f,
+                "{0} of {1} items succeeded, the following failed:",
+                self.succeeded, self.total
+            )?;
+        }
+        for error in &self.errors {
+            writeln!(f, "{error:#}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+// collects the results of a batch command's items, reporting how many succeeded
+// returns a BatchError if any of them failed
+fn batch_result(results: Vec<Result<()>>) -> Result<()> {
+    let total = results.len();
+    let errors = results
+        .into_iter()
+        .filter_map(|r| r.err())
+        .collect::<Vec<_>>();
+    let succeeded = total - errors.len();
+    ITEMS_SUCCEEDED.fetch_add(succeeded, Ordering::Relaxed);
+    ITEMS_FAILED.fetch_add(errors.len(), Ordering::Relaxed);
+    if errors.is_empty() {
+        log::info!("{succeeded} of {total} items succeeded");
+        return Ok(());
+    }
+    Err(BatchError {
+        succeeded,
+        total,
+        errors,
+    }
+    .into())
+}
+
+// counts of the items handled by batch commands, collected into a BatchSummary once the command finishes
+// skipped items (whose output already exists) are counted as succeeded by batch_result, so they're subtracted when summarizing
+static ITEMS_SUCCEEDED: AtomicUsize = AtomicUsize::new(0);
+static ITEMS_FAILED: AtomicUsize = AtomicUsize::new(0);
+static ITEMS_SKIPPED: AtomicUsize = AtomicUsize::new(0);
+static OUTPUT_DIRECTORIES: Mutex<Vec<PathBuf>> = Mutex::new(vec![]);
+// set by --skip-errors
+static SKIP_UNREADABLE_INPUTS: AtomicBool = AtomicBool::new(false);
+
+// a summary of the items handled by a batch command, printed at the end of the run and optionally written as json
+#[derive(Debug, Serialize)]
+struct BatchSummary {
+    processed: usize,
+    skipped: usize,
+    failed: usize,
+    elapsed_seconds: f64,
+    output_directories: Vec<PathBuf>,
+}
+
+impl BatchSummary {
+    fn collect(start: Instant) -> BatchSummary {
+        let skipped = ITEMS_SKIPPED.load(Ordering::Relaxed);
+        let output_directories = OUTPUT_DIRECTORIES
+            .lock()
+            .unwrap()
+            .iter()
+            .sorted()
+            .dedup()
+            .cloned()
+            .collect();
+        BatchSummary {
+            processed: ITEMS_SUCCEEDED
+                .load(Ordering::Relaxed)
+                .saturating_sub(skipped),
+            skipped,
+            failed: ITEMS_FAILED.load(Ordering::Relaxed),
+            elapsed_seconds: start.elapsed().as_secs_f64(),
+            output_directories,
+        }
+    }
+
+    fn total(&self) -> usize {
+        self.processed + self.skipped + self.failed
+    }
+
+    fn write_report(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for BatchSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{0} processed, {1} skipped, {2} failed in {3:.1}s",
+            self.processed, self.skipped, self.failed, self.elapsed_seconds
+        )?;
+        for directory in &self.output_directories {
+            writeln!(f, "output: {directory:#?}")?;
+        }
+        Ok(())
+    }
+}
+
+// the extra arguments given for an external tool, split like a shell would
+#[derive(Clone, Debug)]
+struct ToolArgs(Vec<String>);
+
+fn parse_tool_args(args: &str) -> Result<ToolArgs> {
+    shlex::split(args).map(ToolArgs).with_context(|| {
+        format!("could not split arguments {args:?}, check that their quotes are balanced")
+    })
+}
+
+// checks that the name template only uses known placeholders
+fn parse_name_template(template: &str) -> Result<String> {
+    render_name_template(
+        template,
+        &NameFields {
+            stem: "",
+            lang: None,
+            index: 0,
+            suffix: "",
+            ext: "",
+        },
+    )?;
+    Ok(template.to_string())
+}
+
+fn parse_encoding(label: &str) -> Result<&'static Encoding> {
+    Encoding::for_label(label.as_bytes()).with_context(|| format!("unknown encoding {label:?}"))
+}
 
-fn subtitles_command(_: &Commands, subcommand: &Subtitles) -> Result<()> {
-    let merged_io = merge_io(&subcommand.input, subcommand.track, &subcommand.output)?;
+fn subtitles_command(options: &OutputOptions, subcommand: &Subtitles) -> Result<()> {
     log::debug!("executing command {subcommand:#?}");
+
+    // commands that only report information don't need an output path, so it's only resolved when used
+    let output = || {
+        if subcommand.in_place {
+            return Err(anyhow!(
+                "this command does not support --in-place, an output path (-o/--output) is required"
+            ));
+        }
+        subcommand
+            .output
+            .as_deref()
+            .context("this command requires an output path (-o/--output) or --in-place")
+    };
+    let merged_io = || {
+        if subcommand.in_place {
+            in_place_io(&subcommand.input)
+        } else {
+            merge_io(
+                &subcommand.input,
+                subcommand.track.as_ref(),
+                output()?,
+                subcommand.name_template.as_deref(),
+            )
+        }
+    };
+    // the inputs of commands that handle each input independently are only read as they're processed, to limit memory use
+    let pending_io = || {
+        if subcommand.in_place {
+            in_place_pending_io(&subcommand.input)
+        } else {
+            pending_io(
+                &subcommand.input,
+                subcommand.track.as_ref(),
+                output()?,
+                subcommand.name_template.as_deref(),
+            )
+        }
+    };
+    // modifying files in place always replaces them, so --overwrite is implied
+    let options = &OutputOptions {
+        overwrite: options.overwrite || subcommand.in_place,
+        ..options.clone()
+    };
     match &subcommand.command {
-        SubtitlesCommand::ConvertSubtitles => convert_subtitles(&merged_io)?,
-        SubtitlesCommand::StripHtml => strip_html_from_dir(&merged_io)?,
-        SubtitlesCommand::ShiftTiming { seconds, direction } => {
-            shift_seconds(&merged_io, *seconds, *direction)?
+        SubtitlesCommand::ConvertSubtitles => {
+            convert_subtitles(&pending_io()?, subcommand.track.as_ref(), options)?
+        }
+        SubtitlesCommand::Extract => {
+            extract_subs(&pending_io()?, subcommand.track.as_ref(), options)?
+        }
+        SubtitlesCommand::Repair => repair_subs(&subcommand.input, output()?, options)?,
+        SubtitlesCommand::Transcript {
+            timestamps,
+            join_sentences,
+        } => {
+            let transcript_options = TranscriptOptions {
+                timestamps: *timestamps,
+                join_sentences: *join_sentences,
+            };
+            transcript_subs(&merged_io()?, &transcript_options, options)?
+        }
+        SubtitlesCommand::FixMojibake => fix_mojibake_subs(&merged_io()?, options)?,
+        SubtitlesCommand::StripHtml {
+            keep_italics,
+            keep_bold,
+        } => {
+            let kept_tags = [(*keep_italics, "i"), (*keep_bold, "b")]
+                .into_iter()
+                .filter_map(|(keep, tag)| keep.then_some(tag))
+                .collect::<Vec<_>>();
+            strip_html_from_dir(
+                &pending_io()?,
+                subcommand.track.as_ref(),
+                &kept_tags,
+                options,
+            )?
+        }
+        SubtitlesCommand::Clean => clean_dir(&merged_io()?, options)?,
+        SubtitlesCommand::FillGaps { max_extend } => {
+            fill_gaps_subs(&merged_io()?, *max_extend, options)?
+        }
+        SubtitlesCommand::Dialogue { style } => dialogue_subs(&merged_io()?, *style, options)?,
+        SubtitlesCommand::EnforceCps {
+            max_cps,
+            min_duration,
+        } => enforce_cps_subs(&merged_io()?, *max_cps, *min_duration, options)?,
+        SubtitlesCommand::FixOverlaps { report_only } => {
+            if *report_only {
+                report_overlaps(&subcommand.input, subcommand.track.as_ref())?
+            } else {
+                fix_overlaps_subs(&merged_io()?, options)?
+            }
+        }
+        SubtitlesCommand::Normalize {
+            full_width,
+            smart_quotes,
+            whitespace,
+        } => {
+            let normalize_options = NormalizeOptions {
+                full_width: *full_width,
+                smart_quotes: *smart_quotes,
+                whitespace: *whitespace,
+            };
+            normalize_subs(&merged_io()?, &normalize_options, options)?
+        }
+        SubtitlesCommand::OcrFix {
+            rules,
+            no_default_rules,
+        } => {
+            let mut ocr_rules = if *no_default_rules {
+                vec![]
+            } else {
+                modify::default_ocr_rules()
+            };
+            if let Some(rules) = rules {
+                let file = File::open(rules)
+                    .with_context(|| format!("could not open rules file {rules:#?}"))?;
+                let user_rules: Vec<OcrRule> = serde_json::from_reader(file)
+                    .with_context(|| format!("could not parse rules file {rules:#?}"))?;
+                // user rules take precedence over the defaults
+                ocr_rules.splice(0..0, user_rules);
+            }
+            ocr_fix_subs(&merged_io()?, &ocr_rules, options)?
+        }
+        SubtitlesCommand::StripCredits {
+            patterns,
+            no_default_patterns,
+        } => {
+            let mut credit_patterns = if *no_default_patterns {
+                vec![]
+            } else {
+                modify::default_credit_patterns()
+            };
+            if let Some(patterns) = patterns {
+                let contents = fs::read_to_string(patterns)
+                    .with_context(|| format!("could not read patterns file {patterns:#?}"))?;
+                credit_patterns.extend(
+                    contents
+                        .lines()
+                        .map(|line| line.trim())
+                        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                        .map(String::from),
+                );
+            }
+            let credit_patterns = credit_patterns
+                .iter()
+                .map(|pattern| {
+                    RegexBuilder::new(pattern)
+                        .case_insensitive(true)
+                        .build()
+                        .with_context(|| format!("invalid credits pattern {pattern:?}"))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            strip_credits_subs(&merged_io()?, &credit_patterns, options)?
+        }
+        SubtitlesCommand::Filter {
+            min_duration,
+            max_duration,
+            extend,
+        } => filter_subs(
+            &merged_io()?,
+            *min_duration,
+            *max_duration,
+            *extend,
+            options,
+        )?,
+        SubtitlesCommand::ShiftTiming {
+            seconds,
+            time,
+            direction,
+        } => {
+            // clap guarantees that exactly one of the two is present
+            let seconds = match time {
+                Some(millis) => *millis as f32 / 1000.0,
+                None => seconds.unwrap(),
+            };
+            shift_seconds(&merged_io()?, seconds, *direction, options)?
+        }
+        SubtitlesCommand::Anchor { cue, at } => anchor_subs(&merged_io()?, *cue, *at, options)?,
+        SubtitlesCommand::ShiftSecondary { seconds, direction } => {
+            shift_secondary(&merged_io()?, *seconds, *direction, options)?
+        }
+        SubtitlesCommand::ZeroBase { keep_lead } => {
+            zero_base_subs(&merged_io()?, *keep_lead, options)?
         }
+        SubtitlesCommand::Scale {
+            factor,
+            anchor,
+            scale_log,
+        } => scale_subs(&merged_io()?, *factor, *anchor, *scale_log, options)?,
+        SubtitlesCommand::Trim {
+            start,
+            end,
+            no_rebase,
+        } => trim_subs(&merged_io()?, *start, *end, !*no_rebase, options)?,
+        SubtitlesCommand::ShiftBatch { manifest } => shift_batch(&merged_io()?, manifest, options)?,
         SubtitlesCommand::Sync {
             reference_subtitles,
             reference_track,
-            sync_tool,
-        } => sync_subs(merged_io, reference_subtitles, *reference_track, *sync_tool)?,
+            sync_tools,
+            quick,
+            sync_log,
+        } => sync_subs(
+            merged_io()?,
+            reference_subtitles,
+            reference_track.as_ref(),
+            sync_tools,
+            *quick,
+            *sync_log,
+            options,
+        )?,
         SubtitlesCommand::Combine {
             secondary_subtitles,
             secondary_track,
-        } => combine_subs(merged_io, secondary_subtitles, *secondary_track)?,
-        SubtitlesCommand::MatchVideos { suffix } => {
-            match_videos(&subcommand.input, &subcommand.output, suffix.as_deref())?
+            languages,
+            alignment,
+            style,
+        } => {
+            if languages.is_empty() {
+                combine_subs(
+                    merged_io()?,
+                    secondary_subtitles,
+                    secondary_track.as_ref(),
+                    alignment,
+                    *style,
+                    options,
+                )?
+            } else {
+                combine_by_language(
+                    &subcommand.input,
+                    output()?,
+                    subcommand.name_template.as_deref(),
+                    languages,
+                    alignment,
+                    *style,
+                    options,
+                )?
+            }
+        }
+        SubtitlesCommand::Diff { other, other_track } => diff_subs(
+            &subcommand.input,
+            subcommand.track.as_ref(),
+            other,
+            other_track.as_ref(),
+            options,
+        )?,
+        SubtitlesCommand::Drift {
+            reference_subtitles,
+            reference_track,
+        } => drift_subs(
+            &subcommand.input,
+            subcommand.track.as_ref(),
+            reference_subtitles,
+            reference_track.as_ref(),
+            options,
+        )?,
+        SubtitlesCommand::Check { video } => {
+            check_subs(&subcommand.input, subcommand.track.as_ref(), video, options)?
+        }
+        SubtitlesCommand::Ls => list_subs(&subcommand.input, options)?,
+        SubtitlesCommand::Grep {
+            pattern,
+            ignore_case,
+            regex,
+        } => grep_subs(
+            &subcommand.input,
+            subcommand.track.as_ref(),
+            pattern,
+            *ignore_case,
+            *regex,
+            options,
+        )?,
+        SubtitlesCommand::MatchVideos { suffix, match_by } => match_videos(
+            &subcommand.input,
+            output()?,
+            suffix.as_deref(),
+            subcommand.name_template.as_deref(),
+            *match_by,
+            options,
+        )?,
+        SubtitlesCommand::Concat { offsets } => {
+            concat_subs(merged_io()?, output()?, offsets, options)?
         }
         SubtitlesCommand::AddSubtitles {
             video_path,
             language_code,
+            replace_language,
+            forced,
+            default_track,
+            track_order,
+            verify,
+            muxer,
+            fonts,
         } => add_subtitles(
             &subcommand.input,
-            subcommand.track,
-            &subcommand.output,
+            subcommand.track.as_ref(),
+            output()?,
             video_path,
-            language_code,
+            language_code.as_deref(),
+            *replace_language,
+            *forced,
+            *default_track,
+            *track_order,
+            *verify,
+            *muxer,
+            fonts.as_deref(),
+            options,
         )?,
     }
     Ok(())
 }
 
-fn merge_io(input: &Path, track: Option<u32>, output: &Path) -> Result<Vec<SubtitlesIO>> {
-    let input_subs = parse_subtitles_input(input, track)?;
+// the key used to order parsed inputs so that they can be matched up with other inputs
+// inputs are ordered by path, but several inputs may share a path (e.g., different subtitles tracks of the same video),
+// so the hash of their content is used as a tiebreaker so that distinct subtitles never compare equal
+fn input_sort_key(path: &Path, subtitles: &SrtSubtitles) -> (PathBuf, u64) {
+    (path.to_path_buf(), hash_subtitles(subtitles))
+}
+
+fn merge_io(
+    input: &Path,
+    track: Option<&TrackSelector>,
+    output: &Path,
+    name_template: Option<&str>,
+) -> Result<Vec<SubtitlesIO>> {
+    let mut input_subs = parse_subtitles_input(input, track)?;
     if input_subs.len() == 1 {
         // if there is exactly one entry, the output path is used as a filename
         let (path, subs) = input_subs.first().unwrap();
@@ -254,88 +1431,262 @@ fn merge_io(input: &Path, track: Option<u32>, output: &Path) -> Result<Vec<Subti
             output_path: output.to_path_buf(),
         }])
     } else {
-        Ok(input_subs
-            .iter()
-            .map(|(input_path, subtitles)| {
-                let output_path = output.join(input_path.file_name().unwrap());
-                SubtitlesIO {
-                    input_path: input_path.to_path_buf(),
-                    subtitles: subtitles.clone(),
-                    output_path,
-                }
+        let namer = OutputNamer::new(input, output, name_template)?;
+        // sort so that {index} in the name template follows the order of the filenames
+        input_subs.sort_by_cached_key(|(path, subs)| input_sort_key(path, subs));
+        input_subs
+            .into_iter()
+            .enumerate()
+            .map(|(index, (input_path, subtitles))| {
+                Ok(SubtitlesIO {
+                    output_path: namer.output_path(&input_path, index)?,
+                    input_path,
+                    subtitles,
+                })
             })
-            .collect())
+            .collect()
     }
 }
 
-fn parse_videos(videos: &Vec<PathBuf>, track: u32) -> Result<Vec<(PathBuf, SrtSubtitles)>> {
-    let mut subs: Vec<(PathBuf, SrtSubtitles)> = vec![];
-    let mut errors: Vec<Error> = vec![];
-    videos.iter().for_each(|v| {
-        let result = ffmpeg::extract_subtitles(v, track);
-        match result {
-            Ok(s) => subs.push((v.to_path_buf(), s)),
-            Err(e) => errors.push(e),
-        }
-    });
-    if errors.is_empty() {
-        Ok(subs)
-    } else {
-        for error in errors {
-            log::error!(
-                "error:\n{0:#?}\nroot cause:\n{1}\nbacktrace:\n{2}",
-                error,
-                error.root_cause(),
-                error.backtrace()
-            );
-        }
-        Err(anyhow!("encountered errors, see logs"))
+// names the output files of inputs written into an output directory
+// by default, each is named after its input, otherwise by the name template
+struct OutputNamer<'a> {
+    output: &'a Path,
+    name_template: Option<&'a str>,
+    languages: HashMap<String, String>,
+}
+
+impl<'a> OutputNamer<'a> {
+    fn new(input: &Path, output: &'a Path, name_template: Option<&'a str>) -> Result<Self> {
+        let languages = if input.is_dir() {
+            read_languages_sidecar(input)?
+        } else {
+            HashMap::new()
+        };
+        Ok(OutputNamer {
+            output,
+            name_template,
+            languages,
+        })
+    }
+
+    // index is the input's position in order of filename, starting from 0
+    fn output_path(&self, input_path: &Path, index: usize) -> Result<PathBuf> {
+        let file_name = input_path.file_name().unwrap();
+        let Some(template) = self.name_template else {
+            return Ok(self.output.join(file_name));
+        };
+        let name = render_name_template(
+            template,
+            &NameFields {
+                stem: &input_path.file_stem().unwrap().to_string_lossy(),
+                lang: self
+                    .languages
+                    .get(file_name.to_string_lossy().as_ref())
+                    .map(String::as_str),
+                index: index + 1,
+                suffix: "",
+                ext: &input_path.extension().unwrap_or_default().to_string_lossy(),
+            },
+        )?;
+        Ok(self.output.join(name))
     }
 }
 
-fn parse_subtitles(subtitles: &Vec<PathBuf>) -> Result<Vec<(PathBuf, SrtSubtitles)>> {
-    let mut subs: Vec<(PathBuf, SrtSubtitles)> = vec![];
-    let mut errors: Vec<Error> = vec![];
-    subtitles.iter().for_each(|sub| {
-        let result = ffmpeg::read_subtitles_file(sub);
-        match result {
-            Ok(s) => subs.push((sub.to_path_buf(), s)),
-            Err(e) => errors.push(e),
-        }
-    });
-    if errors.is_empty() {
-        Ok(subs)
-    } else {
-        for error in errors {
-            log::error!(
-                "error:\n{0:#?}\nroot cause:\n{1}\nbacktrace:\n{2}",
-                error,
-                error.root_cause(),
-                error.backtrace()
-            );
-        }
-        Err(anyhow!("encountered errors, see logs"))
+// an input that hasn't been read yet, along with where its output will be written
+// commands that handle each input independently can read, modify, and write one input at a time with these,
+// rather than holding the subtitles of every input in memory at once
+struct PendingIO {
+    input_path: PathBuf,
+    output_path: PathBuf,
+}
+
+impl PendingIO {
+    fn load(&self, track: Option<&TrackSelector>) -> Result<SubtitlesIO> {
+        Ok(SubtitlesIO {
+            input_path: self.input_path.clone(),
+            subtitles: read_input(&self.input_path, track)?,
+            output_path: self.output_path.clone(),
+        })
     }
 }
 
-fn parse_subtitles_input(input: &Path, track: Option<u32>) -> Result<Vec<(PathBuf, SrtSubtitles)>> {
-    if input.is_file() {
-        log::trace!("input {input:#?} detected as single video file");
-        if is_video_file(input) {
-            let track = track.context(
-                "when supplying a video file as input, subtitle track must be specified",
-            )?;
-            Ok(vec![(
-                input.to_path_buf(),
-                ffmpeg::extract_subtitles(input, track)?,
-            )])
-        } else {
-            log::trace!("input {input:#?} detected as single subtitles file");
-            Ok(vec![((input.to_path_buf(), read_subtitles_file(input)?))])
-        }
-    } else if input.is_dir() {
-        let videos = list_video_files(input);
-        let subtitles = list_subtitles_files(input);
+// the same pairing of inputs and outputs as merge_io, without reading the inputs
+fn pending_io(
+    input: &Path,
+    track: Option<&TrackSelector>,
+    output: &Path,
+    name_template: Option<&str>,
+) -> Result<Vec<PendingIO>> {
+    let mut inputs = list_inputs(input, track)?;
+    if let [single] = inputs.as_slice() {
+        // if there is exactly one entry, the output path is used as a filename
+        return Ok(vec![PendingIO {
+            input_path: single.clone(),
+            output_path: output.to_path_buf(),
+        }]);
+    }
+    let namer = OutputNamer::new(input, output, name_template)?;
+    inputs.sort();
+    inputs
+        .into_iter()
+        .enumerate()
+        .map(|(index, input_path)| {
+            Ok(PendingIO {
+                output_path: namer.output_path(&input_path, index)?,
+                input_path,
+            })
+        })
+        .collect()
+}
+
+// pairs each of the input subtitles with its own path as the output, so that they're modified in place
+fn in_place_io(input: &Path) -> Result<Vec<SubtitlesIO>> {
+    in_place_pending_io(input)?
+        .iter()
+        .map(|pending| pending.load(None))
+        .collect()
+}
+
+// the same pairing as in_place_io, without reading the inputs
+fn in_place_pending_io(input: &Path) -> Result<Vec<PendingIO>> {
+    if input.is_file() && is_archive_file(input) {
+        return Err(anyhow!(
+            "input {input:#?} is an archive, its subtitles cannot be modified in place"
+        ));
+    }
+    let contains_videos = if input.is_dir() {
+        !list_video_files(input).is_empty()
+    } else {
+        is_video_file(input)
+    };
+    if contains_videos {
+        return Err(anyhow!(
+            "input {input:#?} contains videos, subtitles cannot be written back into a video's subtitles track\nplease specify an output path (-o/--output) instead"
+        ));
+    }
+    list_inputs(input, None)?
+        .into_iter()
+        .map(|input_path| {
+            let is_srt = input_path
+                .extension()
+                .is_some_and(|ext| ext.to_string_lossy().to_lowercase() == "srt");
+            if !is_srt {
+                return Err(anyhow!(
+                    "input {input_path:#?} is not an srt file, it cannot be modified in place"
+                ));
+            }
+            Ok(PendingIO {
+                output_path: input_path.clone(),
+                input_path,
+            })
+        })
+        .collect()
+}
+
+fn parse_videos(videos: &[PathBuf], track: &TrackSelector) -> Result<Vec<(PathBuf, SrtSubtitles)>> {
+    let parsed = videos
+        .iter()
+        .map(|v| {
+            let result = ffmpeg::resolve_subtitle_track(v, track)
+                .and_then(|track| ffmpeg::extract_subtitles(v, track));
+            (v.to_path_buf(), result)
+        })
+        .collect();
+    readable_inputs(parsed)
+}
+
+fn parse_subtitles(subtitles: &[PathBuf]) -> Result<Vec<(PathBuf, SrtSubtitles)>> {
+    let parsed = subtitles
+        .iter()
+        .map(|sub| (sub.to_path_buf(), ffmpeg::read_subtitles_file(sub)))
+        .collect();
+    readable_inputs(parsed)
+}
+
+// keeps the inputs that were read successfully
+// if any couldn't be read, fails unless --skip-errors was specified, in which case they're skipped with a warning and counted as failed
+fn readable_inputs(
+    parsed: Vec<(PathBuf, Result<SrtSubtitles>)>,
+) -> Result<Vec<(PathBuf, SrtSubtitles)>> {
+    let mut subs: Vec<(PathBuf, SrtSubtitles)> = vec![];
+    let mut errors: Vec<(PathBuf, Error)> = vec![];
+    for (path, result) in parsed {
+        match result {
+            Ok(s) => subs.push((path, s)),
+            Err(e) => errors.push((path, e)),
+        }
+    }
+    if errors.is_empty() {
+        return Ok(subs);
+    }
+
+    if SKIP_UNREADABLE_INPUTS.load(Ordering::Relaxed) {
+        for (path, error) in &errors {
+            log::warn!("skipping {path:#?}, which could not be read: {error:#}");
+        }
+        log::warn!(
+            "skipped {0} of {1} inputs that could not be read",
+            errors.len(),
+            errors.len() + subs.len()
+        );
+        ITEMS_FAILED.fetch_add(errors.len(), Ordering::Relaxed);
+        return Ok(subs);
+    }
+    for (_, error) in errors {
+        log::error!(
+            "error:\n{0:#?}\nroot cause:\n{1}\nbacktrace:\n{2}",
+            error,
+            error.root_cause(),
+            error.backtrace()
+        );
+    }
+    Err(anyhow!(
+        "encountered errors, see logs\nuse --skip-errors to skip inputs that can't be read"
+    ))
+}
+
+fn parse_subtitles_input(
+    input: &Path,
+    track: Option<&TrackSelector>,
+) -> Result<Vec<(PathBuf, SrtSubtitles)>> {
+    let inputs = list_inputs(input, track)?;
+    if let [single] = inputs.as_slice() {
+        return Ok(vec![(single.clone(), read_input(single, track)?)]);
+    }
+    if inputs.iter().any(|path| is_video_file(path)) {
+        parse_videos(&inputs, track.unwrap())
+    } else {
+        parse_subtitles(&inputs)
+    }
+}
+
+// lists the files that the given input consists of, without reading them
+// archives are extracted and treated as a directory of subtitles files
+// a directory must contain either subtitles files or videos, and videos require a track
+// a url is a single subtitles file, which is downloaded when it's read
+fn list_inputs(input: &Path, track: Option<&TrackSelector>) -> Result<Vec<PathBuf>> {
+    if is_url(input) {
+        log::trace!("input {input:#?} detected as url of a single subtitles file");
+        Ok(vec![input.to_path_buf()])
+    } else if input.is_file() && is_archive_file(input) {
+        log::trace!(
+            "input {input:#?} detected as archive, treating it as a directory of subtitles files"
+        );
+        list_inputs(&extract_archive(input)?, track)
+    } else if input.is_file() {
+        if is_video_file(input) {
+            log::trace!("input {input:#?} detected as single video file");
+            track.context(
+                "when supplying a video file as input, subtitle track must be specified",
+            )?;
+        } else {
+            log::trace!("input {input:#?} detected as single subtitles file");
+        }
+        Ok(vec![input.to_path_buf()])
+    } else if input.is_dir() {
+        let videos = list_video_files(input);
+        let subtitles = list_subtitles_files(input);
         if videos.is_empty() && subtitles.is_empty() {
             Err(anyhow!(
                 "input directory does not contain any video or subtitles files"
@@ -349,138 +1700,1522 @@ fn parse_subtitles_input(input: &Path, track: Option<u32>) -> Result<Vec<(PathBu
                 ));
             }
             log::trace!("input {input:#?} detected as directory of video files");
-            parse_videos(&videos, track.unwrap())
-        } else if !subtitles.is_empty() {
-            if track.is_some() {
+            Ok(videos)
+        } else {
+            if let Some(track) = track {
                 return Err(anyhow!(
-                    "video track {0} has been specified, but command is not operating on videos",
-                    track.unwrap()
+                    "video track {track} has been specified, but command is not operating on videos"
                 ));
             }
             log::trace!("input {input:#?} detected as directory of subtitles files");
-            parse_subtitles(&subtitles)
-        } else {
-            unreachable!();
+            Ok(subtitles)
+        }
+    } else {
+        Err(anyhow!(
+            "input path {input:#?} was not a file or directory, are you sure it exists?"
+        ))
+    }
+}
+
+// reads the subtitles of a single input file, extracting them from the given track if it's a video
+fn read_input(path: &Path, track: Option<&TrackSelector>) -> Result<SrtSubtitles> {
+    let source = SubtitleSource::parse(path, track)?;
+    source.validate()?;
+    match source {
+        // srt files are converted by ffmpeg too, like the files of a directory, so that single files are read the same way
+        SubtitleSource::File(path) => read_subtitles_file(&path),
+        source => source.to_subtitles(),
+    }
+}
+
+fn video_command(options: &OutputOptions, subcommand: &Video) -> Result<()> {
+    log::debug!("executing command {subcommand:#?}");
+
+    let accepts_directory = matches!(subcommand.command, VideoCommand::Verify);
+    let is_directory = accepts_directory && subcommand.input.is_dir();
+    if !(subcommand.input.is_file() || is_directory) {
+        return Err(anyhow!(
+            "input path {0:#?} is not a file, are you sure it exists?",
+            subcommand.input
+        ));
+    }
+    match &subcommand.command {
+        VideoCommand::Chapters => list_chapters(&subcommand.input, options)?,
+        VideoCommand::Verify => verify_videos(&subcommand.input, options)?,
+        VideoCommand::SetDefault {
+            subtitles_track,
+            clear: _,
+            output,
+        } => set_default_track(
+            &subcommand.input,
+            *subtitles_track,
+            output.as_deref(),
+            options,
+        )?,
+    }
+    Ok(())
+}
+
+// the result of checking one subtitles track of a video
+#[derive(Serialize)]
+struct TrackCheck {
+    video: PathBuf,
+    track: u32,
+    codec: Option<String>,
+    // the number of subtitles in the track, if it could be read
+    subtitles: Option<usize>,
+    // why the track is considered corrupt, if it is
+    problem: Option<String>,
+}
+
+fn verify_videos(input: &Path, options: &OutputOptions) -> Result<()> {
+    let mut videos = if input.is_dir() {
+        list_video_files(input)
+    } else {
+        vec![input.to_path_buf()]
+    };
+    videos.sort();
+
+    let checks = videos
+        .par_iter()
+        .map(|video| -> Result<Vec<TrackCheck>> {
+            let tracks = ffmpeg::number_of_subtitle_streams(video)?;
+            (0..tracks)
+                .map(|track| {
+                    log::debug!("verifying subtitles track {video:#?}:{track}");
+                    let codec = ffmpeg::subtitle_codec(video, track).ok();
+                    let mut check = TrackCheck {
+                        video: video.clone(),
+                        track,
+                        codec: codec.clone(),
+                        subtitles: None,
+                        problem: None,
+                    };
+                    if codec.is_some_and(|c| ffmpeg::BITMAP_SUBTITLE_CODECS.contains(&c.as_str())) {
+                        return Ok(check);
+                    }
+                    match ffmpeg::extract_subtitles(video, track) {
+                        Ok(subtitles) if subtitles.is_empty() => {
+                            check.subtitles = Some(0);
+                            check.problem = Some("the track contains no subtitles".to_string());
+                        }
+                        Ok(subtitles) => check.subtitles = Some(subtitles.len()),
+                        Err(e) => check.problem = Some(format!("{e:#}")),
+                    }
+                    Ok(check)
+                })
+                .collect()
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect_vec();
+
+    match options.format {
+        OutputFormat::TEXT => {
+            for check in &checks {
+                let status = match (&check.problem, check.subtitles) {
+                    (Some(problem), _) => format!("CORRUPT: {problem}"),
+                    (None, Some(count)) => format!("ok, {count} subtitles"),
+                    (None, None) => "skipped, image-based".to_string(),
+                };
+                println!(
+                    "{0:#?}:{1} ({2}) {3}",
+                    check.video,
+                    check.track,
+                    check.codec.as_deref().unwrap_or("unknown codec"),
+                    status
+                );
+            }
+        }
+        OutputFormat::JSON => println!("{0}", serde_json::to_string_pretty(&checks)?),
+    }
+
+    let corrupt = checks.iter().filter(|c| c.problem.is_some()).count();
+    if corrupt > 0 {
+        return Err(anyhow!(
+            "{corrupt} of {0} subtitles tracks could not be read",
+            checks.len()
+        ));
+    }
+    Ok(())
+}
+
+// with no subtitles track, the default flag is cleared from every subtitles track
+fn set_default_track(
+    video_file: &Path,
+    subtitles_track: Option<u32>,
+    output: Option<&Path>,
+    options: &OutputOptions,
+) -> Result<()> {
+    let output_path = output.unwrap_or(video_file);
+    if output_path != video_file && !options.should_write(output_path) {
+        return Ok(());
+    }
+    mkvmerge::set_default_subtitles_track(video_file, subtitles_track, output_path)?;
+    match subtitles_track {
+        Some(track) => log::info!("subtitles track {track} is now the default in {output_path:#?}"),
+        None => log::info!("cleared the default subtitles track of {output_path:#?}"),
+    }
+    Ok(())
+}
+
+fn list_chapters(video_file: &Path, options: &OutputOptions) -> Result<()> {
+    let chapters = ffmpeg::extract_chapters(video_file)?;
+    match options.format {
+        OutputFormat::TEXT => {
+            if chapters.is_empty() {
+                println!("{video_file:#?} has no chapters");
+            }
+            for (index, chapter) in chapters.iter().enumerate() {
+                println!(
+                    "#{0} {1} --> {2} {3}",
+                    index + 1,
+                    millis_to_timestamp(chapter.start_ms),
+                    millis_to_timestamp(chapter.end_ms),
+                    chapter.title.as_deref().unwrap_or("")
+                );
+            }
+        }
+        OutputFormat::JSON => println!("{0}", serde_json::to_string_pretty(&chapters)?),
+    }
+    Ok(())
+}
+
+fn doctor() -> Result<()> {
+    // colors are only used when printing to a terminal
+    let colored = std::io::stdout().is_terminal();
+    let status = |ok: bool, label: &str| match (colored, ok) {
+        (true, true) => format!("\x1b[32m[{label}]\x1b[0m"),
+        (true, false) => format!("\x1b[31m[{label}]\x1b[0m"),
+        (false, _) => format!("[{label}]"),
+    };
+
+    let mut missing_required = vec![];
+    for tool in &doctor::TOOLS {
+        match doctor::check_tool(tool) {
+            Ok(version) => println!("{0} {1}: {2}", status(true, "ok"), tool.name, version),
+            Err(e) if tool.required => {
+                println!("{0} {1}: {2}", status(false, "missing"), tool.name, e);
+                missing_required.push(tool.name);
+            }
+            Err(e) => println!(
+                "{0} {1} (optional): {2}",
+                status(false, "missing"),
+                tool.name,
+                e
+            ),
+        }
+    }
+    let tmp_writable = match doctor::check_tmp_directory() {
+        Ok(message) => {
+            println!("{0} temporary directory: {1}", status(true, "ok"), message);
+            true
+        }
+        Err(e) => {
+            println!(
+                "{0} temporary directory: {1}",
+                status(false, "not writable"),
+                e
+            );
+            false
+        }
+    };
+
+    if !missing_required.is_empty() {
+        return Err(anyhow!(
+            "required tools are missing: {0}",
+            missing_required.join(", ")
+        ));
+    }
+    if !tmp_writable {
+        return Err(anyhow!("the temporary directory is not writable"));
+    }
+    Ok(())
+}
+
+#[cfg(debug_assertions)]
+fn debug() -> Result<()> {
+    Ok(())
+}
+
+fn convert_subtitles(
+    pending_io: &[PendingIO],
+    track: Option<&TrackSelector>,
+    options: &OutputOptions,
+) -> Result<()> {
+    let results: Vec<Result<()>> = pending_io
+        .par_iter()
+        .map(|pending| {
+            // each input is read, converted, and written before moving on, so that only a few are in memory at once
+            let io = pending.load(track)?;
+            // when converting several files, they're named after their inputs, so their extension must be changed to srt
+            // otherwise, e.g., input.ass would be written as output/input.ass
+            let output_path = if pending_io.len() > 1 {
+                io.output_path.with_extension("srt")
+            } else {
+                io.output_path.clone()
+            };
+            log::debug!("converting {0:#?} to {1:#?}", &io.input_path, &output_path);
+            options.write_from_input(&io.subtitles, Some(&io.input_path), &output_path)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn extract_subs(
+    pending_io: &[PendingIO],
+    track: Option<&TrackSelector>,
+    options: &OutputOptions,
+) -> Result<()> {
+    let track =
+        track.context("the subtitles track to extract must be specified with -t/--track")?;
+    if let Some(pending) = pending_io.iter().find(|p| !is_video_file(&p.input_path)) {
+        return Err(anyhow!(
+            "input {0:#?} is not a video, only videos' subtitles tracks can be extracted",
+            pending.input_path
+        ));
+    }
+
+    let results: Vec<Result<()>> = pending_io
+        .par_iter()
+        .map(|pending| {
+            let video = &pending.input_path;
+            if let TrackSelector::Index(index) = track {
+                let streams = ffmpeg::number_of_subtitle_streams(video)?;
+                if *index >= streams {
+                    log::warn!("skipping {video:#?}, which has {streams} subtitles tracks, so it has no track {index}");
+                    ITEMS_SKIPPED.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+            }
+            // when extracting from several videos, the subtitles are named after their videos, so their extension must be changed to srt
+            let output_path = if pending_io.len() > 1 {
+                pending.output_path.with_extension("srt")
+            } else {
+                pending.output_path.clone()
+            };
+            log::debug!("extracting track {track} of {video:#?} to {output_path:#?}");
+            let io = pending.load(Some(track))?;
+            options.write_from_input(&io.subtitles, None, &output_path)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn repair_subs(input: &Path, output: &Path, options: &OutputOptions) -> Result<()> {
+    // the inputs are read directly, since the usual parsing path is what fails on malformed files
+    let inputs = if input.is_dir() {
+        list_subtitles_files(input)
+    } else {
+        vec![input.to_path_buf()]
+    };
+    let srt_inputs = inputs
+        .into_iter()
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.to_string_lossy().to_lowercase() == "srt")
+        })
+        .collect_vec();
+    if srt_inputs.is_empty() {
+        return Err(anyhow!("input {input:#?} does not contain any srt files"));
+    }
+
+    let results: Vec<Result<()>> = srt_inputs
+        .par_iter()
+        .map(|input_path| {
+            let output_path = if input.is_dir() {
+                output.join(input_path.file_name().unwrap())
+            } else {
+                output.to_path_buf()
+            };
+            log::debug!("repairing {input_path:#?} and saving to {output_path:#?}");
+            let repaired = parse_srt_file(input_path)?;
+            options.write_from_input(&repaired, Some(input_path), &output_path)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn strip_html_from_dir(
+    pending_io: &[PendingIO],
+    track: Option<&TrackSelector>,
+    kept_tags: &[&str],
+    options: &OutputOptions,
+) -> Result<()> {
+    let results: Vec<Result<()>> = pending_io
+        .par_iter()
+        .map(|pending| {
+            let mut io = pending.load(track)?;
+            log::debug!(
+                "stripping html from {0:#?} and saving to {1:#?}",
+                &io.input_path,
+                &io.output_path
+            );
+            modify::strip_html_keeping(&mut io.subtitles, kept_tags)?;
+            io.write_to_output(&io.subtitles, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn clean_dir(merged_io: &Vec<SubtitlesIO>, options: &OutputOptions) -> Result<()> {
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            let mut subs = io.subtitles.clone();
+            log::debug!(
+                "cleaning {0:#?} and saving to {1:#?}",
+                &io.input_path,
+                &io.output_path
+            );
+            modify::clean_subtitles(&mut subs)?;
+            io.write_to_output(&subs, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn shift_seconds(
+    merged_io: &Vec<SubtitlesIO>,
+    mut seconds: f32,
+    direction: ShiftDirection,
+    options: &OutputOptions,
+) -> Result<()> {
+    if let ShiftDirection::EARLIER = direction {
+        seconds = -seconds
+    }
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            let subtitles = &io.subtitles;
+            log::debug!(
+                "shifting timing of {0:#?} and saving to {1:#?}",
+                &io.input_path,
+                &io.output_path
+            );
+            let shifted = modify::shift_seconds(subtitles, seconds)?;
+            io.write_to_output(&shifted, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn anchor_subs(
+    merged_io: &Vec<SubtitlesIO>,
+    cue: usize,
+    at_ms: i64,
+    options: &OutputOptions,
+) -> Result<()> {
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            let anchor = io
+                .subtitles
+                .clone()
+                .into_iter()
+                .find(|subtitle| subtitle.num == cue)
+                .with_context(|| {
+                    format!(
+                        "{0:#?} has no subtitle numbered {cue}, it has {1} subtitles",
+                        &io.input_path,
+                        io.subtitles.len()
+                    )
+                })?;
+            let offset_ms = at_ms - timestamp_to_millis(&anchor.start_time);
+            log::debug!(
+                "shifting timing of {0:#?} by {offset_ms}ms so that subtitle {cue} starts at {1} and saving to {2:#?}",
+                &io.input_path,
+                millis_to_timestamp(at_ms),
+                &io.output_path
+            );
+            let shifted = modify::shift_millis(&io.subtitles, offset_ms)?;
+            io.write_to_output(&shifted, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn shift_secondary(
+    merged_io: &Vec<SubtitlesIO>,
+    mut seconds: f32,
+    direction: ShiftDirection,
+    options: &OutputOptions,
+) -> Result<()> {
+    if let ShiftDirection::EARLIER = direction {
+        seconds = -seconds
+    }
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            log::debug!(
+                "shifting timing of the secondary subtitles of {0:#?} and saving to {1:#?}",
+                &io.input_path,
+                &io.output_path
+            );
+            let (secondary, primary) = split(&io.subtitles, Alignment::TOP);
+            if secondary.is_empty() {
+                return Err(anyhow!(
+                    "{0:#?} has no secondary subtitles, i.e., none tagged with {1}",
+                    &io.input_path,
+                    Alignment::TOP.tag()
+                ));
+            }
+            let shifted = modify::shift_seconds(&secondary, seconds)?;
+            let merged = merge(&primary, &shifted, MergeStyle::TOP_BOTTOM)?;
+            io.write_to_output(&merged, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn zero_base_subs(
+    merged_io: &Vec<SubtitlesIO>,
+    keep_lead_ms: i64,
+    options: &OutputOptions,
+) -> Result<()> {
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            log::debug!(
+                "zero-basing timing of {0:#?} and saving to {1:#?}",
+                &io.input_path,
+                &io.output_path
+            );
+            let shifted = modify::zero_base(&io.subtitles, keep_lead_ms)?;
+            io.write_to_output(&shifted, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+// the details of a scale, written next to its output by scale --scale-log
+#[derive(Serialize)]
+struct ScaleLog<'a> {
+    input: &'a Path,
+    factor: f64,
+    anchor: ScaleAnchor,
+}
+
+fn scale_subs(
+    merged_io: &Vec<SubtitlesIO>,
+    factor: f64,
+    anchor: ScaleAnchor,
+    scale_log: bool,
+    options: &OutputOptions,
+) -> Result<()> {
+    log::info!("scaling timing by {factor}, anchored at {anchor:?}; srt files can't record this, use --scale-log to keep a record of it");
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            log::debug!(
+                "scaling timing of {0:#?} by {factor} and saving to {1:#?}",
+                &io.input_path,
+                &io.output_path
+            );
+            let scaled = modify::scale(&io.subtitles, factor, anchor)?;
+            io.write_to_output(&scaled, options)?;
+            if scale_log {
+                write_sidecar(
+                    &io.output_path,
+                    "scale",
+                    &ScaleLog {
+                        input: &io.input_path,
+                        factor,
+                        anchor,
+                    },
+                )?;
+            }
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn trim_subs(
+    merged_io: &Vec<SubtitlesIO>,
+    start_ms: i64,
+    end_ms: i64,
+    rebase: bool,
+    options: &OutputOptions,
+) -> Result<()> {
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            log::debug!(
+                "trimming {0:#?} to {1}ms-{2}ms and saving to {3:#?}",
+                &io.input_path,
+                start_ms,
+                end_ms,
+                &io.output_path
+            );
+            let trimmed = modify::trim(&io.subtitles, start_ms, end_ms, rebase)?;
+            io.write_to_output(&trimmed, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn transcript_subs(
+    merged_io: &Vec<SubtitlesIO>,
+    transcript_options: &TranscriptOptions,
+    options: &OutputOptions,
+) -> Result<()> {
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            // when converting several files, they're named after their inputs, so their extension must be changed to txt
+            let output_path = if merged_io.len() > 1 {
+                io.output_path.with_extension("txt")
+            } else {
+                io.output_path.clone()
+            };
+            log::debug!(
+                "converting {0:#?} to a transcript at {1:#?}",
+                &io.input_path,
+                &output_path
+            );
+            if !options.should_write(&output_path) {
+                return Ok(());
+            }
+            let transcript = to_transcript(&io.subtitles, transcript_options)?;
+            options.write_text(&transcript, Some(&io.input_path), &output_path)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn fix_mojibake_subs(merged_io: &Vec<SubtitlesIO>, options: &OutputOptions) -> Result<()> {
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            let mut subs = io.subtitles.clone();
+            log::debug!(
+                "fixing mojibake in {0:#?} and saving to {1:#?}",
+                &io.input_path,
+                &io.output_path
+            );
+            let fixed = modify::fix_mojibake(&mut subs)?;
+            log::info!(
+                "{0:#?}: fixed mojibake in {1} subtitles",
+                &io.input_path,
+                fixed
+            );
+            io.write_to_output(&subs, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn normalize_subs(
+    merged_io: &Vec<SubtitlesIO>,
+    normalize_options: &NormalizeOptions,
+    options: &OutputOptions,
+) -> Result<()> {
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            let mut subs = io.subtitles.clone();
+            log::debug!(
+                "normalizing {0:#?} and saving to {1:#?}",
+                &io.input_path,
+                &io.output_path
+            );
+            modify::normalize_text(&mut subs, normalize_options)?;
+            io.write_to_output(&subs, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn ocr_fix_subs(
+    merged_io: &Vec<SubtitlesIO>,
+    rules: &[OcrRule],
+    options: &OutputOptions,
+) -> Result<()> {
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            let mut subs = io.subtitles.clone();
+            log::debug!(
+                "fixing OCR errors in {0:#?} and saving to {1:#?}",
+                &io.input_path,
+                &io.output_path
+            );
+            modify::ocr_fix(&mut subs, rules)?;
+            io.write_to_output(&subs, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn strip_credits_subs(
+    merged_io: &Vec<SubtitlesIO>,
+    patterns: &[Regex],
+    options: &OutputOptions,
+) -> Result<()> {
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            log::debug!(
+                "stripping credits from {0:#?} and saving to {1:#?}",
+                &io.input_path,
+                &io.output_path
+            );
+            let stripped = modify::strip_credits(&io.subtitles, patterns)?;
+            let removed = io.subtitles.len() - stripped.len();
+            if removed > 0 {
+                log::info!("removed {removed} credits from {0:#?}", &io.input_path);
+            }
+            io.write_to_output(&stripped, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+#[derive(Deserialize, Debug)]
+struct ShiftManifestRow {
+    filename: String,
+    seconds: f32,
+}
+
+fn shift_batch(merged_io: &[SubtitlesIO], manifest: &Path, options: &OutputOptions) -> Result<()> {
+    let mut reader = csv::Reader::from_path(manifest)
+        .with_context(|| format!("could not open manifest {manifest:#?}"))?;
+    let rows: Vec<ShiftManifestRow> = reader
+        .deserialize()
+        .collect::<std::result::Result<_, _>>()
+        .with_context(|| format!("could not parse manifest {manifest:#?}"))?;
+
+    let find_io = |filename: &str| {
+        merged_io.iter().find(|io| {
+            io.input_path
+                .file_name()
+                .is_some_and(|name| name == filename)
+        })
+    };
+    let missing = rows
+        .iter()
+        .filter(|row| find_io(&row.filename).is_none())
+        .map(|row| row.filename.as_str())
+        .collect_vec();
+    if !missing.is_empty() {
+        return Err(anyhow!(
+            "the following files are listed in the manifest but are not in the input:\n    {0}",
+            missing.join("\n    ")
+        ));
+    }
+    for io in merged_io {
+        let listed = rows.iter().any(|row| {
+            io.input_path
+                .file_name()
+                .is_some_and(|name| name == row.filename.as_str())
+        });
+        if !listed {
+            log::warn!(
+                "{0:#?} is not listed in the manifest, skipping",
+                &io.input_path
+            );
+        }
+    }
+
+    let results: Vec<Result<()>> = rows
+        .par_iter()
+        .map(|row| {
+            let io = find_io(&row.filename).unwrap();
+            log::debug!(
+                "shifting timing of {0:#?} by {1}s and saving to {2:#?}",
+                &io.input_path,
+                row.seconds,
+                &io.output_path
+            );
+            let shifted = modify::shift_seconds(&io.subtitles, row.seconds)?;
+            io.write_to_output(&shifted, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn filter_subs(
+    merged_io: &Vec<SubtitlesIO>,
+    min_duration: Option<i64>,
+    max_duration: Option<i64>,
+    extend: bool,
+    options: &OutputOptions,
+) -> Result<()> {
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            log::debug!(
+                "filtering {0:#?} and saving to {1:#?}",
+                &io.input_path,
+                &io.output_path
+            );
+            let mut filtered = io.subtitles.clone();
+            if let Some(max_duration) = max_duration {
+                filtered = modify::clamp_max_duration(&filtered, max_duration)?;
+            }
+            if let Some(min_duration) = min_duration {
+                filtered = if extend {
+                    modify::extend_min_duration(&filtered, min_duration)?
+                } else {
+                    modify::filter_min_duration(&filtered, min_duration)?
+                };
+            }
+            io.write_to_output(&filtered, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn fill_gaps_subs(
+    merged_io: &Vec<SubtitlesIO>,
+    max_extend: i64,
+    options: &OutputOptions,
+) -> Result<()> {
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            log::debug!(
+                "filling gaps in {0:#?} and saving to {1:#?}",
+                &io.input_path,
+                &io.output_path
+            );
+            let filled = modify::fill_gaps(&io.subtitles, max_extend)?;
+            io.write_to_output(&filled, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn dialogue_subs(
+    merged_io: &Vec<SubtitlesIO>,
+    style: DialogueStyle,
+    options: &OutputOptions,
+) -> Result<()> {
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            log::debug!(
+                "formatting dialogue in {0:#?} as {style:?} and saving to {1:#?}",
+                &io.input_path,
+                &io.output_path
+            );
+            let formatted = match style {
+                DialogueStyle::DASHES => modify::join_dialogue(&io.subtitles)?,
+                DialogueStyle::SEPARATE => modify::split_dialogue(&io.subtitles)?,
+            };
+            io.write_to_output(&formatted, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn enforce_cps_subs(
+    merged_io: &Vec<SubtitlesIO>,
+    max_cps: f64,
+    min_duration_ms: i64,
+    options: &OutputOptions,
+) -> Result<()> {
+    if max_cps <= 0.0 {
+        return Err(anyhow!(
+            "the maximum characters per second must be positive, got {max_cps}"
+        ));
+    }
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            log::debug!(
+                "enforcing {0} characters per second in {1:#?} and saving to {2:#?}",
+                max_cps,
+                &io.input_path,
+                &io.output_path
+            );
+            let (enforced, report) =
+                modify::enforce_cps(&io.subtitles, max_cps, min_duration_ms)?;
+            log::info!(
+                "{0:#?}: extended {1} subtitles, {2} are still too fast because the next subtitle starts too soon",
+                &io.input_path,
+                report.adjusted,
+                report.unfixable
+            );
+            io.write_to_output(&enforced, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn report_overlaps(input: &Path, track: Option<&TrackSelector>) -> Result<()> {
+    let mut inputs = parse_subtitles_input(input, track)?;
+    inputs.sort_by_cached_key(|(path, subs)| input_sort_key(path, subs));
+    for (path, subtitles) in inputs {
+        let overlaps = find_overlaps(&subtitles);
+        println!("{path:#?}: {0} overlapping subtitles", overlaps.len());
+        for overlap in overlaps {
+            println!(
+                "  #{0} {1} --> {2} overlaps #{3} {4} --> {5} by {6}ms",
+                overlap.earlier.num,
+                overlap.earlier.start_time,
+                overlap.earlier.end_time,
+                overlap.later.num,
+                overlap.later.start_time,
+                overlap.later.end_time,
+                overlap.overlap_ms()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn fix_overlaps_subs(merged_io: &Vec<SubtitlesIO>, options: &OutputOptions) -> Result<()> {
+    let results: Vec<Result<()>> = merged_io
+        .par_iter()
+        .map(|io| {
+            log::debug!(
+                "fixing overlaps in {0:#?} and saving to {1:#?}",
+                &io.input_path,
+                &io.output_path
+            );
+            let overlaps = find_overlaps(&io.subtitles);
+            log::info!(
+                "{0:#?} has {1} overlapping subtitles",
+                &io.input_path,
+                overlaps.len()
+            );
+            let resolved = modify::resolve_overlaps(&io.subtitles)?;
+            io.write_to_output(&resolved, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+    Ok(())
+}
+
+fn combine_subs(
+    mut merged_io: Vec<SubtitlesIO>,
+    secondary_subtitles: &[PathBuf],
+    secondary_track: Option<&TrackSelector>,
+    alignments: &[Alignment],
+    style: MergeStyle,
+    options: &OutputOptions,
+) -> Result<()> {
+    let alignments = secondary_alignments(secondary_subtitles.len(), alignments, style)?;
+
+    // sort to make sure we match the correct pairs
+    // inputs are matched by position, so every set of subtitles must contain the same number of files
+    // the subtitles within each matched file may have any number of cues, since they're merged by start time
+    merged_io.sort_by_cached_key(|io| input_sort_key(&io.input_path, &io.subtitles));
+    let mut secondary_inputs = vec![];
+    for secondary in secondary_subtitles {
+        let mut secondary_input = parse_subtitles_input(secondary, secondary_track)?;
+        if secondary_input.len() != merged_io.len() {
+            return Err(unmatched_inputs_error(
+                &merged_io
+                    .iter()
+                    .map(|io| io.input_path.as_path())
+                    .collect_vec(),
+                &secondary_input
+                    .iter()
+                    .map(|(path, _)| path.as_path())
+                    .collect_vec(),
+                "secondary",
+                secondary,
+            ));
+        }
+        secondary_input.sort_by_cached_key(|(path, subs)| input_sort_key(path, subs));
+        secondary_inputs.push(secondary_input.into_iter());
+    }
+
+    // group each primary input with its matching input from every set of secondary subtitles
+    let grouped = merged_io
+        .into_iter()
+        .map(|io| {
+            let secondaries = secondary_inputs
+                .iter_mut()
+                .map(|input| input.next().unwrap())
+                .collect_vec();
+            (io, secondaries)
+        })
+        .collect_vec();
+
+    let results: Vec<Result<()>> = grouped
+        .into_par_iter()
+        .map(|(io, secondaries)| {
+            log::debug!(
+                "combining {0:#?} with {1:#?} and saving to {2:#?}",
+                &io.input_path,
+                secondaries.iter().map(|(path, _)| path).collect_vec(),
+                &io.output_path
+            );
+            let mut tracks = vec![(&io.subtitles, Alignment::BOTTOM)];
+            for ((_, subtitles), alignment) in zip(&secondaries, &alignments) {
+                tracks.push((subtitles, *alignment));
+            }
+            let merged_subs = merge_many(tracks, style)?;
+            io.write_to_output(&merged_subs, options)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+
+    Ok(())
+}
+
+// the alignment of each set of secondary subtitles, from those given, or the defaults for the first two sets
+fn secondary_alignments(
+    secondary_count: usize,
+    alignments: &[Alignment],
+    style: MergeStyle,
+) -> Result<Vec<Alignment>> {
+    const DEFAULT_ALIGNMENTS: [Alignment; 2] = [Alignment::TOP, Alignment::MIDDLE];
+    if alignments.len() > secondary_count {
+        return Err(anyhow!(
+            "{0} alignments were given for {secondary_count} secondary subtitles",
+            alignments.len()
+        ));
+    }
+    (0..secondary_count)
+        .map(|index| {
+            alignments
+                .get(index)
+                .or(DEFAULT_ALIGNMENTS.get(index))
+                .copied()
+                // stacked subtitles aren't positioned, so they don't need an alignment
+                .or(matches!(style, MergeStyle::STACKED).then_some(Alignment::BOTTOM))
+                .context("an alignment must be given (-a/--alignment) for each set of secondary subtitles beyond the second")
+        })
+        .collect()
+}
+
+// combines the subtitles in a single directory that share a name but have different language suffixes
+// e.g., with the languages en,ja, episode01.en.srt is combined with episode01.ja.srt, and written to episode01.en.srt in the output directory
+// every file with one of the languages must have a counterpart in each of the others
+fn combine_by_language(
+    input: &Path,
+    output: &Path,
+    name_template: Option<&str>,
+    languages: &[String],
+    alignments: &[Alignment],
+    style: MergeStyle,
+    options: &OutputOptions,
+) -> Result<()> {
+    if languages.len() < 2 {
+        return Err(anyhow!(
+            "--languages requires at least two language codes, the primary language first, e.g., en,ja"
+        ));
+    }
+    if !input.is_dir() {
+        return Err(anyhow!("--languages requires the input {input:#?} to be a directory containing the subtitles of each language, e.g., episode01.en.srt and episode01.ja.srt"));
+    }
+    let alignments = secondary_alignments(languages.len() - 1, alignments, style)?;
+    let languages = languages.iter().map(|l| l.to_lowercase()).collect_vec();
+
+    // files are grouped by their name without the language suffix, e.g., episode01
+    let mut groups: BTreeMap<String, HashMap<String, PathBuf>> = BTreeMap::new();
+    for path in list_subtitles_files(input) {
+        let stem = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let Some((name, language)) = stem.rsplit_once('.') else {
+            continue;
+        };
+        let language = language.to_lowercase();
+        if languages.contains(&language) {
+            groups
+                .entry(name.to_string())
+                .or_default()
+                .insert(language, path);
+        }
+    }
+    if groups.is_empty() {
+        return Err(anyhow!(
+            "no subtitles files in {input:#?} have any of the language suffixes {languages:?}, e.g., episode01.{0}.srt",
+            languages[0]
+        ));
+    }
+
+    let incomplete = groups
+        .iter()
+        .filter_map(|(name, files)| {
+            let missing = languages
+                .iter()
+                .filter(|language| !files.contains_key(*language))
+                .join(", ");
+            (!missing.is_empty()).then(|| format!("    {name}: missing {missing}"))
+        })
+        .collect_vec();
+    if !incomplete.is_empty() {
+        return Err(anyhow!(
+            "some subtitles don't have a counterpart in every language, cannot combine them:\n{0}",
+            incomplete.join("\n")
+        ));
+    }
+
+    let namer = OutputNamer::new(input, output, name_template)?;
+    let grouped = groups
+        .into_values()
+        .enumerate()
+        .map(|(index, mut files)| {
+            let primary = files.remove(&languages[0]).unwrap();
+            let secondaries = languages[1..]
+                .iter()
+                .map(|language| files.remove(language).unwrap())
+                .collect_vec();
+            Ok((namer.output_path(&primary, index)?, primary, secondaries))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let results: Vec<Result<()>> = grouped
+        .into_par_iter()
+        .map(|(output_path, primary, secondaries)| {
+            log::debug!(
+                "combining {primary:#?} with {secondaries:#?} and saving to {output_path:#?}"
+            );
+            let primary_subtitles = read_input(&primary, None)?;
+            let secondary_subtitles = secondaries
+                .iter()
+                .map(|path| read_input(path, None))
+                .collect::<Result<Vec<_>>>()?;
+            let mut tracks = vec![(&primary_subtitles, Alignment::BOTTOM)];
+            for (subtitles, alignment) in zip(&secondary_subtitles, &alignments) {
+                tracks.push((subtitles, *alignment));
+            }
+            let merged_subs = merge_many(tracks, style)?;
+            options.write_from_input(&merged_subs, Some(&primary), &output_path)?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+
+    Ok(())
+}
+
+// describes which input files couldn't be matched when the primary input and another input (e.g., secondary or reference) contain different numbers of files
+// files are reported as unmatched when no file in the other input has the same file stem
+// if every stem has a counterpart (or none do, e.g., when the inputs are named differently), the surplus files are reported instead
+fn unmatched_inputs_error(
+    primary: &[&Path],
+    secondary: &[&Path],
+    secondary_label: &str,
+    secondary_input: &Path,
+) -> Error {
+    let stems = |paths: &[&Path]| {
+        paths
+            .iter()
+            .map(|path| path.file_stem().map(|stem| stem.to_os_string()))
+            .collect::<Vec<_>>()
+    };
+    let (primary_stems, secondary_stems) = (stems(primary), stems(secondary));
+    fn unmatched(
+        paths: &[&Path],
+        own_stems: &[Option<OsString>],
+        other_stems: &[Option<OsString>],
+    ) -> Vec<PathBuf> {
+        zip(paths, own_stems)
+            .filter(|(_, stem)| !other_stems.contains(stem))
+            .map(|(path, _)| path.to_path_buf())
+            .collect_vec()
+    }
+    let mut unmatched_primary = unmatched(primary, &primary_stems, &secondary_stems);
+    let mut unmatched_secondary = unmatched(secondary, &secondary_stems, &primary_stems);
+    let nothing_matched =
+        unmatched_primary.len() == primary.len() && unmatched_secondary.len() == secondary.len();
+    if nothing_matched || (unmatched_primary.is_empty() && unmatched_secondary.is_empty()) {
+        let shortest = primary.len().min(secondary.len());
+        unmatched_primary = primary[shortest..]
+            .iter()
+            .map(|p| p.to_path_buf())
+            .collect();
+        unmatched_secondary = secondary[shortest..]
+            .iter()
+            .map(|p| p.to_path_buf())
+            .collect();
+    }
+    anyhow!(
+        "primary and {secondary_label} ({0:#?}) inputs contain different numbers of files ({1} and {2}), cannot match them:\n    unmatched primary files: {3:?}\n    unmatched {secondary_label} files: {4:?}\nremove or add files so that each primary file has a {secondary_label} counterpart",
+        secondary_input,
+        primary.len(),
+        secondary.len(),
+        unmatched_primary,
+        unmatched_secondary
+    )
+}
+
+// the report for one pair of compared subtitles, as printed by the diff command
+#[derive(Serialize)]
+struct DiffOutput {
+    input_path: PathBuf,
+    other_path: PathBuf,
+    mean_offset_ms: Option<f64>,
+    median_offset_ms: Option<i64>,
+    report: DiffReport,
+}
+
+fn diff_subs(
+    input: &Path,
+    input_track: Option<&TrackSelector>,
+    other: &Path,
+    other_track: Option<&TrackSelector>,
+    options: &OutputOptions,
+) -> Result<()> {
+    let mut input_subs = parse_subtitles_input(input, input_track)?;
+    let mut other_subs = parse_subtitles_input(other, other_track)?;
+    if input_subs.len() != other_subs.len() {
+        return Err(anyhow!("subtitle inputs have different lengths, cannot match them to compare:\n    input: {0}\n    other: {1}", input_subs.len(), other_subs.len()));
+    }
+
+    // sort to make sure we match the correct pairs
+    input_subs.sort_by_cached_key(|(path, subs)| input_sort_key(path, subs));
+    other_subs.sort_by_cached_key(|(path, subs)| input_sort_key(path, subs));
+
+    let reports = zip(input_subs, other_subs)
+        .map(|((input_path, subtitles), (other_path, other_subtitles))| {
+            let report = diff(&subtitles, &other_subtitles);
+            DiffOutput {
+                input_path,
+                other_path,
+                mean_offset_ms: report.mean_offset_ms(),
+                median_offset_ms: report.median_offset_ms(),
+                report,
+            }
+        })
+        .collect_vec();
+
+    match options.format {
+        OutputFormat::TEXT => {
+            for output in reports {
+                println!(
+                    "{0:#?} vs {1:#?}\n{2}",
+                    output.input_path, output.other_path, output.report
+                );
+            }
+        }
+        OutputFormat::JSON => println!("{0}", serde_json::to_string_pretty(&reports)?),
+    }
+
+    Ok(())
+}
+
+// the drift of one pair of subtitles, as printed by the drift command
+#[derive(Serialize)]
+struct DriftOutput {
+    input_path: PathBuf,
+    reference_path: PathBuf,
+    report: Option<DriftReport>,
+}
+
+fn drift_subs(
+    input: &Path,
+    input_track: Option<&TrackSelector>,
+    reference: &Path,
+    reference_track: Option<&TrackSelector>,
+    options: &OutputOptions,
+) -> Result<()> {
+    let mut input_subs = parse_subtitles_input(input, input_track)?;
+    let mut reference_subs = parse_subtitles_input(reference, reference_track)?;
+    if input_subs.len() != reference_subs.len() {
+        return Err(anyhow!("subtitle inputs have different lengths, cannot match them to compare:\n    input: {0}\n    reference: {1}", input_subs.len(), reference_subs.len()));
+    }
+
+    // sort to make sure we match the correct pairs
+    input_subs.sort_by_cached_key(|(path, subs)| input_sort_key(path, subs));
+    reference_subs.sort_by_cached_key(|(path, subs)| input_sort_key(path, subs));
+
+    let reports = zip(input_subs, reference_subs)
+        .map(
+            |((input_path, subtitles), (reference_path, reference_subtitles))| DriftOutput {
+                input_path,
+                reference_path,
+                report: drift(&subtitles, &reference_subtitles),
+            },
+        )
+        .collect_vec();
+
+    match options.format {
+        OutputFormat::TEXT => {
+            for output in reports {
+                println!("{0:#?} vs {1:#?}", output.input_path, output.reference_path);
+                match output.report {
+                    Some(report) => println!("{report}"),
+                    None => println!("too few cues could be matched to measure drift\n"),
+                }
+            }
+        }
+        OutputFormat::JSON => println!("{0}", serde_json::to_string_pretty(&reports)?),
+    }
+
+    Ok(())
+}
+
+// the result of checking one subtitles file against its video
+#[derive(Serialize)]
+struct CheckOutput {
+    input_path: PathBuf,
+    video: PathBuf,
+    passed: bool,
+    #[serde(flatten)]
+    report: CheckReport,
+}
+
+fn check_subs(
+    input: &Path,
+    input_track: Option<&TrackSelector>,
+    video: &Path,
+    options: &OutputOptions,
+) -> Result<()> {
+    let mut input_subs = parse_subtitles_input(input, input_track)?;
+    input_subs.sort_by_cached_key(|(path, subs)| input_sort_key(path, subs));
+    let videos = if video.is_dir() {
+        let mut videos = list_video_files(video);
+        videos.sort();
+        if videos.len() != input_subs.len() {
+            return Err(anyhow!("number of subtitles and number of videos are not the same, cannot match them to check:\n    videos: {0}\n    subtitles: {1}", videos.len(), input_subs.len()));
         }
+        videos
+    } else if video.is_file() {
+        vec![video.to_path_buf(); input_subs.len()]
     } else {
-        Err(anyhow!(
-            "input path {input:#?} was not a file or directory, are you sure it exists?"
-        ))
+        return Err(anyhow!(
+            "video path {video:#?} was not a file or directory, are you sure it exists?"
+        ));
+    };
+
+    let checks = zip(input_subs, videos)
+        .collect_vec()
+        .into_par_iter()
+        .map(|((input_path, subtitles), video)| {
+            let report = check_against_video(&subtitles, ffmpeg::video_duration(&video)?);
+            Ok(CheckOutput {
+                input_path,
+                video,
+                passed: report.passed(),
+                report,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    match options.format {
+        OutputFormat::TEXT => {
+            for check in &checks {
+                println!(
+                    "{0:#?} vs {1:#?}: {2}",
+                    check.input_path, check.video, check.report
+                );
+            }
+        }
+        OutputFormat::JSON => println!("{0}", serde_json::to_string_pretty(&checks)?),
     }
-}
 
-#[cfg(debug_assertions)]
-fn debug() -> Result<()> {
+    let failed = checks.iter().filter(|check| !check.passed).count();
+    if failed > 0 {
+        return Err(anyhow!(
+            "{failed} of {0} subtitles failed the check",
+            checks.len()
+        ));
+    }
     Ok(())
 }
 
-fn convert_subtitles(merged_io: &Vec<SubtitlesIO>) -> Result<()> {
-    let result: Result<()> = merged_io
-        .par_iter()
-        .map(|io| {
-            log::debug!(
-                "converting {0:#?} to {1:#?}",
-                &io.input_path,
-                &io.output_path
-            );
-            std::fs::create_dir_all(&io.output_path.parent().unwrap())?;
-            io.subtitles.write_to_file(&io.output_path, None)?;
-            Ok(())
-        })
-        .collect();
-    result?;
-    Ok(())
+// a subtitles file listed by the ls command
+#[derive(Serialize)]
+struct ListedFile {
+    path: PathBuf,
+    format: Option<SubtitlesFormat>,
+    encoding: Option<&'static str>,
+    subtitles: Option<usize>,
+    // why the file couldn't be read, if it couldn't
+    error: Option<String>,
 }
 
-fn strip_html_from_dir(merged_io: &Vec<SubtitlesIO>) -> Result<()> {
-    let result: Result<()> = merged_io
+fn list_subs(input: &Path, options: &OutputOptions) -> Result<()> {
+    // unlike other commands, files are listed if either their extension or their content marks them as subtitles
+    let mut paths = if input.is_dir() {
+        fs::read_dir(input)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|path| path.is_file())
+            .collect_vec()
+    } else if input.is_file() {
+        vec![input.to_path_buf()]
+    } else {
+        return Err(anyhow!(
+            "input path {input:#?} was not a file or directory, are you sure it exists?"
+        ));
+    };
+    paths.sort();
+
+    let listed = paths
         .par_iter()
-        .map(|io| {
-            let mut subs = io.subtitles.clone();
-            log::debug!(
-                "stripping html from {0:#?} and saving to {1:#?}",
-                &io.input_path,
-                &io.output_path
-            );
-            modify::strip_html(&mut subs)?;
-            std::fs::create_dir_all(&io.output_path.parent().unwrap())?;
-            subs.write_to_file(&io.output_path, None)?;
-            Ok(())
+        .map(|path| {
+            let bytes = fs::read(path)?;
+            let format = sniff_subtitles_format(&bytes);
+            if format.is_none() && !is_subtitle_file(path) {
+                return Ok(None);
+            }
+            let (subtitles, error) = match SubtitleSource::File(path.clone()).to_subtitles() {
+                Ok(subtitles) => (Some(subtitles.len()), None),
+                Err(e) => (None, Some(format!("{e:#}"))),
+            };
+            Ok(Some(ListedFile {
+                path: path.clone(),
+                format,
+                encoding: detect_encoding(&bytes).map(|encoding| encoding.name()),
+                subtitles,
+                error,
+            }))
         })
-        .collect();
-    result?;
-    Ok(())
-}
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect_vec();
 
-fn shift_seconds(
-    merged_io: &Vec<SubtitlesIO>,
-    mut seconds: f32,
-    direction: ShiftDirection,
-) -> Result<()> {
-    match direction {
-        ShiftDirection::EARLIER => seconds = -1.0 * seconds,
-        _ => (),
+    match options.format {
+        OutputFormat::TEXT => {
+            for file in &listed {
+                let format = file
+                    .format
+                    .map_or("unknown format".to_string(), |format| format.to_string());
+                let encoding = file.encoding.unwrap_or("unknown encoding");
+                match (&file.subtitles, &file.error) {
+                    (Some(count), _) => println!(
+                        "{0}: {format}, {encoding}, {count} subtitles",
+                        file.path.to_string_lossy()
+                    ),
+                    (None, error) => println!(
+                        "{0}: {format}, {encoding}, could not be read: {1}",
+                        file.path.to_string_lossy(),
+                        error.as_deref().unwrap_or_default().replace('\n', " / ")
+                    ),
+                }
+            }
+        }
+        OutputFormat::JSON => println!("{0}", serde_json::to_string_pretty(&listed)?),
     }
-    let result: Result<()> = merged_io
-        .par_iter()
-        .map(|io| {
-            let subtitles = &io.subtitles;
-            log::debug!(
-                "shifting timing of {0:#?} and saving to {1:#?}",
-                &io.input_path,
-                &io.output_path
-            );
-            let shifted = modify::shift_seconds(subtitles, seconds)?;
-            std::fs::create_dir_all(&io.output_path.parent().unwrap())?;
-            shifted.write_to_file(&io.output_path, None)?;
-            Ok(())
-        })
-        .collect();
-    result?;
+    log::info!("listed {0} subtitles files", listed.len());
+
     Ok(())
 }
 
-fn combine_subs(
-    mut merged_io: Vec<SubtitlesIO>,
-    secondary_subtitles: &Path,
-    secondary_track: Option<u32>,
-) -> Result<()> {
-    let mut secondary_input = parse_subtitles_input(secondary_subtitles, secondary_track)?;
-    if secondary_input.len() != merged_io.len() {
-        return Err(anyhow!("primary and secondary subtitle inputs have different lengths, cannot match them to combine:\n    primary: {0}\n    secondary: {1}", merged_io.len(), secondary_input.len()));
-    }
+// a subtitle matched by the grep command
+#[derive(Serialize)]
+struct GrepMatch {
+    path: PathBuf,
+    num: usize,
+    start_time: String,
+    end_time: String,
+    text: String,
+}
 
-    // sort to make sure we match the correct pairs
-    merged_io.sort_by_key(|io| io.input_path.clone());
-    secondary_input.sort_by_key(|i| i.0.clone());
+fn grep_subs(
+    input: &Path,
+    track: Option<&TrackSelector>,
+    pattern: &str,
+    ignore_case: bool,
+    regex: bool,
+    options: &OutputOptions,
+) -> Result<()> {
+    let pattern = if regex {
+        pattern.to_string()
+    } else {
+        regex::escape(pattern)
+    };
+    let pattern = RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .with_context(|| format!("invalid pattern {pattern:?}"))?;
 
-    let zipped = zip(merged_io, secondary_input);
-    let result: Result<()> = zipped
-        .par_bridge()
-        .map(|(io, (secondary_input, secondary_subtitles))| {
-            log::debug!(
-                "combining {0:#?} with {1:#?} and saving to {2:#?}",
-                &io.input_path,
-                &secondary_input,
-                &io.output_path
-            );
-            std::fs::create_dir_all(&io.output_path.parent().unwrap())?;
-            let primary_subtitles = &io.subtitles;
-            let output_path = &io.output_path;
-            let merged_subs = merge(&primary_subtitles, &secondary_subtitles)?;
-            merged_subs.write_to_file(output_path, None)?;
-            Ok(())
+    let mut inputs = parse_subtitles_input(input, track)?;
+    inputs.sort_by_cached_key(|(path, subs)| input_sort_key(path, subs));
+    let matches = inputs
+        .iter()
+        .flat_map(|(path, subtitles)| {
+            grep(subtitles, &pattern)
+                .into_iter()
+                .map(|subtitle| GrepMatch {
+                    path: path.clone(),
+                    num: subtitle.num,
+                    start_time: subtitle.start_time.to_string(),
+                    end_time: subtitle.end_time.to_string(),
+                    text: subtitle.text,
+                })
         })
-        .collect();
-    result?;
+        .collect_vec();
+
+    match options.format {
+        OutputFormat::TEXT => {
+            for m in &matches {
+                println!(
+                    "{0}:#{1} {2} --> {3}: {4}",
+                    m.path.to_string_lossy(),
+                    m.num,
+                    m.start_time,
+                    m.end_time,
+                    m.text.replace('\n', " / ")
+                );
+            }
+        }
+        OutputFormat::JSON => println!("{0}", serde_json::to_string_pretty(&matches)?),
+    }
+    log::info!("found {0} matching subtitles", matches.len());
 
     Ok(())
 }
 
-fn match_videos(input: &Path, output: &Path, suffix: Option<&str>) -> Result<()> {
+fn match_videos(
+    input: &Path,
+    output: &Path,
+    suffix: Option<&str>,
+    name_template: Option<&str>,
+    match_by: MatchBy,
+    options: &OutputOptions,
+) -> Result<()> {
     let parent_dir = input.file_stem().unwrap().to_string_lossy();
     let default_extension = format!(".{0}", parent_dir);
-    let suffix_str = suffix.unwrap_or_else(|| &default_extension);
+    let languages = read_languages_sidecar(input)?;
     let mut inputs = list_subtitles_files(input);
     let mut videos = list_video_files(output);
 
@@ -490,22 +3225,112 @@ fn match_videos(input: &Path, output: &Path, suffix: Option<&str>) -> Result<()>
 
     inputs.sort();
     videos.sort();
+    if let MatchBy::CONTENT = match_by {
+        videos = match_videos_by_content(&inputs, &videos)?;
+    }
 
-    let result: Result<()> = zip(inputs, videos)
+    let results: Vec<Result<()>> = zip(inputs, videos)
+        .enumerate()
         .par_bridge()
-        .map(|(subtitle, video)| {
-            let video_name = video.file_stem().unwrap();
-            let output_filename = PathBuf::from(format!(
-                "{0}{1}.srt",
-                output.join(video_name).to_string_lossy(),
-                suffix_str
-            ));
-            std::fs::copy(subtitle, output_filename)?;
+        .map(|(index, (subtitle, video))| {
+            let video_name = video.file_stem().unwrap().to_string_lossy();
+            let language = subtitle
+                .file_name()
+                .and_then(|name| languages.get(name.to_string_lossy().as_ref()));
+            // an explicit suffix takes priority, then the subtitles' language from the sidecar, then the directory name
+            let language_extension = language.map(|language| format!(".{language}"));
+            let suffix_str = suffix
+                .or(language_extension.as_deref())
+                .unwrap_or(&default_extension);
+            let output_filename = output.join(render_name_template(
+                name_template.unwrap_or("{stem}{suffix}.srt"),
+                &NameFields {
+                    stem: &video_name,
+                    lang: language.map(String::as_str),
+                    index: index + 1,
+                    suffix: suffix_str,
+                    ext: &subtitle.extension().unwrap_or_default().to_string_lossy(),
+                },
+            )?);
+            if options.should_write(&output_filename) {
+                std::fs::copy(subtitle, output_filename)?;
+            }
             Ok(())
         })
         .collect();
 
-    result?;
+    batch_result(results)?;
+
+    Ok(())
+}
+
+// reorders the videos so that each is paired with the subtitles file at the same position that fits its audio best
+// every subtitles file is synced against every video, and the pairing with the highest total score is chosen
+fn match_videos_by_content(subtitles: &[PathBuf], videos: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    log::info!(
+        "scoring {0} subtitles files against {1} videos, this may take a while...",
+        subtitles.len(),
+        videos.len()
+    );
+    let scores = subtitles
+        .par_iter()
+        .map(|subtitles_file| {
+            let scores = videos
+                .par_iter()
+                .map(|video| sync_score(video, subtitles_file))
+                .collect::<Result<Vec<_>>>()?;
+            // scores of different subtitles files aren't comparable, so each file's scores are scaled relative to its best fit
+            let best = scores.iter().cloned().fold(f64::MIN, f64::max);
+            Ok(scores
+                .into_iter()
+                .map(|score| if best > 0.0 { score / best } else { score })
+                .collect())
+        })
+        .collect::<Result<Vec<Vec<f64>>>>()?;
+    let assignment = best_assignment(&scores);
+    for (subtitles_file, video) in zip(subtitles, &assignment) {
+        log::info!("matched {subtitles_file:#?} with {0:#?}", videos[*video]);
+    }
+    Ok(assignment
+        .into_iter()
+        .map(|video| videos[video].clone())
+        .collect())
+}
+
+fn concat_subs(
+    mut merged_io: Vec<SubtitlesIO>,
+    output: &Path,
+    offsets: &str,
+    options: &OutputOptions,
+) -> Result<()> {
+    // sort so that the parts are concatenated in order of their filenames
+    merged_io.sort_by_cached_key(|io| input_sort_key(&io.input_path, &io.subtitles));
+
+    let offsets: Vec<Option<i64>> = if offsets == "auto" {
+        vec![None; merged_io.len()]
+    } else {
+        offsets
+            .split(',')
+            .map(|offset| parse_timestamp(offset).map(Some))
+            .collect::<Result<_>>()?
+    };
+    if offsets.len() != merged_io.len() {
+        return Err(anyhow!(
+            "number of offsets and number of parts are not the same:\n    offsets: {0}\n    parts: {1}",
+            offsets.len(),
+            merged_io.len()
+        ));
+    }
+
+    for io in merged_io.iter() {
+        log::debug!("concatenating part {0:#?}", &io.input_path);
+    }
+    let parts = zip(merged_io, offsets)
+        .map(|(io, offset)| (io.subtitles, offset))
+        .collect_vec();
+    let concatenated = concat(parts)?;
+
+    options.write(&concatenated, output)?;
 
     Ok(())
 }
@@ -513,20 +3338,54 @@ fn match_videos(input: &Path, output: &Path, suffix: Option<&str>) -> Result<()>
 fn sync_subs(
     mut merged_io: Vec<SubtitlesIO>,
     reference_subtitles: &Path,
-    reference_track: Option<u32>,
-    sync_tool: SyncTool,
+    reference_track: Option<&TrackSelector>,
+    sync_tools: &[SyncTool],
+    quick_sample_ms: Option<i64>,
+    sync_log: bool,
+    options: &OutputOptions,
 ) -> Result<()> {
-    let mut secondary_input = parse_subtitles_input(reference_subtitles, reference_track)?;
+    // a video reference without a track means syncing to the video's audio, which is represented by having no reference subtitles
+    let mut secondary_input: Vec<(PathBuf, Option<SrtSubtitles>)> = match audio_references(
+        reference_subtitles,
+        reference_track,
+    ) {
+        Some(videos) => {
+            if quick_sample_ms.is_some() {
+                return Err(anyhow!(
+                        "--quick can only be used when syncing to subtitles, but the reference {reference_subtitles:#?} is a video without a track (-y)"
+                    ));
+            }
+            videos.into_iter().map(|video| (video, None)).collect()
+        }
+        None => parse_subtitles_input(reference_subtitles, reference_track)?
+            .into_iter()
+            .map(|(path, subs)| (path, Some(subs)))
+            .collect(),
+    };
     if secondary_input.len() != merged_io.len() {
-        return Err(anyhow!("primary and secondary subtitle inputs have different lengths, cannot match them to combine:\n    primary: {0}\n    secondary: {1}", merged_io.len(), secondary_input.len()));
+        return Err(unmatched_inputs_error(
+            &merged_io
+                .iter()
+                .map(|io| io.input_path.as_path())
+                .collect_vec(),
+            &secondary_input
+                .iter()
+                .map(|(path, _)| path.as_path())
+                .collect_vec(),
+            "reference",
+            reference_subtitles,
+        ));
     }
 
     // sort to make sure we match the correct pairs
-    merged_io.sort_by_key(|io| io.input_path.clone());
-    secondary_input.sort_by_key(|i| i.0.clone());
+    merged_io.sort_by_cached_key(|io| input_sort_key(&io.input_path, &io.subtitles));
+    secondary_input.sort_by_cached_key(|(path, subs)| match subs {
+        Some(subs) => input_sort_key(path, subs),
+        None => (path.clone(), 0),
+    });
 
     let zipped: Vec<_> = zip(merged_io, secondary_input).collect();
-    let result: Result<()> = zipped
+    let results: Vec<Result<()>> = zipped
         .par_iter()
         .map(|(io, (reference_input, reference_subtitles))| {
             log::debug!(
@@ -535,27 +3394,108 @@ fn sync_subs(
                 &reference_input,
                 &io.output_path
             );
-            std::fs::create_dir_all(&io.output_path.parent().unwrap())?;
             let primary_subtitles = &io.subtitles;
-            let output_path = &io.output_path;
-            let synced_subs = sync(&reference_subtitles, &primary_subtitles, &sync_tool)?;
-            synced_subs.write_to_file(output_path, None)?;
+            let (synced_subs, report) = match (reference_subtitles, quick_sample_ms) {
+                (None, _) => sync_to_video(reference_input, primary_subtitles, sync_tools)?,
+                (Some(reference_subtitles), Some(sample_ms)) => quick_sync(
+                    reference_subtitles,
+                    primary_subtitles,
+                    sync_tools,
+                    sample_ms,
+                )?,
+                (Some(reference_subtitles), None) => {
+                    sync(reference_subtitles, primary_subtitles, sync_tools)?
+                }
+            };
+            io.write_to_output(&synced_subs, options)?;
+            if sync_log {
+                write_sync_log(&io.input_path, reference_input, &io.output_path, report)?;
+            }
             Ok(())
         })
         .collect();
-    result?;
+    batch_result(results)?;
+
+    Ok(())
+}
+
+// the videos whose audio the subtitles should be synced to, if the reference is a video or directory of videos without a track
+// otherwise, the reference is read as subtitles
+fn audio_references(reference: &Path, track: Option<&TrackSelector>) -> Option<Vec<PathBuf>> {
+    if track.is_some() {
+        return None;
+    }
+    if reference.is_file() && is_video_file(reference) {
+        return Some(vec![reference.to_path_buf()]);
+    }
+    if reference.is_dir() && list_subtitles_files(reference).is_empty() {
+        let videos = list_video_files(reference);
+        if !videos.is_empty() {
+            return Some(videos);
+        }
+    }
+    None
+}
+
+// the details of a sync, written next to its output by sync --sync-log
+#[derive(Serialize)]
+struct SyncLog<'a> {
+    input: &'a Path,
+    reference: &'a Path,
+    #[serde(flatten)]
+    report: SyncReport,
+}
+
+fn write_sync_log(
+    input: &Path,
+    reference: &Path,
+    output_path: &Path,
+    report: SyncReport,
+) -> Result<()> {
+    let log = SyncLog {
+        input,
+        reference,
+        report,
+    };
+    write_sidecar(output_path, "sync", &log)
+}
 
+// writes the given details as json next to the given output, e.g., episode.srt.sync.json for the kind "sync"
+fn write_sidecar(output_path: &Path, kind: &str, details: &impl Serialize) -> Result<()> {
+    let mut sidecar_path = output_path.as_os_str().to_owned();
+    sidecar_path.push(format!(".{kind}.json"));
+    log::debug!("writing {kind} log to {sidecar_path:#?}");
+    fs::write(&sidecar_path, serde_json::to_string_pretty(details)?)
+        .with_context(|| format!("could not write {kind} log {sidecar_path:#?}"))?;
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn add_subtitles(
     input: &Path,
-    input_track: Option<u32>,
+    input_track: Option<&TrackSelector>,
     output: &Path,
     videos_path: &Path,
-    language_code: &str,
+    language_code: Option<&str>,
+    replace_language: bool,
+    forced: bool,
+    default_track: bool,
+    track_order: TrackPosition,
+    verify: bool,
+    muxer: Muxer,
+    fonts_directory: Option<&Path>,
+    options: &OutputOptions,
 ) -> Result<()> {
+    let fonts = fonts_directory.map(Fonts::read).transpose()?;
+    if fonts.is_some() && !matches!(muxer, Muxer::MKVMERGE) {
+        return Err(anyhow!("--fonts can only be used with --muxer mkvmerge"));
+    }
     let mut subtitles = parse_subtitles_input(input, input_track)?;
+    let languages = if input.is_dir() {
+        read_languages_sidecar(input)?
+    } else {
+        HashMap::new()
+    };
 
     let mut videos = if videos_path.is_dir() {
         list_video_files(videos_path)
@@ -568,72 +3508,256 @@ fn add_subtitles(
     }
 
     videos.sort();
-    subtitles.sort_by_key(|(path, _)| path.clone());
+    subtitles.sort_by_cached_key(|(path, subs)| input_sort_key(path, subs));
+
+    // if the videos were given as a single file, the output should be a single file
+    // if the videos were given as a directory, we'll use the given output as a directory, and name the output videos the same as their input counterpart
+    // this holds even if the directory contains only one video
+    // this is decided (and the output directory created) once up front, rather than by each of the parallel items
+    let videos_kind = if videos_path.is_dir() {
+        VideosKind::DIRECTORY
+    } else {
+        VideosKind::FILE
+    };
+    if matches!(videos_kind, VideosKind::DIRECTORY) {
+        fs::create_dir_all(output)?;
+    } else {
+        fs::create_dir_all(output.parent().context("output path has no parent")?)?;
+    }
 
     let units = zip(subtitles, videos).collect_vec();
-    for ((input_path, subtitles), video_path) in units {
-        // get subtitles path on disk
-        let subtitles_path = if is_video_file(&input_path) {
-            let tmp_filename = format!("add_{0}.srt", hash_subtitles(&subtitles));
-            let tmp_filepath = TMP_DIRECTORY.get().unwrap().join(tmp_filename);
-            // if input path is a video file, we'll need to save the extracted subs and point to the extracted path
-            subtitles.write_to_file(&tmp_filepath, None)?;
-            tmp_filepath
-        } else {
-            // if input path is not a video file, we can assume it's a subtitles file and point to the path
-            input_path
-        };
+    let results: Vec<Result<()>> = units
+        .into_par_iter()
+        .map(|((input_path, subtitles), video_path)| {
+            // the sidecar's language for this input takes priority over the language given on the command line
+            // if neither is given, it's detected from the subtitles themselves
+            let language_code = match input_path
+                .file_name()
+                .and_then(|name| languages.get(name.to_string_lossy().as_ref()))
+                .map(String::as_str)
+                .or(language_code)
+            {
+                Some(code) => code.to_string(),
+                None => {
+                    let detected = lang::detect(&subtitles).with_context(|| format!("could not detect the language of {input_path:#?}, please specify it with -c/--language-code"))?;
+                    log::info!("detected language {detected} for {input_path:#?}, use -c/--language-code to override it");
+                    detected
+                }
+            };
+            let metadata = TrackMetadata {
+                language: Some(language_code.clone()),
+                name: language_code,
+                default: default_track,
+                forced,
+            };
 
-        let output_path = if subtitles.len() == 1 {
-            // if there's only one input, the output should be a single file
-            fs::create_dir_all(output.parent().context("output path has no parent")?)?;
-            output.to_path_buf()
-        } else {
-            // if there are multiple inputs, we'll use the given output as a directory, and name the output videos the same as their input counterpart
-            fs::create_dir_all(output)?;
+            let output_path = add_subtitles_output_path(&video_path, videos_kind, output)?;
+            if !options.should_write(&output_path) {
+                return Ok(());
+            }
+
+            // get subtitles path on disk
+            let subtitles_path = if is_video_file(&input_path) {
+                // the filename is a hash of the subtitles, so parallel items never write to the same file
+                let tmp_filename = format!("add_{0}.srt", hash_subtitles(&subtitles));
+                let tmp_filepath = TMP_DIRECTORY.get().unwrap().join(tmp_filename);
+                // if input path is a video file, we'll need to save the extracted subs and point to the extracted path
+                subtitles.write_to_file(&tmp_filepath, None)?;
+                tmp_filepath
+            } else {
+                // if input path is not a video file, we can assume it's a subtitles file and point to the path
+                if let Some(fonts) = &fonts {
+                    fonts.warn_missing(&input_path)?;
+                }
+                input_path.clone()
+            };
+
+            log::debug!("adding {input_path:#?} to {video_path:#?} and saving to {output_path:#?}");
+            warn_if_subtitles_exceed_video(&subtitles, &video_path);
+            mux::add_subtitles_tracks(
+                muxer,
+                &video_path,
+                &[SubtitlesTrack {
+                    path: &subtitles_path,
+                    metadata,
+                }],
+                fonts.as_ref().map(|fonts| fonts.files.as_slice()).unwrap_or_default(),
+                replace_language,
+                matches!(track_order, TrackPosition::FIRST),
+                verify,
+                &output_path,
+            )?;
+            Ok(())
+        })
+        .collect();
+    batch_result(results)?;
+
+    Ok(())
+}
+
+// whether the videos given to add-subtitles were a single file, or a directory of videos
+#[derive(Debug, Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
+enum VideosKind {
+    FILE,
+    DIRECTORY,
+}
+
+// the path that add-subtitles writes the given video to
+// a single video is written to the output path itself, while the videos of a directory are written into the output directory
+// under their own names, even if the directory contains only one video
+fn add_subtitles_output_path(
+    video_path: &Path,
+    videos_kind: VideosKind,
+    output: &Path,
+) -> Result<PathBuf> {
+    match videos_kind {
+        VideosKind::FILE => Ok(output.to_path_buf()),
+        VideosKind::DIRECTORY => {
             let filename = video_path
                 .file_name()
                 .context("video file has no file name")?;
-            output.join(filename)
-        };
-        mkvmerge::add_subtitles_track(
-            &video_path,
-            &subtitles_path,
-            Some(language_code),
-            language_code,
-            &output_path,
-        )?;
+            Ok(output.join(filename))
+        }
     }
+}
 
-    Ok(())
+// warns if the subtitles continue well past the end of the video, which usually means they belong to a different video
+fn warn_if_subtitles_exceed_video(subtitles: &SrtSubtitles, video_file: &Path) {
+    // how far past the end of the video (as a fraction of its duration) the subtitles may extend
+    const TOLERANCE: f64 = 0.05;
+
+    let duration = match ffmpeg::video_duration(video_file) {
+        Ok(duration) => duration,
+        Err(e) => {
+            log::warn!(
+                "could not determine duration of {video_file:#?}, skipping sanity check: {e}"
+            );
+            return;
+        }
+    };
+    let Some(last_end) = subtitles
+        .into_iter()
+        .map(|s| timestamp_to_millis(&s.end_time))
+        .max()
+    else {
+        return;
+    };
+
+    let duration_millis = duration.as_millis() as f64;
+    if last_end as f64 > duration_millis * (1.0 + TOLERANCE) {
+        log::warn!(
+            "subtitles end at {0:.1}s, but video {video_file:#?} is only {1:.1}s long; are these subtitles for a different video?",
+            last_end as f64 / 1000.0,
+            duration_millis / 1000.0
+        );
+    }
 }
 
-fn operations_command(_: &Commands, operations: &CompoundOperations) -> Result<()> {
+fn operations_command(options: &OutputOptions, operations: &CompoundOperations) -> Result<()> {
     match &operations.command {
         CompoundOperationsCommand::AddDualSubs {
             videos_path,
             subtitles_track,
+            track_manifest,
             subtitles_path,
             output_path,
             language_code,
+            single_track_name,
+            dual_track_name,
+            dual_language_code,
+            dual_mode,
+            dump_reference,
+            track_order,
+            verify,
+            muxer,
+            container,
+            drop_original_subs,
+            keep_audio,
+            drop_attachments,
+            progress,
         } => dual_subs_command(
-            &videos_path,
-            &subtitles_path,
-            *subtitles_track,
-            &language_code,
-            &output_path,
+            videos_path,
+            subtitles_path,
+            &ReferenceTracks {
+                default: subtitles_track.clone(),
+                per_video: match track_manifest {
+                    Some(manifest) => read_track_manifest(manifest)?,
+                    None => HashMap::new(),
+                },
+            },
+            &DualSubsTracks {
+                language_code: language_code.clone(),
+                single_track_name: single_track_name
+                    .clone()
+                    .unwrap_or_else(|| language_code.clone()),
+                dual_track_name: dual_track_name
+                    .clone()
+                    .unwrap_or_else(|| format!("dual-{language_code}")),
+                dual_language_code: dual_language_code.clone(),
+                mode: *dual_mode,
+                order: *track_order,
+                verify: *verify,
+                muxer: *muxer,
+                container: *container,
+                streams: StreamSelection {
+                    drop_subtitles: *drop_original_subs,
+                    drop_attachments: *drop_attachments,
+                    audio_languages: keep_audio.clone(),
+                },
+            },
+            output_path,
+            dump_reference.as_deref(),
+            *progress,
+            options,
         ),
     }?;
 
     Ok(())
 }
 
+// the names, language codes, mode, and order of the tracks added to each video by add-dual-subs
+// along with whether to verify them, the muxer that adds them, the container they're added in, and which of the video's original streams to keep
+struct DualSubsTracks {
+    language_code: String,
+    single_track_name: String,
+    dual_track_name: String,
+    dual_language_code: Option<String>,
+    mode: DualMode,
+    order: TrackOrder,
+    verify: bool,
+    muxer: Muxer,
+    container: Container,
+    streams: StreamSelection,
+}
+
+// the subtitles track used as a timing reference for each video by add-dual-subs
+struct ReferenceTracks {
+    default: Option<TrackSelector>,
+    // keyed by video filename
+    per_video: HashMap<String, TrackSelector>,
+}
+
+impl ReferenceTracks {
+    fn for_video(&self, video_file: &Path) -> Result<&TrackSelector> {
+        video_file
+            .file_name()
+            .and_then(|name| self.per_video.get(name.to_string_lossy().as_ref()))
+            .or(self.default.as_ref())
+            .with_context(|| format!("no reference track was given for {video_file:#?}, it must be listed in --track-manifest, or --subtitles-track must be specified"))
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn dual_subs_command(
     videos_path: &Path,
     subtitles_path: &Path,
-    track: u32,
-    language_code: &str,
+    reference_tracks: &ReferenceTracks,
+    tracks: &DualSubsTracks,
     output: &Path,
+    dump_reference: Option<&Path>,
+    record_progress: bool,
+    options: &OutputOptions,
 ) -> Result<()> {
     if videos_path == output {
         return Err(anyhow!("videos path and output path are the same, this could cause overwriting of the original video files\nplease choose a different output path"));
@@ -653,26 +3777,42 @@ fn dual_subs_command(
     video_files.sort();
     subtitles_files.sort();
 
+    // check that every video has a reference track before doing any work
+    for video_file in &video_files {
+        reference_tracks.for_video(video_file)?;
+    }
+
+    if matches!(tracks.container, Container::MP4) {
+        if matches!(tracks.muxer, Muxer::MKVMERGE) {
+            log::info!("mkvmerge can't write mp4 files, muxing with ffmpeg instead");
+        }
+        log::warn!("mp4 subtitles (mov_text) don't support positioning, so the {{\\an8}} tags that place subtitles at the top of the video won't render; both sets of subtitles will be displayed at the bottom");
+    }
+
+    let progress = if record_progress {
+        Some(ProgressManifest::open(output, options.overwrite)?)
+    } else {
+        None
+    };
+
     let zipped = zip(video_files, subtitles_files).collect::<Vec<_>>();
-    let errors = zipped
+    let results = zipped
         .par_iter()
         .enumerate()
         .map(|tuple: (usize, &(PathBuf, PathBuf))| {
-            dual_subs_command_single(tuple, track, language_code, output)
+            let track = reference_tracks.for_video(&tuple.1 .0)?;
+            dual_subs_command_single(
+                tuple,
+                track,
+                tracks,
+                output,
+                dump_reference,
+                progress.as_ref(),
+                options,
+            )
         })
-        .filter(|r| r.is_err())
-        .map(|r| r.err().unwrap())
         .collect::<Vec<_>>();
-    if !errors.is_empty() {
-        let mut error_vec: Vec<u8> = vec![];
-        for error in errors {
-            writeln!(error_vec, "{error}")?;
-        }
-        return Err(anyhow!(
-            "one or more operations not successful:\n{0}",
-            String::from_utf8(error_vec)?
-        ));
-    }
+    batch_result(results)?;
 
     log::info!("done! finished processing all videos");
 
@@ -681,74 +3821,249 @@ fn dual_subs_command(
 
 fn dual_subs_command_single(
     tuple: (usize, &(PathBuf, PathBuf)),
-    track: u32,
-    language_code: &str,
+    track: &TrackSelector,
+    tracks: &DualSubsTracks,
     output: &Path,
+    dump_reference: Option<&Path>,
+    progress: Option<&ProgressManifest>,
+    options: &OutputOptions,
 ) -> Result<()> {
     let (index, (video_file, subtitles_file)) = tuple;
-    log::info!("started processing video #{index}");
     let video_filename = video_file.file_stem().unwrap().to_string_lossy();
+    let final_video = output.join(format!(
+        "{0}.{1}",
+        video_filename,
+        tracks.container.extension()
+    ));
+    // check before doing any work so that existing results aren't needlessly recomputed
+    if let Some(progress) = progress {
+        if progress.is_completed(video_file) {
+            log::info!("#{index}: {video_file:#?} was finished by a previous run, skipping");
+            ITEMS_SKIPPED.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        // when resuming, the progress file is the record of which outputs are finished, so an unlisted output was interrupted and is redone
+        // without a progress file from a previous run, existing outputs are left to the overwrite policy
+        if progress.resumed && final_video.exists() {
+            log::warn!("#{index}: {final_video:#?} was not finished by a previous run, redoing it");
+            fs::remove_file(&final_video)?;
+        }
+    }
+    if !options.should_write(&final_video) {
+        return Ok(());
+    }
+    log::info!("started processing video #{index}");
 
     // convert video to mkv
     log::info!("#{index}: converting video to mkv...");
-    let mkv_filepath = ffmpeg::convert_to_mkv(video_file)?;
+    let mkv_filepath = ffmpeg::convert_to_mkv(video_file, &tracks.streams)?;
     // extract provided track number
     log::info!("#{index}: extracting reference subs...");
+    let track = ffmpeg::resolve_subtitle_track(video_file, track)?;
     let mut subs_from_video = ffmpeg::extract_subtitles(video_file, track)?;
+    if let Some(dump_dir) = dump_reference {
+        let dump_file = dump_dir.join(format!("{0}.srt", video_filename));
+        log::info!("#{index}: saving reference subs to {dump_file:#?}...");
+        std::fs::create_dir_all(dump_dir)?;
+        subs_from_video.write_to_file(&dump_file, None)?;
+    }
     // convert provided subs to srt and sync
     // surround in a scope block so that we don't accidentally use the raw subs_from_file in later steps
     let mut synced_subs_from_file = {
         log::info!("#{index}: converting subs to srt...");
-        let subs_from_file = ffmpeg::read_subtitles_file(&subtitles_file)?;
+        let subs_from_file = ffmpeg::read_subtitles_file(subtitles_file)?;
         // sync subs
         log::info!("#{index}: syncing subs...");
-        sync(&subs_from_video, &subs_from_file, &SyncTool::FFSUBSYNC)?
+        sync(&subs_from_video, &subs_from_file, &[SyncTool::FFSUBSYNC])?.0
     };
+    warn_if_subtitles_exceed_video(&synced_subs_from_file, video_file);
     log::info!("#{index}: stripping HTML from subs...");
     strip_html(&mut subs_from_video)?;
     strip_html(&mut synced_subs_from_file)?;
-    // combine provided subs with extracted track
-    log::info!("#{index}: merging subs...");
-    let merged_subs = merge(&subs_from_video, &synced_subs_from_file)?;
+    let (single_subs, dual_subs) = match tracks.mode {
+        DualMode::MERGED => {
+            // combine provided subs with extracted track
+            log::info!("#{index}: merging subs...");
+            let merged_subs = merge(
+                &subs_from_video,
+                &synced_subs_from_file,
+                MergeStyle::TOP_BOTTOM,
+            )?;
+            (synced_subs_from_file, merged_subs)
+        }
+        DualMode::SEPARATE => {
+            // the provided subs are displayed at the top, so they don't cover the reference subs at the bottom
+            log::info!("#{index}: aligning subs to the top...");
+            let top_subs = merge_many(
+                vec![(&synced_subs_from_file, Alignment::TOP)],
+                MergeStyle::TOP_BOTTOM,
+            )?;
+            (top_subs, subs_from_video)
+        }
+    };
 
     // add sub tracks to mkv file
 
-    // determine temporary filepaths for subs and videos
-    let intermediate_video = TMP_DIRECTORY
-        .get()
-        .unwrap()
-        .join(format!("{0}-intermediate.mkv", video_filename));
-    let single_sub_filepath = TMP_DIRECTORY
-        .get()
-        .unwrap()
-        .join(format!("{0}-single.srt", video_filename));
-    synced_subs_from_file.write_to_file(&single_sub_filepath, None)?;
-    let dual_sub_filepath = TMP_DIRECTORY
-        .get()
-        .unwrap()
-        .join(format!("{0}-dual.srt", video_filename));
-    merged_subs.write_to_file(&dual_sub_filepath, None)?;
-
-    // add single sub track
-    log::info!("#{index}: adding single subs track...");
-    mkvmerge::add_subtitles_track(
-        &mkv_filepath,
-        &single_sub_filepath,
-        Some(language_code),
-        language_code,
-        &intermediate_video,
-    )?;
-    // add dual sub track
-    log::info!("#{index}: adding dual subs track...");
-    let final_video = output.join(format!("{0}.mkv", video_filename));
+    // determine temporary filepaths for subs
+    let single_sub_filepath = TMP_DIRECTORY.get().unwrap().join(format!(
+        "{0}_{1}-single.srt",
+        video_filename,
+        hash_path(video_file)
+    ));
+    single_subs.write_to_file(&single_sub_filepath, None)?;
+    let dual_sub_filepath = TMP_DIRECTORY.get().unwrap().join(format!(
+        "{0}_{1}-dual.srt",
+        video_filename,
+        hash_path(video_file)
+    ));
+    dual_subs.write_to_file(&dual_sub_filepath, None)?;
+
+    // add both sub tracks, in the requested order
+    log::info!("#{index}: adding single and dual subs tracks...");
+    let single_track = SubtitlesTrack {
+        path: &single_sub_filepath,
+        metadata: TrackMetadata {
+            language: Some(tracks.language_code.clone()),
+            name: tracks.single_track_name.clone(),
+            ..Default::default()
+        },
+    };
+    let dual_track = SubtitlesTrack {
+        path: &dual_sub_filepath,
+        metadata: TrackMetadata {
+            language: tracks.dual_language_code.clone(),
+            name: tracks.dual_track_name.clone(),
+            ..Default::default()
+        },
+    };
+    let new_tracks = match tracks.order {
+        TrackOrder::SINGLE_FIRST => [single_track, dual_track],
+        TrackOrder::DUAL_FIRST => [dual_track, single_track],
+    };
     std::fs::create_dir_all(output)?;
-    mkvmerge::add_subtitles_track(
-        &intermediate_video,
-        &dual_sub_filepath,
-        None,
-        format!("dual-{language_code}").as_str(),
+    // mkvmerge can't write mp4 files
+    let muxer = match tracks.container {
+        Container::MKV => tracks.muxer,
+        Container::MP4 => Muxer::FFMPEG,
+    };
+    mux::add_subtitles_tracks(
+        muxer,
+        &mkv_filepath,
+        &new_tracks,
+        &[],
+        false,
+        false,
+        tracks.verify,
         &final_video,
     )?;
+    if let Some(progress) = progress {
+        progress.complete(video_file)?;
+    }
     log::info!("finished processing video #{index}");
     Ok(())
 }
+
+// the videos that add-dual-subs has finished, recorded in the output directory as they finish
+// so that a run that was interrupted can continue where it left off
+struct ProgressManifest {
+    path: PathBuf,
+    // the filenames of the finished videos
+    completed: Mutex<HashSet<String>>,
+    // whether the progress of a previous run was read, i.e., this run is resuming it
+    resumed: bool,
+}
+
+impl ProgressManifest {
+    const FILENAME: &'static str = ".subbub-progress";
+
+    // reads the progress of a previous run from the output directory, or starts over when overwriting
+    fn open(output: &Path, overwrite: bool) -> Result<Self> {
+        let path = output.join(Self::FILENAME);
+        let mut completed = HashSet::new();
+        let mut resumed = false;
+        if path.is_file() {
+            if overwrite {
+                log::info!("clearing the progress of previous runs in {path:#?}");
+                fs::remove_file(&path)?;
+            } else {
+                let text = fs::read_to_string(&path)
+                    .with_context(|| format!("could not read progress file {path:#?}"))?;
+                completed.extend(
+                    text.lines()
+                        .filter(|line| !line.trim().is_empty())
+                        .map(str::to_string),
+                );
+                resumed = true;
+                log::info!(
+                    "{0} videos were finished by previous runs, according to {path:#?}",
+                    completed.len()
+                );
+            }
+        }
+        Ok(ProgressManifest {
+            path,
+            completed: Mutex::new(completed),
+            resumed,
+        })
+    }
+
+    fn is_completed(&self, video_file: &Path) -> bool {
+        self.completed
+            .lock()
+            .unwrap()
+            .contains(&Self::key(video_file))
+    }
+
+    // records the video as finished, appending it to the progress file right away so that it survives an interruption
+    fn complete(&self, video_file: &Path) -> Result<()> {
+        let key = Self::key(video_file);
+        // hold the lock while writing so that lines from parallel videos aren't interleaved
+        let mut completed = self.completed.lock().unwrap();
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("could not write progress file {0:#?}", self.path))?;
+        writeln!(file, "{key}")?;
+        completed.insert(key);
+        Ok(())
+    }
+
+    fn key(video_file: &Path) -> String {
+        video_file
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_subtitles_writes_a_single_video_to_the_output_file() {
+        let output = add_subtitles_output_path(
+            Path::new("videos/episode01.mkv"),
+            VideosKind::FILE,
+            Path::new("out/episode01.subbed.mkv"),
+        )
+        .unwrap();
+        assert_eq!(output, PathBuf::from("out/episode01.subbed.mkv"));
+    }
+
+    #[test]
+    fn add_subtitles_writes_a_directory_of_one_video_into_the_output_directory() {
+        let output = add_subtitles_output_path(
+            Path::new("videos/episode01.mkv"),
+            VideosKind::DIRECTORY,
+            Path::new("out"),
+        )
+        .unwrap();
+        assert_eq!(output, PathBuf::from("out/episode01.mkv"));
+    }
+}
@@ -0,0 +1,212 @@
+// this file contains functions for converting between srt and ass/ssa subtitles
+// ass specification: http://www.tcax.org/docs/ass-specs.htm
+
+use anyhow::{anyhow, Result};
+use srtlib::{Subtitles, Timestamp};
+use std::str::FromStr;
+
+use crate::core::time::timestamp_to_millis;
+
+// html tags used by srt, and the ass override tags with the same effect
+const TAG_CONVERSIONS: [(&str, &str); 6] = [
+    ("<i>", r"{\i1}"),
+    ("</i>", r"{\i0}"),
+    ("<b>", r"{\b1}"),
+    ("</b>", r"{\b0}"),
+    ("<u>", r"{\u1}"),
+    ("</u>", r"{\u0}"),
+];
+
+const ASS_HEADER: &str = "[Script Info]
+ScriptType: v4.00+
+WrapStyle: 0
+ScaledBorderAndShadow: yes
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,0,2,10,10,10,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+";
+
+// the fonts used by the styles of the given ass/ssa subtitles, as named in the Fontname column of their Style lines
+// a leading @ (which marks a vertical font) isn't part of the font's name, so it's removed
+pub fn referenced_fonts(text: &str) -> Vec<String> {
+    let mut fontname_column = None;
+    let mut fonts = vec![];
+    let mut in_styles = false;
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            // [V4+ Styles] for ass, [V4 Styles] for ssa
+            in_styles = line.to_lowercase().ends_with("styles]");
+        } else if !in_styles {
+            continue;
+        } else if let Some(format) = line.strip_prefix("Format:") {
+            fontname_column = format
+                .split(',')
+                .position(|column| column.trim().eq_ignore_ascii_case("fontname"));
+        } else if let Some(style) = line.strip_prefix("Style:") {
+            if let Some(font) = fontname_column.and_then(|column| style.split(',').nth(column)) {
+                let font = font.trim().trim_start_matches('@').to_string();
+                if !font.is_empty() && !fonts.contains(&font) {
+                    fonts.push(font);
+                }
+            }
+        }
+    }
+    fonts
+}
+
+// the resolution that positions and sizes in an ass file are relative to, usually the video's resolution
+// parsed from WIDTHxHEIGHT, e.g., 1920x1080
+#[derive(Debug, Clone, Copy)]
+pub struct PlayRes {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for PlayRes {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parsed = s.split_once(['x', 'X']).and_then(|(width, height)| {
+            Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+        });
+        match parsed {
+            Some((width, height)) if width > 0 && height > 0 => Ok(PlayRes { width, height }),
+            _ => Err(anyhow!(
+                "resolution {s:?} must be formatted as WIDTHxHEIGHT, e.g., 1920x1080"
+            )),
+        }
+    }
+}
+
+// converts the line breaks in ass subtitle text to srt line breaks
+// ass uses \N for a hard line break, \n for a soft line break (only applied in some wrapping styles), and \h for a non-breaking space
+// srt has no soft line breaks, so both become newlines
+pub fn ass_to_srt_text(text: &str) -> String {
+    text.replace(r"\N", "\n")
+        .replace(r"\n", "\n")
+        .replace(r"\h", "\u{a0}")
+}
+
+// converts srt subtitle text to ass subtitle text, with newlines as \N and html formatting as override tags
+pub fn srt_to_ass_text(text: &str) -> String {
+    let mut converted = text.trim_end().replace("\r\n", "\n").replace('\n', r"\N");
+    for (html, ass) in TAG_CONVERSIONS {
+        converted = converted.replace(html, ass);
+    }
+    converted
+}
+
+// converts the line breaks in each subtitle of subtitles that were converted from ass by ffmpeg
+// ffmpeg usually converts them itself, but leaves some behind (e.g., soft line breaks), which would otherwise be displayed literally
+pub fn fix_ass_line_breaks(subtitles: &mut Subtitles) {
+    for subtitle in subtitles {
+        subtitle.text = ass_to_srt_text(&subtitle.text);
+    }
+}
+
+// serializes the subtitles as an ass file, with every subtitle using a single default style
+// if a resolution is given, it's recorded as the file's PlayResX/PlayResY, otherwise players assume 384x288
+pub fn to_ass(subtitles: &Subtitles, play_res: Option<PlayRes>) -> String {
+    let mut sorted = subtitles.clone().to_vec();
+    sorted.sort_by_key(|s| s.start_time);
+
+    let mut ass = ASS_HEADER.to_string();
+    if let Some(play_res) = play_res {
+        ass = ass.replacen(
+            "[Script Info]\n",
+            &format!(
+                "[Script Info]\nPlayResX: {0}\nPlayResY: {1}\n",
+                play_res.width, play_res.height
+            ),
+            1,
+        );
+    }
+    for subtitle in sorted {
+        ass.push_str(&format!(
+            "Dialogue: 0,{0},{1},Default,,0,0,0,,{2}\n",
+            ass_timestamp(&subtitle.start_time),
+            ass_timestamp(&subtitle.end_time),
+            srt_to_ass_text(&subtitle.text)
+        ));
+    }
+    ass
+}
+
+// formats a timestamp as H:MM:SS.cc, since ass timestamps are in centiseconds
+fn ass_timestamp(timestamp: &Timestamp) -> String {
+    let centis = (timestamp_to_millis(timestamp) + 5) / 10;
+    format!(
+        "{0}:{1:02}:{2:02}.{3:02}",
+        centis / 360_000,
+        centis / 6000 % 60,
+        centis / 100 % 60,
+        centis % 100
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use srtlib::Subtitle;
+
+    #[test]
+    fn two_line_subtitles_round_trip_through_ass() {
+        let text = "first line\nsecond line";
+        let subtitles = Subtitles::new_from_vec(vec![Subtitle::new(
+            1,
+            Timestamp::new(0, 0, 1, 0),
+            Timestamp::new(0, 0, 2, 0),
+            text.to_string(),
+        )]);
+
+        // the text is the last of the dialogue's fields, and may itself contain commas
+        let ass = to_ass(&subtitles, None);
+        let dialogues = ass
+            .lines()
+            .filter_map(|line| line.strip_prefix("Dialogue:"))
+            .map(|dialogue| dialogue.splitn(10, ',').last().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(dialogues, [r"first line\Nsecond line"]);
+
+        let mut read = Subtitles::new_from_vec(vec![Subtitle::new(
+            1,
+            Timestamp::new(0, 0, 1, 0),
+            Timestamp::new(0, 0, 2, 0),
+            dialogues[0].clone(),
+        )]);
+        fix_ass_line_breaks(&mut read);
+        assert_eq!(read.to_vec()[0].text, text);
+    }
+
+    #[test]
+    fn referenced_fonts_reads_the_fontname_column_of_each_style() {
+        let ass = "[Script Info]
+Title: Style: Not,A,Style
+
+[V4+ Styles]
+Format: Name, Fontsize, Fontname, Bold
+Style: Default,20,Noto Sans,0
+Style: Vertical,20,@MS Gothic,0
+Style: Sign,30,Noto Sans,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Style: Dialogue,0,Not A Font,0
+";
+        assert_eq!(referenced_fonts(ass), ["Noto Sans", "MS Gothic"]);
+    }
+
+    #[test]
+    fn referenced_fonts_reads_ssa_styles() {
+        let ssa = "[V4 Styles]
+Format: Name, Fontname, Fontsize
+Style: Default,Arial,20
+";
+        assert_eq!(referenced_fonts(ssa), ["Arial"]);
+        assert!(referenced_fonts("").is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+// this file contains functions for pairing up items, e.g., subtitles files with the videos they belong to
+
+// finds the pairing of rows to columns with the highest total score, using the hungarian algorithm
+// scores must be a square matrix, where scores[row][column] is the score of pairing that row with that column
+// returns the column paired with each row
+pub fn best_assignment(scores: &[Vec<f64>]) -> Vec<usize> {
+    let n = scores.len();
+    if n == 0 {
+        return vec![];
+    }
+    // the algorithm minimizes cost, so the scores are negated
+    // rows and columns are 1-indexed below, with index 0 used as a sentinel
+    let cost = |row: usize, column: usize| -scores[row - 1][column - 1];
+    let mut row_potential = vec![0.0; n + 1];
+    let mut column_potential = vec![0.0; n + 1];
+    // the row assigned to each column, or 0 if the column is unassigned
+    let mut assigned_row = vec![0; n + 1];
+    let mut previous_column = vec![0; n + 1];
+
+    for row in 1..=n {
+        assigned_row[0] = row;
+        let mut column = 0;
+        let mut min_slack = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        // find an augmenting path from the new row to an unassigned column
+        loop {
+            used[column] = true;
+            let current_row = assigned_row[column];
+            let mut delta = f64::INFINITY;
+            let mut next_column = 0;
+            for candidate in 1..=n {
+                if used[candidate] {
+                    continue;
+                }
+                let slack = cost(current_row, candidate)
+                    - row_potential[current_row]
+                    - column_potential[candidate];
+                if slack < min_slack[candidate] {
+                    min_slack[candidate] = slack;
+                    previous_column[candidate] = column;
+                }
+                if min_slack[candidate] < delta {
+                    delta = min_slack[candidate];
+                    next_column = candidate;
+                }
+            }
+            for candidate in 0..=n {
+                if used[candidate] {
+                    row_potential[assigned_row[candidate]] += delta;
+                    column_potential[candidate] -= delta;
+                } else {
+                    min_slack[candidate] -= delta;
+                }
+            }
+            column = next_column;
+            if assigned_row[column] == 0 {
+                break;
+            }
+        }
+        // flip the assignments along the path
+        loop {
+            let previous = previous_column[column];
+            assigned_row[column] = assigned_row[previous];
+            column = previous;
+            if column == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut assignment = vec![0; n];
+    for column in 1..=n {
+        assignment[assigned_row[column] - 1] = column - 1;
+    }
+    assignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn best_assignment_of_nothing_is_empty() {
+        assert!(best_assignment(&[]).is_empty());
+    }
+
+    #[test]
+    fn best_assignment_of_a_single_pair() {
+        assert_eq!(best_assignment(&[vec![-3.5]]), [0]);
+    }
+
+    #[test]
+    fn best_assignment_beats_greedy_matching() {
+        // matching each row with its best remaining column pairs row 0 with column 0, for a total of 10 + 1 + 5
+        let scores = [
+            vec![10.0, 9.0, 1.0],
+            vec![9.0, 1.0, 1.0],
+            vec![1.0, 1.0, 5.0],
+        ];
+        assert_eq!(best_assignment(&scores), [1, 0, 2]);
+    }
+}
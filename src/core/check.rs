@@ -0,0 +1,120 @@
+// this file contains functions to check whether subtitles plausibly belong to a video
+
+use serde::Serialize;
+use srtlib::Subtitles;
+use std::fmt::Display;
+use std::time::Duration;
+
+use crate::core::time::timestamp_to_millis;
+
+// how far past the end of the video (as a fraction of its duration) the subtitles may extend
+const OVERRUN_TOLERANCE: f64 = 0.05;
+// below this fraction of the video's duration, the subtitles are too short to be for the whole video
+const MIN_COVERAGE: f64 = 0.5;
+// the range of subtitles per minute that looks like dialogue
+// fewer suggests e.g., signs or forced subtitles only, more suggests e.g., karaoke or duplicated subtitles
+const MIN_SUBTITLES_PER_MINUTE: f64 = 2.0;
+const MAX_SUBTITLES_PER_MINUTE: f64 = 60.0;
+
+#[derive(Serialize)]
+pub struct CheckReport {
+    pub subtitles: usize,
+    pub first_start_ms: Option<i64>,
+    pub last_end_ms: Option<i64>,
+    pub video_duration_ms: i64,
+    pub subtitles_per_minute: Option<f64>,
+    // the reasons the subtitles don't look like they belong to the video, if any
+    pub problems: Vec<String>,
+}
+
+impl CheckReport {
+    pub fn passed(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+// checks that the subtitles' timing fits a video of the given duration, and that their density looks like dialogue
+pub fn check_against_video(subtitles: &Subtitles, video_duration: Duration) -> CheckReport {
+    let video_duration_ms = video_duration.as_millis() as i64;
+    let first_start_ms = subtitles
+        .into_iter()
+        .map(|s| timestamp_to_millis(&s.start_time))
+        .min();
+    let last_end_ms = subtitles
+        .into_iter()
+        .map(|s| timestamp_to_millis(&s.end_time))
+        .max();
+
+    let mut problems = vec![];
+    let mut subtitles_per_minute = None;
+    match (first_start_ms, last_end_ms) {
+        (Some(first_start), Some(last_end)) => {
+            if last_end as f64 > video_duration_ms as f64 * (1.0 + OVERRUN_TOLERANCE) {
+                problems.push(format!(
+                    "subtitles end at {0}, after the end of the video at {1}",
+                    seconds(last_end),
+                    seconds(video_duration_ms)
+                ));
+            }
+            let span = last_end - first_start;
+            if (span as f64) < video_duration_ms as f64 * MIN_COVERAGE {
+                problems.push(format!(
+                    "subtitles span only {0} of the video's {1}",
+                    seconds(span),
+                    seconds(video_duration_ms)
+                ));
+            }
+            if span > 0 {
+                let per_minute = subtitles.len() as f64 / (span as f64 / 60_000.0);
+                subtitles_per_minute = Some(per_minute);
+                if per_minute < MIN_SUBTITLES_PER_MINUTE {
+                    problems.push(format!(
+                        "only {per_minute:.1} subtitles per minute, which is too sparse for dialogue"
+                    ));
+                } else if per_minute > MAX_SUBTITLES_PER_MINUTE {
+                    problems.push(format!(
+                        "{per_minute:.1} subtitles per minute, which is too dense for dialogue"
+                    ));
+                }
+            }
+        }
+        _ => problems.push("there are no subtitles".to_string()),
+    }
+
+    CheckReport {
+        subtitles: subtitles.len(),
+        first_start_ms,
+        last_end_ms,
+        video_duration_ms,
+        subtitles_per_minute,
+        problems,
+    }
+}
+
+fn seconds(millis: i64) -> String {
+    format!("{0:.1}s", millis as f64 / 1000.0)
+}
+
+impl Display for CheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.passed() {
+            writeln!(f, "passed")?;
+        } else {
+            writeln!(f, "failed")?;
+        }
+        writeln!(f, "subtitles: {0}", self.subtitles)?;
+        if let (Some(first_start), Some(last_end)) = (self.first_start_ms, self.last_end_ms) {
+            writeln!(
+                f,
+                "subtitles from {0} to {1}, video is {2}",
+                seconds(first_start),
+                seconds(last_end),
+                seconds(self.video_duration_ms)
+            )?;
+        }
+        for problem in &self.problems {
+            writeln!(f, "  {problem}")?;
+        }
+        Ok(())
+    }
+}
@@ -1,34 +1,81 @@
 use crate::core::ffmpeg;
-use anyhow::Result;
+use crate::core::time::{millis_to_timestamp, parse_timestamp};
+use anyhow::{anyhow, Context, Result};
 use clap::ValueEnum;
+use encoding_rs::{EncoderResult, Encoding, UTF_8};
+use itertools::Itertools;
 use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use srtlib::Subtitles;
 use std::{
+    collections::HashMap,
     fmt::Display,
+    fs::File,
     hash::{DefaultHasher, Hash, Hasher},
     path::{Path, PathBuf},
     process::{Command, Output},
+    str::FromStr,
 };
+use zip::ZipArchive;
 
 pub static TMP_DIRECTORY: Lazy<OnceCell<PathBuf>> = Lazy::new(|| OnceCell::from(tmp_directory()));
-pub const VIDEO_FILE_EXTENSIONS: [&str; 3] = ["mkv", "mp4", "avi"];
-pub const SUBTITLES_FILE_EXTENSIONS: [&str; 3] = ["ass", "ssa", "srt"];
+pub const VIDEO_FILE_EXTENSIONS: [&str; 8] =
+    ["mkv", "mp4", "avi", "mov", "webm", "ts", "m4v", "flv"];
+// VobSub subtitles are a pair of files, .idx and .sub; only the .idx is listed here so that each pair counts as one item
+// SAMI subtitles (.smi/.sami) are converted by ffmpeg; when a file has several languages, their text is kept together in each subtitle
+pub const SUBTITLES_FILE_EXTENSIONS: [&str; 6] = ["ass", "ssa", "srt", "idx", "smi", "sami"];
+pub const ARCHIVE_FILE_EXTENSIONS: [&str; 1] = ["zip"];
+pub const LANGUAGES_SIDECAR_FILENAME: &str = "languages.toml";
+
+// the temporary directory used by functions that aren't given one, which is created in the working directory
+// library users that run concurrently, or can't write to the working directory, should use the *_in variants of those functions
+// (e.g., ffmpeg::extract_subtitles_in, sync::sync_in) with their own temporary directories instead
+pub fn default_tmp_directory() -> &'static Path {
+    TMP_DIRECTORY.get().unwrap()
+}
 
 fn tmp_directory() -> PathBuf {
     let dir = PathBuf::from("tmp/");
 
     if !dir.exists() {
         std::fs::create_dir_all(&dir)
-            .expect(&format!("could not create temporary directory {dir:#?}"));
+            .unwrap_or_else(|_| panic!("could not create temporary directory {dir:#?}"));
     }
 
     dir
 }
 
+// constructs a command for the given external tool (e.g., ffmpeg, mkvmerge, ffsubsync)
+// the binary may be overridden with an environment variable named after the tool, e.g., SUBBUB_FFMPEG=/opt/ffmpeg/bin/ffmpeg
+// otherwise, the tool is looked up on the PATH
+pub fn tool_command(tool: &str) -> Command {
+    let variable = format!("SUBBUB_{0}", tool.to_uppercase());
+    match std::env::var_os(&variable) {
+        Some(binary) if !binary.is_empty() => {
+            log::trace!("using {binary:#?} for {tool} from {variable}");
+            Command::new(binary)
+        }
+        _ => Command::new(tool),
+    }
+}
+
+// extra arguments appended to the commands run for external tools, keyed by the tool's name
+// set once from the command line, for options that subbub doesn't model (e.g., ffsubsync's --gss)
+pub static EXTRA_TOOL_ARGS: OnceCell<HashMap<String, Vec<String>>> = OnceCell::new();
+
+// returns the extra arguments given for the given tool, if any
+pub fn extra_tool_args(tool: &str) -> &'static [String] {
+    EXTRA_TOOL_ARGS
+        .get()
+        .and_then(|args| args.get(tool))
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
 pub fn is_subtitle_file(path: &Path) -> bool {
     if let Some(ext) = path.extension() {
-        if SUBTITLES_FILE_EXTENSIONS.contains(&ext.to_string_lossy().to_string().as_str()) {
+        if SUBTITLES_FILE_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()) {
             return true;
         }
     }
@@ -38,7 +85,7 @@ pub fn is_subtitle_file(path: &Path) -> bool {
 
 pub fn is_video_file(path: &Path) -> bool {
     if let Some(ext) = path.extension() {
-        if VIDEO_FILE_EXTENSIONS.contains(&ext.to_string_lossy().to_string().as_str()) {
+        if VIDEO_FILE_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()) {
             return true;
         }
     }
@@ -46,11 +93,167 @@ pub fn is_video_file(path: &Path) -> bool {
     false
 }
 
+pub fn is_archive_file(path: &Path) -> bool {
+    if let Some(ext) = path.extension() {
+        if ARCHIVE_FILE_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()) {
+            return true;
+        }
+    }
+
+    false
+}
+
+// as extract_archive_in, using the default temporary directory
+pub fn extract_archive(archive_file: &Path) -> Result<PathBuf> {
+    extract_archive_in(default_tmp_directory(), archive_file)
+}
+
+// extracts the subtitles files contained in the given archive to a directory inside the given temporary directory, and returns that directory
+// entries that are not subtitles files are ignored, and any directory structure inside the archive is flattened
+pub fn extract_archive_in(tmp_dir: &Path, archive_file: &Path) -> Result<PathBuf> {
+    let output_dir = tmp_dir.join(format!("arc_{0}", hash_path(archive_file)));
+    std::fs::create_dir_all(&output_dir)?;
+
+    let mut archive = ZipArchive::new(File::open(archive_file)?)
+        .with_context(|| format!("could not open archive {archive_file:#?}"))?;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let Some(entry_path) = entry.enclosed_name() else {
+            log::warn!(
+                "skipping archive entry with unsafe path {0:?}",
+                entry.name()
+            );
+            continue;
+        };
+        // the .sub half of a VobSub pair isn't a subtitles file by itself, but is needed alongside its .idx
+        let is_vobsub_data = entry_path
+            .extension()
+            .is_some_and(|ext| ext.to_string_lossy().to_lowercase() == "sub");
+        if entry.is_dir() || !(is_subtitle_file(&entry_path) || is_vobsub_data) {
+            log::trace!("skipping archive entry {entry_path:#?}, not a subtitles file");
+            continue;
+        }
+        let output_path = output_dir.join(entry_path.file_name().unwrap());
+        log::debug!(
+            "extracting {entry_path:#?} from archive {archive_file:#?} to {output_path:#?}"
+        );
+        std::io::copy(&mut entry, &mut File::create(output_path)?)?;
+    }
+
+    Ok(output_dir)
+}
+
+// reads the languages sidecar in the given directory, which maps filenames in that directory to language codes, e.g.,
+//     "episode01.srt" = "eng"
+//     "episode02.srt" = "jpn"
+// returns an empty map if the directory has no sidecar
+pub fn read_languages_sidecar(directory: &Path) -> Result<HashMap<String, String>> {
+    let sidecar = directory.join(LANGUAGES_SIDECAR_FILENAME);
+    if !sidecar.is_file() {
+        return Ok(HashMap::new());
+    }
+    log::debug!("reading languages from sidecar {sidecar:#?}");
+    let contents = std::fs::read_to_string(&sidecar)?;
+    toml::from_str(&contents)
+        .with_context(|| format!("could not parse languages sidecar {sidecar:#?}"))
+}
+
+// the values substituted into the placeholders of an output naming template, e.g., "{stem}.{lang}.srt"
+//     {stem}    the file stem of the input (for match-videos, of the video the subtitles are matched with)
+//     {lang}    the language of the input from a languages.toml sidecar, or empty if it has none
+//     {index}   the position of the input in order of filename, starting from 1
+//     {suffix}  the suffix given to match-videos, or empty for other commands
+//     {ext}     the extension of the input
+pub struct NameFields<'a> {
+    pub stem: &'a str,
+    pub lang: Option<&'a str>,
+    pub index: usize,
+    pub suffix: &'a str,
+    pub ext: &'a str,
+}
+
+// fills in the placeholders of the given naming template, returning an error for unknown placeholders
+// an empty {lang} takes its separating dot with it (e.g., "{stem}.{lang}.srt" becomes "{stem}.srt"), so that the name stays valid
+pub fn render_name_template(template: &str, fields: &NameFields) -> Result<String> {
+    let placeholder = Regex::new(r"\{([^{}]*)\}").unwrap();
+    let mut unknown = vec![];
+    let mut rendered = String::new();
+    let mut literal_start = 0;
+    let mut skip_dot = false;
+    for captures in placeholder.captures_iter(template) {
+        let whole = captures.get(0).unwrap();
+        let mut literal = &template[literal_start..whole.start()];
+        if skip_dot {
+            literal = literal.strip_prefix('.').unwrap_or(literal);
+            skip_dot = false;
+        }
+        let value = match &captures[1] {
+            "stem" => fields.stem.to_string(),
+            "lang" => {
+                let lang = fields.lang.unwrap_or_default();
+                if lang.is_empty() {
+                    // the dot before it is removed, or the one after it if it starts the template
+                    match literal.strip_suffix('.') {
+                        Some(stripped) => literal = stripped,
+                        None => skip_dot = rendered.is_empty() && literal.is_empty(),
+                    }
+                }
+                lang.to_string()
+            }
+            "index" => fields.index.to_string(),
+            "suffix" => fields.suffix.to_string(),
+            "ext" => fields.ext.to_string(),
+            other => {
+                unknown.push(other.to_string());
+                String::new()
+            }
+        };
+        rendered.push_str(literal);
+        rendered.push_str(&value);
+        literal_start = whole.end();
+    }
+    let rest = &template[literal_start..];
+    rendered.push_str(if skip_dot {
+        rest.strip_prefix('.').unwrap_or(rest)
+    } else {
+        rest
+    });
+    if !unknown.is_empty() {
+        return Err(anyhow!(
+            "unknown placeholders {unknown:?} in name template {template:?}, expected one of {{stem}}, {{lang}}, {{index}}, {{suffix}}, {{ext}}"
+        ));
+    }
+    Ok(rendered)
+}
+
+// reads a toml manifest mapping video filenames to the subtitles track to use for each of them, e.g.,
+//     "episode01.mkv" = 2
+//     "episode02.mkv" = "lang=eng"
+// each track is either an index among the video's subtitles tracks, or a language, as accepted by TrackSelector
+pub fn read_track_manifest(manifest: &Path) -> Result<HashMap<String, TrackSelector>> {
+    log::debug!("reading tracks from manifest {manifest:#?}");
+    let contents = std::fs::read_to_string(manifest)
+        .with_context(|| format!("could not read track manifest {manifest:#?}"))?;
+    let entries: HashMap<String, toml::Value> = toml::from_str(&contents)
+        .with_context(|| format!("could not parse track manifest {manifest:#?}"))?;
+    entries
+        .into_iter()
+        .map(|(filename, value)| {
+            let track = match &value {
+                toml::Value::Integer(index) => TrackSelector::from_str(&index.to_string()),
+                toml::Value::String(track) => TrackSelector::from_str(track),
+                _ => Err(anyhow!("expected a track index or language, got {value}")),
+            }
+            .with_context(|| format!("invalid track for {filename:?} in {manifest:#?}"))?;
+            Ok((filename, track))
+        })
+        .collect()
+}
+
 pub fn list_video_files(directory: &Path) -> Vec<PathBuf> {
     directory
         .read_dir()
         .unwrap()
-        .into_iter()
         .filter_map(|entry| {
             let path = entry.unwrap().path();
             if is_video_file(&path) {
@@ -66,7 +269,6 @@ pub fn list_subtitles_files(directory: &Path) -> Vec<PathBuf> {
     directory
         .read_dir()
         .unwrap()
-        .into_iter()
         .filter_map(|entry| {
             let path = entry.unwrap().path();
             if is_subtitle_file(&path) {
@@ -78,18 +280,87 @@ pub fn list_subtitles_files(directory: &Path) -> Vec<PathBuf> {
         .collect()
 }
 
+// where a set of subtitles is read from
+// a source is parsed from a path without touching the filesystem, so the same syntax can describe files that don't exist yet
+// use validate to check that the source exists before reading it
+#[derive(Debug, Clone)]
 pub enum SubtitleSource {
     File(PathBuf),
     VideoTrack {
         video_file: PathBuf,
-        subtitle_track: u32,
+        subtitle_track: TrackSelector,
     },
+    // an archive (e.g., zip) containing subtitles files
+    Archive(PathBuf),
+    // a subtitles file downloaded over http(s), which requires the url feature
+    Url(String),
+}
+
+// the attributes of a subtitles track added to a video
+#[derive(Debug, Clone, Default)]
+pub struct TrackMetadata {
+    pub language: Option<String>,
+    pub name: String,
+    // when set, players select the track when no other track is chosen
+    pub default: bool,
+    // when set, players display the track automatically (e.g., for subtitles that only cover foreign dialogue)
+    pub forced: bool,
+}
+
+// a subtitles file to add to a video as a new track
+pub struct SubtitlesTrack<'a> {
+    pub path: &'a Path,
+    pub metadata: TrackMetadata,
+}
+
+// selects a subtitles track in a video, either by its index among the video's subtitles tracks, or by its language
+// parsed from either an index (e.g., 2) or a language code (e.g., lang=eng, or lang=und for tracks without a language)
+#[derive(Debug, Clone)]
+pub enum TrackSelector {
+    Index(u32),
+    Language(String),
+}
+
+impl FromStr for TrackSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(language) = s.strip_prefix("lang=") {
+            if language.is_empty() {
+                return Err(anyhow!(
+                    "track {s:?} is missing a language code, e.g., lang=eng"
+                ));
+            }
+            return Ok(TrackSelector::Language(language.to_string()));
+        }
+        let index = s.parse().with_context(|| {
+            format!("track {s:?} must be an index (e.g., 2) or a language (e.g., lang=eng)")
+        })?;
+        Ok(TrackSelector::Index(index))
+    }
+}
+
+impl Display for TrackSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrackSelector::Index(index) => write!(f, "{index}"),
+            TrackSelector::Language(language) => write!(f, "lang={language}"),
+        }
+    }
+}
+
+// the format of the information printed by commands that report on subtitles (e.g., diff)
+#[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    TEXT,
+    JSON,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, Copy)]
 #[serde(rename_all = "snake_case")]
 pub enum SyncTool {
-    #[serde(alias = "ffsubsync")]
+    #[serde(rename = "ffsubsync")]
     FFSUBSYNC,
 }
 
@@ -102,17 +373,315 @@ pub enum ShiftDirection {
     LATER,
 }
 
+// where a set of subtitles is displayed on the video when combined with others
+#[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Alignment {
+    BOTTOM,
+    TOP,
+    MIDDLE,
+}
+
+impl Alignment {
+    // the ass override tag that places a subtitle at this alignment, using numpad layout
+    // bottom is the default position, so those subtitles are left untagged
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Alignment::BOTTOM => "",
+            Alignment::TOP => r"{\an8}",
+            Alignment::MIDDLE => r"{\an5}",
+        }
+    }
+}
+
+// how combined subtitles are displayed together
+#[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, Copy)]
+#[serde(rename_all = "snake_case")]
+#[allow(non_camel_case_types)]
+pub enum MergeStyle {
+    // each set of subtitles is displayed at its own position, using ass alignment tags (e.g., {\an8})
+    TOP_BOTTOM,
+    // subtitles displayed at the same time are combined into one cue, one line per set of subtitles
+    // for players that don't understand alignment tags in srt files, and display them as text
+    STACKED,
+}
+
+// how subtitles files are paired with the videos they belong to
+#[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchBy {
+    // in order of their filenames
+    ORDER,
+    // by how well each subtitles file syncs to each video's audio
+    CONTENT,
+}
+
+// the order of the subtitle tracks added to each video by add-dual-subs
+#[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, Copy)]
+#[serde(rename_all = "snake_case")]
+#[allow(non_camel_case_types)]
+pub enum TrackOrder {
+    SINGLE_FIRST,
+    DUAL_FIRST,
+}
+
+// the time that stays fixed when scaling the timeline of subtitles
+#[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ScaleAnchor {
+    // the start of the video
+    ZERO,
+    // the start of the first subtitle
+    FIRST,
+}
+
+// how dialogue between several speakers is formatted
+#[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum DialogueStyle {
+    // one cue for all the speakers, each line starting with a dash, e.g., "- Hi.\n- Hello."
+    DASHES,
+    // one cue for each speaker, all displayed at the same time
+    SEPARATE,
+}
+
+// how add-dual-subs adds the two sets of subtitles to each video
+#[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum DualMode {
+    // a dual track, with both sets of subtitles merged into one track at the top and bottom of the video
+    MERGED,
+    // two separate tracks, the given subtitles aligned at the top and the reference subtitles at the bottom
+    // for players that can display two subtitle tracks at once (e.g., mpv's secondary subtitles)
+    SEPARATE,
+}
+
+// where newly added subtitle tracks are placed relative to a video's existing subtitle tracks
+#[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackPosition {
+    LAST,
+    FIRST,
+}
+
+// the tool used to add subtitles tracks to videos
+// both support each track's language, name, and default and forced flags
+#[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Muxer {
+    MKVMERGE,
+    FFMPEG,
+}
+
+// the container of the videos written by add-dual-subs
+#[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    MKV,
+    // mp4 can only contain mov_text subtitles, which don't support styling or positioning, and only ffmpeg can write it
+    MP4,
+}
+
+impl Container {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Container::MKV => "mkv",
+            Container::MP4 => "mp4",
+        }
+    }
+}
+
+impl Muxer {
+    // the name of the tool's binary, as given to tool_command
+    pub fn tool(&self) -> &'static str {
+        match self {
+            Muxer::MKVMERGE => "mkvmerge",
+            Muxer::FFMPEG => "ffmpeg",
+        }
+    }
+}
+
+// the line endings used when writing subtitles files
+#[derive(Serialize, Deserialize, Debug, Clone, ValueEnum, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum LineEndings {
+    LF,
+    CRLF,
+    // uses the line endings of the input file, falling back to lf if they can't be detected (e.g., the input is a video)
+    PRESERVE,
+}
+
+// detects whether the given text file uses crlf or lf line endings
+// returns None if the file can't be read or has no line endings
+pub fn detect_line_endings(path: &Path) -> Option<LineEndings> {
+    let bytes = std::fs::read(path).ok()?;
+    let first_newline = bytes.iter().position(|b| *b == b'\n')?;
+    if first_newline > 0 && bytes[first_newline - 1] == b'\r' {
+        Some(LineEndings::CRLF)
+    } else {
+        Some(LineEndings::LF)
+    }
+}
+
+// the format of a subtitles file, as determined by its content
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitlesFormat {
+    SRT,
+    ASS,
+    SSA,
+    WEBVTT,
+    SAMI,
+    VOBSUB,
+    MICRODVD,
+}
+
+impl Display for SubtitlesFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{0}", format!("{self:?}").to_lowercase())
+    }
+}
+
+// determines the format of subtitles from their content, rather than trusting the file's extension
+// (e.g., a .srt file that actually contains ass subtitles)
+// only the start of the text is inspected; returns None if the content isn't recognized
+pub fn sniff_subtitles_format(bytes: &[u8]) -> Option<SubtitlesFormat> {
+    let (text, _) = detect_encoding(bytes)
+        .unwrap_or(UTF_8)
+        .decode_with_bom_removal(bytes);
+    let head = text.chars().take(4096).collect::<String>();
+    let trimmed = head.trim_start();
+    let lowercase = trimmed.to_lowercase();
+
+    if lowercase.starts_with("webvtt") {
+        Some(SubtitlesFormat::WEBVTT)
+    } else if lowercase.starts_with("[script info]") {
+        // ass declares itself as v4.00+, while ssa is v4.00
+        if lowercase.contains("v4.00+") || lowercase.contains("[v4+ styles]") {
+            Some(SubtitlesFormat::ASS)
+        } else {
+            Some(SubtitlesFormat::SSA)
+        }
+    } else if lowercase.starts_with("<sami") {
+        Some(SubtitlesFormat::SAMI)
+    } else if lowercase.starts_with("# vobsub index file") {
+        Some(SubtitlesFormat::VOBSUB)
+    } else if Regex::new(r"^\{\d+\}\{\d*\}").unwrap().is_match(trimmed) {
+        Some(SubtitlesFormat::MICRODVD)
+    } else if Regex::new(r"(?m)^\s*\d+:\d+:\d+[,.]\d+\s*-->\s*\d+:\d+:\d+[,.]\d+")
+        .unwrap()
+        .is_match(&head)
+    {
+        Some(SubtitlesFormat::SRT)
+    } else {
+        None
+    }
+}
+
+// detects the text encoding of the given bytes from their byte order mark, or as utf-8 if they're valid utf-8
+// returns None if the encoding can't be determined, e.g., for legacy encodings such as windows-1251
+pub fn detect_encoding(bytes: &[u8]) -> Option<&'static Encoding> {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        Some(encoding)
+    } else if std::str::from_utf8(bytes).is_ok() {
+        Some(UTF_8)
+    } else {
+        None
+    }
+}
+
 impl SubtitleSource {
+    // determines the kind of source from the path's extension alone
+    // videos require a track; any other file that isn't an archive is treated as a subtitles file
+    pub fn parse(path: &Path, subtitle_track: Option<&TrackSelector>) -> Result<SubtitleSource> {
+        if is_url(path) {
+            let url = path.to_string_lossy();
+            let scheme = url
+                .split_once("://")
+                .map(|(scheme, _)| scheme.to_lowercase());
+            if !matches!(scheme.as_deref(), Some("http" | "https")) {
+                return Err(anyhow!(
+                    "unsupported url {url:?}, only http and https urls are supported"
+                ));
+            }
+            Ok(SubtitleSource::Url(url.to_string()))
+        } else if is_video_file(path) {
+            let subtitle_track = subtitle_track.with_context(|| {
+                format!("when supplying a video file {path:#?} as input, subtitle track must be specified")
+            })?;
+            Ok(SubtitleSource::VideoTrack {
+                video_file: path.to_path_buf(),
+                subtitle_track: subtitle_track.clone(),
+            })
+        } else if is_archive_file(path) {
+            Ok(SubtitleSource::Archive(path.to_path_buf()))
+        } else {
+            Ok(SubtitleSource::File(path.to_path_buf()))
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            SubtitleSource::File(path) | SubtitleSource::Archive(path) => path,
+            SubtitleSource::VideoTrack { video_file, .. } => video_file,
+            SubtitleSource::Url(url) => Path::new(url),
+        }
+    }
+
+    // checks that the source exists and is a file, as required to read it
+    // urls can't be checked without downloading them, so only the support for downloading them is checked
+    pub fn validate(&self) -> Result<()> {
+        if let SubtitleSource::Url(url) = self {
+            if !cfg!(feature = "url") {
+                return Err(anyhow!(
+                    "cannot read {url:?}, subbub was built without support for urls\nrebuild it with --features url"
+                ));
+            }
+            return Ok(());
+        }
+        let path = self.path();
+        if !path.is_file() {
+            return Err(anyhow!(
+                "input path {path:#?} was not a file, are you sure it exists?"
+            ));
+        }
+        Ok(())
+    }
+
+    // as to_subtitles_in, using the default temporary directory
     pub fn to_subtitles(&self) -> Result<Subtitles> {
+        self.to_subtitles_in(default_tmp_directory())
+    }
+
+    // reads the subtitles from this source, writing any converted, extracted, or downloaded files to the given temporary directory
+    pub fn to_subtitles_in(&self, tmp_dir: &Path) -> Result<Subtitles> {
         match self {
             SubtitleSource::File(pathbuf) => {
-                let extension = pathbuf.extension().unwrap();
-                let subtitles = if extension == "srt" {
+                let extension = pathbuf
+                    .extension()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_lowercase();
+                // subtitles packs are often mislabeled (e.g., ass subtitles in a .srt file), so the format is detected from the content
+                // falling back to the extension if the content isn't recognized (e.g., binary VobSub .sub files)
+                let bytes = std::fs::read(pathbuf)
+                    .with_context(|| format!("could not read subtitles file {pathbuf:#?}"))?;
+                let is_srt = match sniff_subtitles_format(&bytes) {
+                    Some(format) => {
+                        let is_srt = format == SubtitlesFormat::SRT;
+                        if is_srt != (extension == "srt") {
+                            log::warn!("{pathbuf:#?} contains {format} subtitles, reading it as {format} rather than by its extension");
+                        }
+                        is_srt
+                    }
+                    None => extension == "srt",
+                };
+                let subtitles = if is_srt {
                     // if the subtitles are already srt format, we can read them directly
-                    Subtitles::parse_from_file(pathbuf, None)?
+                    parse_srt_file(pathbuf)?
                 } else {
                     // otherwise, we need to convert the file using ffmpeg first
-                    ffmpeg::read_subtitles_file(pathbuf)?
+                    ffmpeg::read_subtitles_file_in(tmp_dir, pathbuf)?
                 };
                 Ok(subtitles)
             }
@@ -120,16 +689,175 @@ impl SubtitleSource {
                 video_file,
                 subtitle_track,
             } => {
-                let s = ffmpeg::extract_subtitles(video_file, *subtitle_track)?;
+                let subtitle_track = ffmpeg::resolve_subtitle_track(video_file, subtitle_track)?;
+                let s = ffmpeg::extract_subtitles_in(tmp_dir, video_file, subtitle_track)?;
                 Ok(s)
             }
+            SubtitleSource::Archive(archive_file) => {
+                // an archive can only be read as a single set of subtitles if it contains exactly one subtitles file
+                let extracted = list_subtitles_files(&extract_archive_in(tmp_dir, archive_file)?);
+                match extracted.as_slice() {
+                    [subtitles_file] => SubtitleSource::File(subtitles_file.clone()).to_subtitles_in(tmp_dir),
+                    _ => Err(anyhow!(
+                        "archive {archive_file:#?} contains {0} subtitles files, expected exactly one",
+                        extracted.len()
+                    )),
+                }
+            }
+            SubtitleSource::Url(url) => {
+                SubtitleSource::File(download(tmp_dir, url)?).to_subtitles_in(tmp_dir)
+            }
+        }
+    }
+}
+
+// reads an srt file, repairing malformed timestamps before parsing
+pub fn parse_srt_file(path: &Path) -> Result<Subtitles> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read subtitles file {path:#?}"))?;
+    let subtitles = Subtitles::parse_from_str(repair_srt(&text))
+        .with_context(|| format!("could not parse subtitles file {path:#?}"))?;
+    Ok(subtitles)
+}
+
+// normalizes the timing lines of srt text so that srtlib can parse them
+// this fixes '.' instead of ',' before milliseconds, missing leading zeros or hours, and irregular spacing around -->
+// lines that can't be repaired are left as they are
+// byte order marks are removed, and runs of blank lines (including lines of only whitespace) are collapsed to the single blank line that separates cues
+pub fn repair_srt(text: &str) -> String {
+    let mut lines: Vec<String> = vec![];
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        // files that were concatenated may have byte order marks partway through
+        let line = line.replace('\u{feff}', "");
+        if line.trim().is_empty() {
+            if lines.last().is_some_and(|last| !last.is_empty()) {
+                lines.push(String::new());
+            }
+            continue;
+        }
+        lines.push(repair_timing_line(&line).unwrap_or(line));
+    }
+    lines.join("\n")
+}
+
+fn repair_timing_line(line: &str) -> Option<String> {
+    let (start, rest) = line.split_once("-->")?;
+    let mut rest = rest.split_whitespace();
+    let end = rest.next()?;
+    // anything after the end timestamp is position information, which is preserved
+    let position = rest.join(" ");
+
+    let start = millis_to_timestamp(parse_timestamp(start).ok()?);
+    let end = millis_to_timestamp(parse_timestamp(end).ok()?);
+    if position.is_empty() {
+        Some(format!("{start} --> {end}"))
+    } else {
+        Some(format!("{start} --> {end} {position}"))
+    }
+}
+
+// encodes serialized subtitles (e.g., srt or ass) with the given line endings and encoding
+// errors if any character can't be represented in the encoding, rather than silently replacing it
+// the preserve line endings must be resolved by the caller, and are otherwise treated as lf
+pub fn encode_text(
+    text: &str,
+    encoding: &'static Encoding,
+    line_endings: LineEndings,
+) -> Result<Vec<u8>> {
+    let text = match line_endings {
+        LineEndings::CRLF => text.replace('\n', "\r\n"),
+        LineEndings::LF | LineEndings::PRESERVE => text.to_string(),
+    };
+    if encoding == UTF_8 {
+        return Ok(text.into_bytes());
+    }
+
+    let mut encoder = encoding.new_encoder();
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut remaining = text.as_str();
+    loop {
+        bytes.reserve(
+            encoder
+                .max_buffer_length_from_utf8_without_replacement(remaining.len())
+                .context("subtitles are too large to encode")?,
+        );
+        let (result, read) =
+            encoder.encode_from_utf8_to_vec_without_replacement(remaining, &mut bytes, true);
+        remaining = &remaining[read..];
+        match result {
+            EncoderResult::InputEmpty => return Ok(bytes),
+            EncoderResult::OutputFull => continue,
+            EncoderResult::Unmappable(c) => {
+                return Err(anyhow!(
+                    "character {c:?} cannot be represented in the {0} encoding",
+                    encoding.name()
+                ))
+            }
         }
     }
 }
 
 pub fn hash_subtitles(subtitles: &Subtitles) -> u64 {
-    let s = subtitles.to_string();
-    hash_string(&s)
+    // normalize the serialized form so that the hash doesn't depend on srtlib's formatting quirks
+    // (trailing whitespace, CRLF vs LF, trailing blank lines)
+    let s = subtitles
+        .to_string()
+        .lines()
+        .map(|line| line.trim_end())
+        .join("\n");
+    hash_string(s.trim_end())
+}
+
+// downloads the given url to the given temporary directory, returning the path of the downloaded file
+// the file keeps the extension of the url, so that it's read in the right format
+#[cfg(feature = "url")]
+fn download(tmp_dir: &Path, url: &str) -> Result<PathBuf> {
+    use std::io::Read;
+
+    let file_name = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|path| path.rsplit('/').next())
+        .unwrap_or_default();
+    let extension = Path::new(file_name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| "srt".to_string());
+    let tmp_file = tmp_dir.join(format!("url_{0}.{extension}", hash_string(url)));
+
+    log::debug!("downloading {url:?} to {tmp_file:#?}");
+    let mut bytes = vec![];
+    ureq::get(url)
+        .call()
+        .with_context(|| format!("could not download {url:?}"))?
+        .into_reader()
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("could not read the response from {url:?}"))?;
+    std::fs::write(&tmp_file, bytes)
+        .with_context(|| format!("could not write {url:?} to {tmp_file:#?}"))?;
+    Ok(tmp_file)
+}
+
+#[cfg(not(feature = "url"))]
+fn download(_tmp_dir: &Path, url: &str) -> Result<PathBuf> {
+    Err(anyhow!(
+        "cannot download {url:?}, subbub was built without support for urls\nrebuild it with --features url"
+    ))
+}
+
+// whether the given path is actually a url, e.g., https://example.com/episode01.srt
+pub fn is_url(path: &Path) -> bool {
+    path.to_string_lossy().contains("://")
+}
+
+// hashes the canonical form of the given path, for naming temporary files after the file they were created from
+// unlike the file's name, this is distinct for files with the same name in different directories
+pub fn hash_path(path: &Path) -> u64 {
+    let canonical = path
+        .canonicalize()
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    hash_string(&canonical.to_string_lossy())
 }
 
 pub fn hash_string(s: &str) -> u64 {
@@ -158,3 +886,116 @@ pub fn pretty_output(output: &Output) -> String {
     );
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRT: &str = "1\n00:00:01,000 --> 00:00:02,000\nHello\nthere\n\n2\n00:00:03,000 --> 00:00:04,500\nGeneral Kenobi\n";
+
+    // parses the text the way subtitles files are parsed
+    fn parse(text: &str) -> Subtitles {
+        Subtitles::parse_from_str(repair_srt(text)).unwrap()
+    }
+
+    #[test]
+    fn hash_subtitles_is_unchanged_by_a_round_trip() {
+        let crlf = SRT.replace('\n', "\r\n");
+        let trailing_whitespace = SRT
+            .replace("Hello\n", "Hello  \n")
+            .replace("Kenobi\n", "Kenobi \t\n");
+        for text in [SRT, crlf.as_str(), trailing_whitespace.as_str()] {
+            let parsed = parse(text);
+            let round_tripped = parse(&parsed.to_string());
+            assert_eq!(hash_subtitles(&parsed), hash_subtitles(&round_tripped));
+            assert_eq!(hash_subtitles(&parsed), hash_subtitles(&parse(SRT)));
+        }
+    }
+
+    #[test]
+    fn parse_srt_file_handles_boms_and_whitespace_only_blank_lines() {
+        let text = "\u{feff}1\n00:00:01,000 --> 00:00:02,000\nHello\n \n\t\n2\n00:00:03,000 --> 00:00:04,000\nthere\n  \n\n3\n00:00:05,000 --> 00:00:06,000\nfriend\n";
+        // srtlib doesn't separate cues at whitespace-only lines, so it merges them into the text of the cue before
+        let raw = Subtitles::parse_from_str(text.to_string());
+        assert!(!raw.is_ok_and(|subtitles| subtitles.len() == 3));
+
+        let path = std::env::temp_dir().join(format!("subbub_repair_{0}.srt", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let parsed = parse_srt_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let texts = parsed
+            .unwrap()
+            .to_vec()
+            .into_iter()
+            .map(|s| s.text)
+            .collect_vec();
+        assert_eq!(texts, ["Hello", "there", "friend"]);
+    }
+
+    #[test]
+    fn is_video_file_ignores_extension_case() {
+        for name in ["a.mkv", "a.MKV", "a.Mov", "a.webm", "a.Mp4"] {
+            assert!(is_video_file(Path::new(name)), "{name}");
+        }
+        assert!(!is_video_file(Path::new("a.srt")));
+        assert!(!is_video_file(Path::new("mkv")));
+    }
+
+    #[test]
+    fn is_subtitle_file_ignores_extension_case() {
+        for name in ["a.srt", "a.SRT", "a.Ass", "a.SSA", "a.Smi"] {
+            assert!(is_subtitle_file(Path::new(name)), "{name}");
+        }
+        assert!(!is_subtitle_file(Path::new("a.MKV")));
+        assert!(is_video_file(Path::new("a.MKV")));
+    }
+
+    fn render(template: &str, stem: &str, lang: Option<&str>) -> String {
+        let fields = NameFields {
+            stem,
+            lang,
+            index: 1,
+            suffix: "",
+            ext: "srt",
+        };
+        render_name_template(template, &fields).unwrap()
+    }
+
+    #[test]
+    fn render_name_template_removes_the_separator_of_an_empty_lang() {
+        assert_eq!(
+            render("{stem}.{lang}.srt", "Mr..Robot", None),
+            "Mr..Robot.srt"
+        );
+        assert_eq!(
+            render("{stem}.{lang}", "Show...Title.", None),
+            "Show...Title."
+        );
+        assert_eq!(render("{lang}.{stem}.{ext}", "Show", None), "Show.srt");
+        assert_eq!(render("{stem}.{lang}.srt", "Show", Some("")), "Show.srt");
+    }
+
+    #[test]
+    fn render_name_template_keeps_a_present_lang() {
+        assert_eq!(
+            render("{stem}.{lang}.forced.srt", "Mr..Robot", Some("en")),
+            "Mr..Robot.en.forced.srt"
+        );
+        assert_eq!(
+            render("{lang}.{stem}.{ext}", "Show...", Some("ja")),
+            "ja.Show....srt"
+        );
+        assert!(render_name_template(
+            "{stem}.{language}.srt",
+            &NameFields {
+                stem: "Show",
+                lang: None,
+                index: 1,
+                suffix: "",
+                ext: "srt",
+            }
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,428 @@
+// this file contains functions to compare two sets of subtitles
+
+use itertools::Itertools;
+use serde::{Serialize, Serializer};
+use srtlib::{Subtitle, Subtitles};
+use std::fmt::Display;
+use std::iter::zip;
+
+use crate::core::time::{millis_to_timestamp, timestamp_to_millis};
+
+// a cue from one set of subtitles paired with the cue in the other set that starts closest to it
+#[derive(Serialize)]
+pub struct CueMatch {
+    #[serde(serialize_with = "serialize_subtitle")]
+    pub subtitle: Subtitle,
+    #[serde(serialize_with = "serialize_subtitle")]
+    pub other: Subtitle,
+    // how much later the other cue starts, in milliseconds
+    pub offset_ms: i64,
+}
+
+impl CueMatch {
+    pub fn text_changed(&self) -> bool {
+        self.subtitle.text.trim() != self.other.text.trim()
+    }
+}
+
+#[derive(Serialize)]
+pub struct DiffReport {
+    pub count: usize,
+    pub other_count: usize,
+    pub matches: Vec<CueMatch>,
+}
+
+impl DiffReport {
+    pub fn mean_offset_ms(&self) -> Option<f64> {
+        if self.matches.is_empty() {
+            return None;
+        }
+        let total: i64 = self.matches.iter().map(|m| m.offset_ms).sum();
+        Some(total as f64 / self.matches.len() as f64)
+    }
+
+    // if the median offset accounts for nearly all cues, the other subtitles are likely a constant shift of these
+    pub fn median_offset_ms(&self) -> Option<i64> {
+        let offsets = self
+            .matches
+            .iter()
+            .map(|m| m.offset_ms)
+            .sorted()
+            .collect_vec();
+        offsets.get(offsets.len() / 2).copied()
+    }
+
+    pub fn text_changes(&self) -> impl Iterator<Item = &CueMatch> {
+        self.matches.iter().filter(|m| m.text_changed())
+    }
+}
+
+impl Display for DiffReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "cues: {0} vs {1} ({2:+})",
+            self.count,
+            self.other_count,
+            self.other_count as i64 - self.count as i64
+        )?;
+        match (self.mean_offset_ms(), self.median_offset_ms()) {
+            (Some(mean), Some(median)) => {
+                writeln!(f, "mean offset of matched cues: {mean:+.0}ms")?;
+                writeln!(f, "median offset of matched cues: {median:+}ms")?;
+            }
+            _ => writeln!(f, "no cues could be matched")?,
+        }
+        let text_changes = self.text_changes().collect_vec();
+        writeln!(f, "text changes: {0}", text_changes.len())?;
+        for change in text_changes {
+            writeln!(
+                f,
+                "  #{0} {1}: {2:?}\n  #{3} {4}: {5:?}",
+                change.subtitle.num,
+                change.subtitle.start_time,
+                change.subtitle.text,
+                change.other.num,
+                change.other.start_time,
+                change.other.text
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// compares two sets of subtitles, matching each cue with the other cue that has the nearest start time
+pub fn diff(subtitles: &Subtitles, other: &Subtitles) -> DiffReport {
+    let others = other
+        .into_iter()
+        .sorted_by_key(|s| s.start_time)
+        .collect_vec();
+    let other_starts = others
+        .iter()
+        .map(|s| timestamp_to_millis(&s.start_time))
+        .collect_vec();
+
+    let matches = subtitles
+        .into_iter()
+        .filter_map(|subtitle| {
+            let start = timestamp_to_millis(&subtitle.start_time);
+            let index = nearest_index(&other_starts, start)?;
+            Some(CueMatch {
+                subtitle: subtitle.clone(),
+                other: others[index].clone(),
+                offset_ms: other_starts[index] - start,
+            })
+        })
+        .collect_vec();
+
+    DiffReport {
+        count: subtitles.len(),
+        other_count: other.len(),
+        matches,
+    }
+}
+
+// srtlib's subtitles aren't serializable, so they're serialized as their fields, with timestamps in srt format
+fn serialize_subtitle<S: Serializer>(
+    subtitle: &Subtitle,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct SerializedSubtitle<'a> {
+        num: usize,
+        start_time: String,
+        end_time: String,
+        text: &'a str,
+    }
+
+    SerializedSubtitle {
+        num: subtitle.num,
+        start_time: subtitle.start_time.to_string(),
+        end_time: subtitle.end_time.to_string(),
+        text: &subtitle.text,
+    }
+    .serialize(serializer)
+}
+
+// returns the index of the value in the sorted slice that is closest to the target
+fn nearest_index(sorted: &[i64], target: i64) -> Option<usize> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let index = sorted.partition_point(|v| *v < target);
+    if index == 0 {
+        Some(0)
+    } else if index == sorted.len() {
+        Some(sorted.len() - 1)
+    } else if target - sorted[index - 1] <= sorted[index] - target {
+        Some(index - 1)
+    } else {
+        Some(index)
+    }
+}
+
+// how much the fitted offset may change between the first and last matched cues before the subtitles are considered to drift
+// smaller changes are within the precision of the sync tools, so a constant shift is enough
+const DRIFT_THRESHOLD_MS: f64 = 250.0;
+// the number of points the offsets over time are summarized with
+const DRIFT_SAMPLES: usize = 10;
+
+// the correction that best brings the subtitles in line with the other subtitles
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase", tag = "correction")]
+pub enum Correction {
+    // the subtitles are already in sync
+    NONE,
+    // the subtitles are off by a constant offset, e.g., shift-timing
+    SHIFT { seconds: f64 },
+    // the subtitles drift, e.g., scale anchored at zero, then shift-timing
+    SCALE { factor: f64, shift_seconds: f64 },
+}
+
+impl Display for Correction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let shift = |f: &mut std::fmt::Formatter<'_>, seconds: f64| {
+            let direction = if seconds < 0.0 { "earlier" } else { "later" };
+            write!(f, "shift-timing -s {0:.3} -d {direction}", seconds.abs())
+        };
+        match self {
+            Correction::NONE => write!(f, "none, the subtitles are in sync"),
+            Correction::SHIFT { seconds } => shift(f, *seconds),
+            Correction::SCALE {
+                factor,
+                shift_seconds,
+            } => {
+                write!(f, "scale -x {factor:.6} -a zero, then ")?;
+                shift(f, *shift_seconds)
+            }
+        }
+    }
+}
+
+// how the offset between matched cues changes over the course of the subtitles
+// fitted as offset = intercept + slope * start, where start is the start of a cue in the subtitles
+#[derive(Serialize)]
+pub struct DriftReport {
+    // the number of matched cues the fit is based on, excluding outliers
+    pub matched: usize,
+    pub intercept_ms: f64,
+    // how many milliseconds the offset changes by for each second of the subtitles
+    pub slope_ms_per_second: f64,
+    // the fitted offsets at the first and last matched cues
+    pub first_start_ms: i64,
+    pub last_start_ms: i64,
+    pub offset_at_first_ms: f64,
+    pub offset_at_last_ms: f64,
+    // the median offset of the matched cues in each of several equal spans of time, as (start of span, offset)
+    pub offsets_over_time: Vec<(i64, i64)>,
+    pub correction: Correction,
+}
+
+impl Display for DriftReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "matched cues: {0}", self.matched)?;
+        writeln!(
+            f,
+            "offset at {0}: {1:+.0}ms",
+            millis_to_timestamp(self.first_start_ms),
+            self.offset_at_first_ms
+        )?;
+        writeln!(
+            f,
+            "offset at {0}: {1:+.0}ms",
+            millis_to_timestamp(self.last_start_ms),
+            self.offset_at_last_ms
+        )?;
+        writeln!(
+            f,
+            "drift: {0:+.2}ms per minute",
+            self.slope_ms_per_second * 60.0
+        )?;
+        writeln!(f, "offsets over time:")?;
+        for (start, offset) in &self.offsets_over_time {
+            writeln!(f, "  {0}  {offset:+}ms", millis_to_timestamp(*start))?;
+        }
+        writeln!(f, "recommended correction: {0}", self.correction)
+    }
+}
+
+// measures how the timing of the subtitles drifts relative to the other subtitles, recommending a correction
+// cues are matched by nearest start time, as in diff, so an offset larger than the gaps between cues can't be measured
+// in that case, shift the subtitles by roughly the right amount first (e.g., by diff's median offset)
+// returns None if too few cues could be matched to fit a line
+pub fn drift(subtitles: &Subtitles, other: &Subtitles) -> Option<DriftReport> {
+    let points = diff(subtitles, other)
+        .matches
+        .iter()
+        .map(|m| (timestamp_to_millis(&m.subtitle.start_time), m.offset_ms))
+        .sorted()
+        .collect_vec();
+
+    // mismatched cues (e.g., lines that only exist in one set of subtitles) have wildly different offsets,
+    // so the line is fitted again without the points that are far from the first fit
+    let (intercept, slope) = fit_line(&points)?;
+    let residuals = points
+        .iter()
+        .map(|(x, y)| (*y as f64 - (intercept + slope * *x as f64)).abs())
+        .collect_vec();
+    let median_residual = residuals
+        .iter()
+        .copied()
+        .sorted_by(f64::total_cmp)
+        .nth(residuals.len() / 2)?;
+    let tolerance = (median_residual * 3.0).max(500.0);
+    let inliers = zip(&points, &residuals)
+        .filter(|(_, residual)| **residual <= tolerance)
+        .map(|(point, _)| *point)
+        .collect_vec();
+    let (intercept, slope) = fit_line(&inliers)?;
+
+    let first_start = inliers.first()?.0;
+    let last_start = inliers.last()?.0;
+    let offset_at = |x: i64| intercept + slope * x as f64;
+    let offset_at_first = offset_at(first_start);
+    let offset_at_last = offset_at(last_start);
+
+    let correction = if (offset_at_last - offset_at_first).abs() >= DRIFT_THRESHOLD_MS {
+        // synced time = start + intercept + slope * start = (1 + slope) * start + intercept
+        Correction::SCALE {
+            factor: 1.0 + slope,
+            shift_seconds: intercept / 1000.0,
+        }
+    } else {
+        let median_offset = inliers
+            .iter()
+            .map(|(_, y)| *y)
+            .sorted()
+            .nth(inliers.len() / 2)?;
+        if median_offset == 0 {
+            Correction::NONE
+        } else {
+            Correction::SHIFT {
+                seconds: median_offset as f64 / 1000.0,
+            }
+        }
+    };
+
+    let span = ((last_start - first_start) / DRIFT_SAMPLES as i64).max(1);
+    let offsets_over_time = inliers
+        .iter()
+        .chunk_by(|(x, _)| ((x - first_start) / span).min(DRIFT_SAMPLES as i64 - 1))
+        .into_iter()
+        .map(|(sample, group)| {
+            let offsets = group.map(|(_, y)| *y).sorted().collect_vec();
+            (first_start + sample * span, offsets[offsets.len() / 2])
+        })
+        .collect_vec();
+
+    Some(DriftReport {
+        matched: inliers.len(),
+        intercept_ms: intercept,
+        slope_ms_per_second: slope * 1000.0,
+        first_start_ms: first_start,
+        last_start_ms: last_start,
+        offset_at_first_ms: offset_at_first,
+        offset_at_last_ms: offset_at_last,
+        offsets_over_time,
+        correction,
+    })
+}
+
+// fits a line to the given points with least squares, returning its intercept and slope
+// returns None if there are fewer than two distinct x values, since no line can be fitted
+fn fit_line(points: &[(i64, i64)]) -> Option<(f64, f64)> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| *x as f64).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| *y as f64).sum::<f64>() / n;
+    let covariance: f64 = points
+        .iter()
+        .map(|(x, y)| (*x as f64 - mean_x) * (*y as f64 - mean_y))
+        .sum();
+    let variance: f64 = points
+        .iter()
+        .map(|(x, _)| (*x as f64 - mean_x).powi(2))
+        .sum();
+    if variance == 0.0 {
+        return None;
+    }
+    let slope = covariance / variance;
+    Some((mean_y - slope * mean_x, slope))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subtitles(starts: impl IntoIterator<Item = i64>) -> Subtitles {
+        Subtitles::new_from_vec(
+            starts
+                .into_iter()
+                .enumerate()
+                .map(|(index, start)| {
+                    Subtitle::new(
+                        index + 1,
+                        millis_to_timestamp(start),
+                        millis_to_timestamp(start + 1000),
+                        format!("cue {index}"),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn starts() -> Vec<i64> {
+        (0..60).map(|index| 5000 + index * 10_000).collect()
+    }
+
+    #[test]
+    fn drift_recommends_a_shift_for_a_constant_offset() {
+        let report = drift(
+            &subtitles(starts()),
+            &subtitles(starts().into_iter().map(|start| start + 1500)),
+        )
+        .unwrap();
+        assert_eq!(report.matched, 60);
+        assert!(
+            matches!(report.correction, Correction::SHIFT { seconds } if seconds == 1.5),
+            "{0:?}",
+            report.correction
+        );
+    }
+
+    #[test]
+    fn drift_recommends_a_scale_for_a_linear_drift() {
+        // the other subtitles are 0.1% slower, and start 200ms later
+        let other = starts()
+            .into_iter()
+            .map(|start| (start as f64 * 1.001).round() as i64 + 200);
+        let report = drift(&subtitles(starts()), &subtitles(other)).unwrap();
+        let Correction::SCALE {
+            factor,
+            shift_seconds,
+        } = report.correction
+        else {
+            panic!("expected a scale, got {0:?}", report.correction);
+        };
+        assert!((factor - 1.001).abs() < 1e-6, "{factor}");
+        assert!((shift_seconds - 0.2).abs() < 0.005, "{shift_seconds}");
+    }
+
+    #[test]
+    fn drift_ignores_an_outlier_cue() {
+        let other = starts()
+            .into_iter()
+            .enumerate()
+            .map(|(index, start)| start + if index == 10 { 5500 } else { 1500 });
+        let report = drift(&subtitles(starts()), &subtitles(other)).unwrap();
+        assert_eq!(report.matched, 59);
+        assert!(report.slope_ms_per_second.abs() < 1e-9);
+        assert!(
+            matches!(report.correction, Correction::SHIFT { seconds } if seconds == 1.5),
+            "{0:?}",
+            report.correction
+        );
+    }
+}
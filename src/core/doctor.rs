@@ -0,0 +1,89 @@
+// this file contains checks that the environment subbub runs in is set up correctly
+
+use anyhow::{anyhow, Result};
+use std::io::ErrorKind;
+
+use crate::core::data::{pretty_cmd, tool_command, TMP_DIRECTORY};
+
+// an external tool that subbub invokes, and the argument that makes it print its version
+pub struct Tool {
+    pub name: &'static str,
+    pub version_arg: &'static str,
+    // optional tools are only needed for some inputs (e.g., image-based subtitles)
+    pub required: bool,
+}
+
+pub const TOOLS: [Tool; 7] = [
+    Tool {
+        name: "ffmpeg",
+        version_arg: "-version",
+        required: true,
+    },
+    Tool {
+        name: "ffprobe",
+        version_arg: "-version",
+        required: true,
+    },
+    Tool {
+        name: "ffsubsync",
+        version_arg: "--version",
+        required: true,
+    },
+    Tool {
+        name: "mkvmerge",
+        version_arg: "--version",
+        required: true,
+    },
+    Tool {
+        name: "mkvpropedit",
+        version_arg: "--version",
+        required: false,
+    },
+    Tool {
+        name: "pgsrip",
+        version_arg: "--version",
+        required: false,
+    },
+    Tool {
+        name: "vobsub2srt",
+        version_arg: "--help",
+        required: false,
+    },
+];
+
+// checks that the given tool can be run, returning the first line it printed (usually its version)
+// the binary is resolved the same way as when the tool is used, so overrides such as SUBBUB_FFMPEG are respected
+pub fn check_tool(tool: &Tool) -> Result<String> {
+    let mut command = tool_command(tool.name);
+    command.arg(tool.version_arg);
+    log::debug!("{0}", pretty_cmd(&command));
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(anyhow!("not found, is it installed and in PATH?"))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    // some tools print their version to stderr, and some exit unsuccessfully when asked for it, so only presence is checked
+    let text = if output.stdout.is_empty() {
+        String::from_utf8_lossy(&output.stderr).to_string()
+    } else {
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    Ok(text
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty())
+        .unwrap_or("unknown version")
+        .to_string())
+}
+
+// checks that files can be created in the temporary directory
+pub fn check_tmp_directory() -> Result<String> {
+    let tmp_dir = TMP_DIRECTORY.get().unwrap();
+    std::fs::create_dir_all(tmp_dir)?;
+    let test_file = tmp_dir.join("doctor_write_test");
+    std::fs::write(&test_file, b"subbub")?;
+    std::fs::remove_file(&test_file)?;
+    Ok(format!("{tmp_dir:#?} is writable"))
+}
@@ -0,0 +1,87 @@
+// this file contains functions for exporting subtitles to other formats, e.g., plain text
+
+use anyhow::Result;
+use srtlib::Subtitles;
+
+use crate::core::modify::clean_subtitles;
+use crate::core::time::timestamp_to_millis;
+
+// a pause between subtitles longer than this starts a new paragraph in a transcript
+const PARAGRAPH_GAP_MS: i64 = 2000;
+
+// the options of to_transcript, each of which is opt-in
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TranscriptOptions {
+    // prefixes each line with the time its first subtitle starts, e.g., [01:23]
+    pub timestamps: bool,
+    // continues a line with the next subtitle until a sentence ends, rather than placing each subtitle on its own line
+    pub join_sentences: bool,
+}
+
+// converts the subtitles to a plain text transcript, without timing, html, or bracketed information (e.g., [music])
+// each subtitle's lines are joined into one, and pauses between subtitles separate paragraphs with a blank line
+pub fn to_transcript(subtitles: &Subtitles, options: &TranscriptOptions) -> Result<String> {
+    let mut cleaned = subtitles.clone();
+    clean_subtitles(&mut cleaned)?;
+    let mut cues = cleaned.to_vec();
+    cues.sort_by_key(|s| s.start_time);
+
+    let mut paragraphs: Vec<Vec<String>> = vec![];
+    let mut previous_end: Option<i64> = None;
+    // whether the last line ends in the middle of a sentence, and may be continued
+    let mut open_sentence = false;
+    for cue in cues {
+        let text = cue
+            .text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if text.is_empty() {
+            continue;
+        }
+        let start = timestamp_to_millis(&cue.start_time);
+        let new_paragraph = previous_end.is_none_or(|end| start - end > PARAGRAPH_GAP_MS);
+        previous_end = Some(timestamp_to_millis(&cue.end_time));
+        if new_paragraph {
+            paragraphs.push(vec![]);
+            open_sentence = false;
+        }
+        let paragraph = paragraphs.last_mut().unwrap();
+
+        if options.join_sentences && open_sentence {
+            let line = paragraph.last_mut().unwrap();
+            line.push(' ');
+            line.push_str(&text);
+        } else if options.timestamps {
+            paragraph.push(format!("[{0}] {text}", transcript_timestamp(start)));
+        } else {
+            paragraph.push(text);
+        }
+        open_sentence = !ends_sentence(paragraph.last().unwrap());
+    }
+
+    let transcript = paragraphs
+        .into_iter()
+        .map(|lines| lines.join("\n"))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    Ok(format!("{transcript}\n"))
+}
+
+fn ends_sentence(text: &str) -> bool {
+    text.trim_end_matches(['"', '\'', '”', '’', ')', '♪', ' '])
+        .ends_with(['.', '!', '?', '…', '。', '！', '？'])
+}
+
+// formats the time as [mm:ss], or [hh:mm:ss] from an hour onwards
+fn transcript_timestamp(millis: i64) -> String {
+    let seconds = millis / 1000;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours:02}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes:02}:{seconds:02}")
+    }
+}
@@ -1,25 +1,65 @@
 // functions that invoke ffmpeg
 use anyhow::{anyhow, Context, Result};
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use srtlib::Subtitles;
 use std::{
+    collections::HashMap,
+    io::ErrorKind,
     path::{Path, PathBuf},
     process::Command,
     str::FromStr,
+    time::Duration,
 };
 
-use crate::core::data::{pretty_cmd, pretty_output, TMP_DIRECTORY};
+use crate::core::data::{default_tmp_directory, pretty_cmd, pretty_output, tool_command};
 
-use super::data::hash_string;
+use super::ass::fix_ass_line_breaks;
+use super::data::{
+    hash_path, sniff_subtitles_format, SubtitlesFormat, SubtitlesTrack, TrackSelector,
+};
+
+// subtitle codecs that store images rather than text, and must be OCR'd to produce srt
+pub const BITMAP_SUBTITLE_CODECS: [&str; 4] =
+    ["hdmv_pgs_subtitle", "dvd_subtitle", "dvb_subtitle", "xsub"];
 
+// constructs an ffmpeg command that never waits for input
+// ffmpeg asks before replacing an existing file, and waits on stdin for an answer that never comes
+// outputs have already been checked against --overwrite by this point, and temporary files may be left over from a previous run
+// (e.g., with --keep-tmp-files), so existing files are always replaced
+fn ffmpeg_command() -> Command {
+    let mut command = tool_command("ffmpeg");
+    command
+        .arg("-y") // replace existing files without asking
+        .arg("-nostdin"); // never read from stdin, even if ffmpeg would otherwise ask
+    command
+}
+
+// as extract_subtitles_in, using the default temporary directory
 pub fn extract_subtitles(video_file: &Path, subtitle_track: u32) -> Result<Subtitles> {
-    let tmp_file = TMP_DIRECTORY.get().unwrap().join(format!(
+    extract_subtitles_in(default_tmp_directory(), video_file, subtitle_track)
+}
+
+// extracts the given subtitle track of the video, writing intermediate files to the given temporary directory
+// image-based tracks are converted to text with OCR
+pub fn extract_subtitles_in(
+    tmp_dir: &Path,
+    video_file: &Path,
+    subtitle_track: u32,
+) -> Result<Subtitles> {
+    let codec = subtitle_codec(video_file, subtitle_track)?;
+    if BITMAP_SUBTITLE_CODECS.contains(&codec.as_str()) {
+        log::info!("subtitle track {video_file:#?}:{subtitle_track} is image-based ({codec}), attempting OCR");
+        return ocr_subtitles(tmp_dir, video_file, subtitle_track, &codec);
+    }
+
+    let tmp_file = tmp_dir.join(format!(
         "ext_{0}_{1}.srt",
-        hash_string(&video_file.file_stem().unwrap().to_string_lossy()),
+        hash_path(video_file),
         subtitle_track
     ));
 
-    let mut command = Command::new("ffmpeg");
+    let mut command = ffmpeg_command();
     command
         .arg("-i") // select the input video
         .arg(video_file.as_os_str())
@@ -42,38 +82,115 @@ pub fn extract_subtitles(video_file: &Path, subtitle_track: u32) -> Result<Subti
     log::trace!("{0}", pretty_output(&output));
 
     log::debug!("reading from temporary file {tmp_file:#?} extracted from video {video_file:#?}:{subtitle_track}");
-    let subs = read_subtitles_file(&tmp_file)?;
+    let mut subs = read_subtitles_file_in(tmp_dir, &tmp_file)?;
+    if codec == "ass" || codec == "ssa" {
+        fix_ass_line_breaks(&mut subs);
+    }
 
     Ok(subs)
 }
 
-pub fn add_subtitles_track(
+// adds the given subtitles files to the video as new tracks, in the order given, as mkvmerge::add_subtitles_tracks does
+// unlike mkvmerge, ffmpeg can also write mp4 files, whose subtitles are converted to mov_text
+// when replace_language is set, existing subtitle tracks with the same language code as a new track are removed from the video
+// the new tracks are placed either before or after the video's existing subtitle tracks, after its other tracks
+// returns the number of subtitles tracks the output should contain
+pub fn add_subtitles_tracks(
     video_file: &Path,
-    subtitles_file: &Path,
-    track_number: u32,
-    language_code: &str,
+    new_tracks: &[SubtitlesTrack],
+    replace_language: bool,
+    new_tracks_first: bool,
     output_path: &Path,
-) -> Result<()> {
-    let mut command = Command::new("ffmpeg");
+) -> Result<usize> {
+    let replaced_languages = if replace_language {
+        new_tracks
+            .iter()
+            .filter_map(|t| t.metadata.language.as_deref())
+            .collect()
+    } else {
+        vec![]
+    };
+    let existing_languages = subtitle_track_languages(video_file)?;
+    let kept_subtitles = existing_languages
+        .iter()
+        .positions(|language| {
+            !language
+                .as_deref()
+                .is_some_and(|language| replaced_languages.contains(&language))
+        })
+        .collect_vec();
+    if kept_subtitles.len() < existing_languages.len() {
+        log::info!("removing existing {replaced_languages:?} subtitle tracks from {video_file:#?}");
+    }
+
+    // the video is input 0 and each new track is its own input, so a new track's stream is <input index>:0
+    let existing_subtitles = kept_subtitles.iter().map(|index| format!("0:s:{index}"));
+    let added_subtitles = (1..=new_tracks.len()).map(|input_index| format!("{input_index}:0"));
+    let subtitle_maps = if new_tracks_first {
+        added_subtitles.chain(existing_subtitles).collect_vec()
+    } else {
+        existing_subtitles.chain(added_subtitles).collect_vec()
+    };
+    // mp4 files can only contain mov_text subtitles, which drop any styling or positioning (e.g., {\an8})
+    let is_mp4 = output_path.extension().is_some_and(|ext| {
+        ["mp4", "m4v", "mov"].contains(&ext.to_string_lossy().to_lowercase().as_str())
+    });
+    let subtitle_codec = if is_mp4 { "mov_text" } else { "srt" };
+    // the index among the output's subtitles streams of the first new track
+    let first_new_track = if new_tracks_first {
+        0
+    } else {
+        kept_subtitles.len()
+    };
+
+    let mut command = ffmpeg_command();
+    command.arg("-i").arg(video_file); // input the video file
+    for track in new_tracks {
+        command.arg("-i").arg(track.path); // input the subtitles file
+    }
     command
-        .arg("-i") // input the video file
-        .arg(video_file)
-        .arg("-i") // input the subtitles file
-        .arg(subtitles_file)
-        .arg("-map") // map both inputs to the output file
+        .arg("-map") // map all of the video's tracks except its subtitles, which are mapped in order below
         .arg("0")
         .arg("-map")
-        .arg("1")
+        .arg("-0:s");
+    if is_mp4 {
+        // mp4 files can't contain attachments (e.g., the fonts that most fansubbed mkvs carry), so they're left out
+        command.arg("-map").arg("-0:t");
+    }
+    for map in subtitle_maps {
+        command.arg("-map").arg(map);
+    }
+    command
         .arg("-c") // do not re-encode the video
         .arg("copy")
         .arg("-c:s") // set subtitle format
-        .arg("srt")
+        .arg(subtitle_codec)
         .arg("-max_interleave_delta") // workaround for a known issue with mkv + subtitles with large gaps, see https://old.reddit.com/r/ffmpeg/comments/1do9azh/difficulty_adding_subtitles_track_to_video/la8bnh8/
-        .arg("0")
-        .arg(format!("-metadata:s:s:{track_number}")) // set the track number (and also specify that they're subtitles)
-        .arg(format!("language={language_code}")) // add the language code
-        .arg(output_path) // finally, the output path of the newly created video file
-        ;
+        .arg("0");
+    for (i, track) in new_tracks.iter().enumerate() {
+        let track_number = first_new_track + i;
+        let metadata = &track.metadata;
+        // dispositions are flags joined with +, or 0 to clear any that the track would otherwise inherit
+        let disposition = [(metadata.default, "default"), (metadata.forced, "forced")]
+            .into_iter()
+            .filter_map(|(set, flag)| set.then_some(flag))
+            .join("+");
+        command
+            .arg(format!("-metadata:s:s:{track_number}")) // set the track's name
+            .arg(format!("title={0}", metadata.name))
+            .arg(format!("-disposition:s:{track_number}")) // set the track's default and forced flags
+            .arg(if disposition.is_empty() {
+                "0"
+            } else {
+                &disposition
+            });
+        if let Some(language_code) = &metadata.language {
+            command
+                .arg(format!("-metadata:s:s:{track_number}")) // set the track number (and also specify that they're subtitles)
+                .arg(format!("language={language_code}")); // add the language code
+        }
+    }
+    command.arg(output_path); // finally, the output path of the newly created video file
 
     log::debug!("{0}", pretty_cmd(&command));
     let output = command.output()?;
@@ -87,16 +204,184 @@ pub fn add_subtitles_track(
     }
     log::trace!("{0}", pretty_output(&output));
 
-    Ok(())
+    Ok(kept_subtitles.len() + new_tracks.len())
 }
 
-pub fn read_subtitles_file(path: &Path) -> Result<Subtitles> {
-    let tmp_file = TMP_DIRECTORY.get().unwrap().join(format!(
-        "con_{0}.srt",
-        hash_string(&path.file_stem().unwrap().to_string_lossy())
+// returns the codec name (as reported by ffprobe) of the given subtitle track
+pub fn subtitle_codec(video_file: &Path, subtitle_track: u32) -> Result<String> {
+    let mut command = tool_command("ffprobe");
+    command
+        .arg("-v")
+        .arg("error")
+        .arg("-select_streams")
+        .arg(format!("s:{subtitle_track}"))
+        .arg("-show_entries")
+        .arg("stream=codec_name")
+        .arg("-of")
+        .arg("csv=p=0")
+        .arg(video_file.as_os_str());
+    log::debug!("{0}", pretty_cmd(&command));
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "command was not successfully executed:\n{0}\n{1}",
+            pretty_cmd(&command),
+            pretty_output(&output)
+        ));
+    }
+    log::trace!("{0}", pretty_output(&output));
+    let stdout =
+        String::from_utf8(output.stdout.clone()).context("could not parse stdout to utf8")?;
+    let codec = stdout.trim().to_string();
+    if codec.is_empty() {
+        return Err(anyhow!(
+            "video {video_file:#?} does not have a subtitle track {subtitle_track}"
+        ));
+    }
+    Ok(codec)
+}
+
+// converts an image-based subtitle track to text using OCR
+// the track is first copied out of the video unchanged, then handed to the OCR tool
+fn ocr_subtitles(
+    tmp_dir: &Path,
+    video_file: &Path,
+    subtitle_track: u32,
+    codec: &str,
+) -> Result<Subtitles> {
+    if codec != "hdmv_pgs_subtitle" {
+        return Err(anyhow!(
+            "OCR of {codec} subtitle tracks is not supported, only PGS (hdmv_pgs_subtitle) tracks can be converted to text"
+        ));
+    }
+
+    // the OCR tool writes its output next to its input, so give each job its own directory
+    let ocr_dir = tmp_dir.join(format!(
+        "ocr_{0}_{1}",
+        hash_path(video_file),
+        subtitle_track
     ));
+    std::fs::create_dir_all(&ocr_dir)?;
+    let sup_file = ocr_dir.join("track.sup");
+
+    let mut command = ffmpeg_command();
+    command
+        .arg("-i") // select the input video
+        .arg(video_file.as_os_str())
+        .arg("-map") // select the subtitle track
+        .arg(format!("0:s:{subtitle_track}"))
+        .arg("-c:s") // copy the bitmap subtitles as-is
+        .arg("copy")
+        .arg(sup_file.as_os_str()) // select the output file
+        ;
+    log::debug!("{0}", pretty_cmd(&command));
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "command was not successfully executed:\n{0}\n{1}",
+            pretty_cmd(&command),
+            pretty_output(&output)
+        ));
+    }
+    log::trace!("{0}", pretty_output(&output));
+
+    let mut command = tool_command("pgsrip");
+    command.arg(sup_file.as_os_str());
+    log::debug!("{0}", pretty_cmd(&command));
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(anyhow!(
+                "subtitle track {video_file:#?}:{subtitle_track} is image-based and requires OCR, but pgsrip was not found in PATH\nsee https://github.com/ratoaq2/pgsrip for installation instructions"
+            ));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if !output.status.success() {
+        return Err(anyhow!(
+            "command was not successfully executed:\n{0}\n{1}",
+            pretty_cmd(&command),
+            pretty_output(&output)
+        ));
+    }
+    log::trace!("{0}", pretty_output(&output));
+
+    // pgsrip may add a language code to the output filename, so look for any srt it produced
+    let srt_file = ocr_dir
+        .read_dir()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .find(|path| path.extension().is_some_and(|ext| ext == "srt"))
+        .context("OCR tool did not produce an srt file")?;
+    log::debug!(
+        "reading OCR output {srt_file:#?} extracted from video {video_file:#?}:{subtitle_track}"
+    );
+    let subs = Subtitles::parse_from_file(srt_file, None)?;
+
+    Ok(subs)
+}
+
+// converts a VobSub (.idx + .sub pair) to text using OCR
+fn ocr_vobsub(tmp_dir: &Path, idx_file: &Path) -> Result<Subtitles> {
+    let sub_file = idx_file.with_extension("sub");
+    if !sub_file.is_file() {
+        return Err(anyhow!(
+            "VobSub index {idx_file:#?} has no accompanying {sub_file:#?}, both files are required"
+        ));
+    }
+
+    // the OCR tool writes its output next to its input, so copy the pair into their own directory
+    let ocr_dir = tmp_dir.join(format!("ocr_{0}", hash_path(idx_file)));
+    std::fs::create_dir_all(&ocr_dir)?;
+    let basename = ocr_dir.join("track");
+    std::fs::copy(idx_file, basename.with_extension("idx"))?;
+    std::fs::copy(&sub_file, basename.with_extension("sub"))?;
+
+    let mut command = tool_command("vobsub2srt");
+    command.arg(basename.as_os_str()); // vobsub2srt takes the path without an extension
+    log::debug!("{0}", pretty_cmd(&command));
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(anyhow!(
+                "VobSub subtitles {idx_file:#?} are image-based and require OCR, but vobsub2srt was not found in PATH\nsee https://github.com/ruediger/VobSub2SRT for installation instructions"
+            ));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if !output.status.success() {
+        return Err(anyhow!(
+            "command was not successfully executed:\n{0}\n{1}",
+            pretty_cmd(&command),
+            pretty_output(&output)
+        ));
+    }
+    log::trace!("{0}", pretty_output(&output));
+
+    let srt_file = basename.with_extension("srt");
+    log::debug!("reading OCR output {srt_file:#?} converted from {idx_file:#?}");
+    let subs = Subtitles::parse_from_file(srt_file, None)?;
 
-    let mut command = Command::new("ffmpeg");
+    Ok(subs)
+}
+
+// as read_subtitles_file_in, using the default temporary directory
+pub fn read_subtitles_file(path: &Path) -> Result<Subtitles> {
+    read_subtitles_file_in(default_tmp_directory(), path)
+}
+
+// reads a subtitles file of any format ffmpeg supports, converting it to srt in the given temporary directory
+pub fn read_subtitles_file_in(tmp_dir: &Path, path: &Path) -> Result<Subtitles> {
+    if path
+        .extension()
+        .is_some_and(|ext| ext.to_string_lossy().to_lowercase() == "idx")
+    {
+        // VobSub subtitles are images, so they can't be converted by ffmpeg
+        return ocr_vobsub(tmp_dir, path);
+    }
+
+    let tmp_file = tmp_dir.join(format!("con_{0}.srt", hash_path(path)));
+
+    let mut command = ffmpeg_command();
     command
         .arg("-i") // select input subtitles file
         .arg(path.as_os_str())
@@ -117,13 +402,112 @@ pub fn read_subtitles_file(path: &Path) -> Result<Subtitles> {
     log::trace!("{0}", pretty_output(&output));
 
     log::debug!("reading from temporary file {tmp_file:#?} converted from {path:#?}");
-    let subs = Subtitles::parse_from_file(tmp_file, None)?;
+    let mut subs = Subtitles::parse_from_file(tmp_file, None)?;
+    // the format is sniffed from the file's contents, since ass files are sometimes misnamed (e.g., as .srt)
+    // the extension is only used if the contents can't be read or recognized
+    let is_ass = match std::fs::read(path)
+        .ok()
+        .and_then(|bytes| sniff_subtitles_format(&bytes))
+    {
+        Some(format) => matches!(format, SubtitlesFormat::ASS | SubtitlesFormat::SSA),
+        None => path.extension().is_some_and(|ext| {
+            ["ass", "ssa"].contains(&ext.to_string_lossy().to_lowercase().as_str())
+        }),
+    };
+    if is_ass {
+        fix_ass_line_breaks(&mut subs);
+    }
 
     Ok(subs)
 }
 
+// resolves the given track selector to the index of a subtitles track in the given video
+// errors if no track, or more than one track, has the selected language
+pub fn resolve_subtitle_track(video_file: &Path, selector: &TrackSelector) -> Result<u32> {
+    let language = match selector {
+        TrackSelector::Index(index) => return Ok(*index),
+        TrackSelector::Language(language) => language,
+    };
+
+    let languages = subtitle_track_languages(video_file)?;
+    let candidates = languages
+        .iter()
+        .enumerate()
+        .filter(|(_, track_language)| {
+            // tracks without a language tag are considered undetermined
+            let track_language = track_language.as_deref().unwrap_or("und");
+            track_language.eq_ignore_ascii_case(language)
+        })
+        .map(|(index, _)| index as u32)
+        .collect_vec();
+
+    match candidates.as_slice() {
+        [index] => {
+            log::debug!("resolved subtitles track {selector} in {video_file:#?} to track {index}");
+            Ok(*index)
+        }
+        [] => Err(anyhow!(
+            "no subtitles track in {video_file:#?} has language {language:?}, the tracks' languages are: {0:?}",
+            languages
+        )),
+        _ => Err(anyhow!(
+            "multiple subtitles tracks in {video_file:#?} have language {language:?}: {candidates:?}\nplease specify the track by index instead"
+        )),
+    }
+}
+
+// returns the language tag of each subtitles track in the given video, in order, as reported by ffprobe
+pub fn subtitle_track_languages(video_file: &Path) -> Result<Vec<Option<String>>> {
+    stream_languages(video_file, "s")
+}
+
+// returns the language tag of each audio track in the given video, in order, as reported by ffprobe
+pub fn audio_track_languages(video_file: &Path) -> Result<Vec<Option<String>>> {
+    stream_languages(video_file, "a")
+}
+
+// stream_type is an ffmpeg stream specifier, e.g., "s" for subtitles or "a" for audio
+fn stream_languages(video_file: &Path, stream_type: &str) -> Result<Vec<Option<String>>> {
+    let mut command = tool_command("ffprobe");
+    command
+        .arg("-v")
+        .arg("error")
+        .arg("-select_streams")
+        .arg(stream_type)
+        .arg("-show_entries")
+        .arg("stream=index:stream_tags=language")
+        .arg("-of")
+        .arg("csv=p=0")
+        .arg(video_file.as_os_str());
+    log::debug!("{0}", pretty_cmd(&command));
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "command was not successfully executed:\n{0}\n{1}",
+            pretty_cmd(&command),
+            pretty_output(&output)
+        ));
+    }
+    log::trace!("{0}", pretty_output(&output));
+    let stdout =
+        String::from_utf8(output.stdout.clone()).context("could not parse stdout to utf8")?;
+    // each line is the stream index, followed by the language if the stream has one, e.g., "2,eng" or "3"
+    let languages = stdout
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.trim()
+                .split_once(',')
+                .map(|(_, language)| language.to_string())
+                .filter(|language| !language.is_empty())
+        })
+        .collect_vec();
+    Ok(languages)
+}
+
+// returns the number of subtitles tracks in the given video, as reported by ffprobe
 pub fn number_of_subtitle_streams(video_file: &Path) -> Result<u32> {
-    let mut command = Command::new("ffprobe");
+    let mut command = tool_command("ffprobe");
     command
         .arg("-v")
         .arg("error")
@@ -146,20 +530,184 @@ pub fn number_of_subtitle_streams(video_file: &Path) -> Result<u32> {
     log::trace!("{0}", pretty_output(&output));
     let stdout =
         String::from_utf8(output.stdout.clone()).context("could not parse stdout to utf8")?;
-    let len = stdout.split('\n').collect_vec().len();
+    // each line is the index of one subtitles stream
+    let len = stdout
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .count();
     Ok(len as u32)
 }
 
-pub fn convert_to_mkv(video_file: &Path) -> Result<PathBuf> {
-    let mut command = Command::new("ffmpeg");
-    let output_file = TMP_DIRECTORY.get().unwrap().join(PathBuf::from_str(
-        format!("{0}.mkv", video_file.file_stem().unwrap().to_string_lossy()).as_str(),
+// returns the duration of the given video, as reported by ffprobe
+pub fn video_duration(video_file: &Path) -> Result<Duration> {
+    let mut command = tool_command("ffprobe");
+    command
+        .arg("-v")
+        .arg("error")
+        .arg("-show_entries")
+        .arg("format=duration")
+        .arg("-of")
+        .arg("csv=p=0")
+        .arg(video_file.as_os_str());
+    log::debug!("{0}", pretty_cmd(&command));
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "command was not successfully executed:\n{0}\n{1}",
+            pretty_cmd(&command),
+            pretty_output(&output)
+        ));
+    }
+    log::trace!("{0}", pretty_output(&output));
+    let stdout =
+        String::from_utf8(output.stdout.clone()).context("could not parse stdout to utf8")?;
+    let seconds: f64 = stdout.trim().parse().with_context(|| {
+        format!(
+            "could not parse duration {0:?} of video {video_file:#?}",
+            stdout.trim()
+        )
+    })?;
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+// a chapter marker in a video
+#[derive(Serialize, Debug, Clone)]
+pub struct Chapter {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub title: Option<String>,
+}
+
+// the subset of `ffprobe -show_chapters` output that we use
+#[derive(Deserialize, Debug)]
+struct ProbedChapters {
+    chapters: Vec<ProbedChapter>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProbedChapter {
+    // times are given in seconds, as strings, e.g., "312.345000"
+    start_time: String,
+    end_time: String,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+// returns the chapters of the given video, as reported by ffprobe
+pub fn extract_chapters(video_file: &Path) -> Result<Vec<Chapter>> {
+    let mut command = tool_command("ffprobe");
+    command
+        .arg("-v")
+        .arg("error")
+        .arg("-show_chapters")
+        .arg("-of")
+        .arg("json")
+        .arg(video_file.as_os_str());
+    log::debug!("{0}", pretty_cmd(&command));
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "command was not successfully executed:\n{0}\n{1}",
+            pretty_cmd(&command),
+            pretty_output(&output)
+        ));
+    }
+    log::trace!("{0}", pretty_output(&output));
+    let probed: ProbedChapters = serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("could not parse chapters of video {video_file:#?}"))?;
+
+    let seconds_to_millis = |seconds: &str| -> Result<i64> {
+        let seconds: f64 = seconds.parse().with_context(|| {
+            format!("could not parse chapter time {seconds:?} of video {video_file:#?}")
+        })?;
+        Ok((seconds * 1000.0).round() as i64)
+    };
+    probed
+        .chapters
+        .into_iter()
+        .map(|chapter| {
+            Ok(Chapter {
+                start_ms: seconds_to_millis(&chapter.start_time)?,
+                end_ms: seconds_to_millis(&chapter.end_time)?,
+                title: chapter.tags.get("title").cloned(),
+            })
+        })
+        .collect()
+}
+
+// which streams of a video are kept when it's converted to mkv
+// by default, every stream is kept
+#[derive(Debug, Clone, Default)]
+pub struct StreamSelection {
+    // leaves out the video's existing subtitle streams
+    pub drop_subtitles: bool,
+    // leaves out attachments, e.g., fonts
+    pub drop_attachments: bool,
+    // when not empty, only the audio streams with these language codes are kept
+    pub audio_languages: Vec<String>,
+}
+
+// as convert_to_mkv_in, using the default temporary directory
+pub fn convert_to_mkv(video_file: &Path, streams: &StreamSelection) -> Result<PathBuf> {
+    convert_to_mkv_in(default_tmp_directory(), video_file, streams)
+}
+
+// copies the selected streams of the given video into an mkv file in the given temporary directory
+pub fn convert_to_mkv_in(
+    tmp_dir: &Path,
+    video_file: &Path,
+    streams: &StreamSelection,
+) -> Result<PathBuf> {
+    let mut command = ffmpeg_command();
+    let output_file = tmp_dir.join(PathBuf::from_str(
+        format!(
+            "{0}_{1}.mkv",
+            video_file.file_stem().unwrap().to_string_lossy(),
+            hash_path(video_file)
+        )
+        .as_str(),
     )?);
     command
         .arg("-i") // select input video file
         .arg(video_file.as_os_str())
         .arg("-map") // select all streams
-        .arg("0")
+        .arg("0");
+    if streams.drop_subtitles {
+        command
+            .arg("-map") // deselect the subtitle streams
+            .arg("-0:s");
+    }
+    if streams.drop_attachments {
+        command
+            .arg("-map") // deselect the attachments
+            .arg("-0:t");
+    }
+    if !streams.audio_languages.is_empty() {
+        let kept_audio = audio_track_languages(video_file)?
+            .into_iter()
+            .enumerate()
+            .filter(|(_, language)| {
+                language
+                    .as_ref()
+                    .is_some_and(|language| streams.audio_languages.contains(language))
+            })
+            .map(|(index, _)| index)
+            .collect_vec();
+        // a video without audio is almost certainly a mistake, e.g., a typo in the language code
+        if kept_audio.is_empty() {
+            return Err(anyhow!(
+                "{video_file:#?} has no audio tracks with the language(s) {0}",
+                streams.audio_languages.join(", ")
+            ));
+        }
+        command
+            .arg("-map") // deselect the audio streams, then select the kept ones again
+            .arg("-0:a");
+        for index in kept_audio {
+            command.arg("-map").arg(format!("0:a:{index}"));
+        }
+    }
+    command
         .arg("-c") // copy streams
         .arg("copy")
         .arg(&output_file) // output file
@@ -177,3 +725,41 @@ pub fn convert_to_mkv(video_file: &Path) -> Result<PathBuf> {
 
     Ok(output_file)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMI: &str = "<SAMI>
+<HEAD>
+<STYLE TYPE=\"text/css\"><!--
+.ENCC {Name: English; lang: en-US;}
+.FRCC {Name: French; lang: fr-FR;}
+--></STYLE>
+</HEAD>
+<BODY>
+<SYNC Start=1000><P Class=ENCC>Hello there</P><P Class=FRCC>Bonjour</P>
+<SYNC Start=2000><P Class=ENCC>&nbsp;</P>
+</BODY>
+</SAMI>
+";
+
+    #[test]
+    fn sami_subtitles_keep_their_primary_language_text() {
+        if tool_command("ffmpeg").arg("-version").output().is_err() {
+            eprintln!("skipping, ffmpeg is not available");
+            return;
+        }
+        let tmp_dir = std::env::temp_dir().join("subbub_sami_test");
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+        let path = tmp_dir.join("multilingual.smi");
+        std::fs::write(&path, SAMI).unwrap();
+
+        let subtitles = read_subtitles_file_in(&tmp_dir, &path).unwrap().to_vec();
+        assert!(!subtitles.is_empty());
+        let first = &subtitles[0];
+        assert_eq!(first.start_time.to_string(), "00:00:01,000");
+        assert!(first.text.contains("Hello there"), "{0:?}", first.text);
+        assert!(first.text.contains("Bonjour"), "{0:?}", first.text);
+    }
+}
@@ -0,0 +1,239 @@
+// this file contains functions for attaching fonts to videos, so that players can render ass subtitles with the fonts their styles use
+
+use anyhow::{anyhow, Context, Result};
+use encoding_rs::UTF_8;
+use std::path::{Path, PathBuf};
+
+use crate::core::ass::referenced_fonts;
+use crate::core::data::{detect_encoding, sniff_subtitles_format, SubtitlesFormat};
+
+pub const FONT_FILE_EXTENSIONS: [&str; 4] = ["ttf", "otf", "ttc", "otc"];
+
+// the font files in a directory, to be attached to videos, and the names of the fonts they contain
+pub struct Fonts {
+    pub directory: PathBuf,
+    pub files: Vec<PathBuf>,
+    // lowercase, since ass styles name fonts case-insensitively
+    names: Vec<String>,
+}
+
+impl Fonts {
+    // reads the fonts in the given directory (but not its subdirectories)
+    pub fn read(directory: &Path) -> Result<Fonts> {
+        let mut files = std::fs::read_dir(directory)
+            .with_context(|| format!("could not read fonts directory {directory:#?}"))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && mime_type(path).is_some())
+            .collect::<Vec<_>>();
+        if files.is_empty() {
+            return Err(anyhow!(
+                "fonts directory {directory:#?} contains no font files ({0})",
+                FONT_FILE_EXTENSIONS.join(", ")
+            ));
+        }
+        files.sort();
+
+        let mut names = vec![];
+        for file in &files {
+            let bytes = std::fs::read(file)
+                .with_context(|| format!("could not read font file {file:#?}"))?;
+            let mut file_names = font_names(&bytes);
+            if file_names.is_empty() {
+                log::warn!("could not read the name of the font in {file:#?}, matching it by its filename instead");
+            }
+            // fonts are often named after the font they contain, so the filename is matched too
+            if let Some(stem) = file.file_stem() {
+                file_names.push(stem.to_string_lossy().to_string());
+            }
+            names.extend(
+                file_names
+                    .into_iter()
+                    .map(|name| name.trim().to_lowercase()),
+            );
+        }
+
+        Ok(Fonts {
+            directory: directory.to_path_buf(),
+            files,
+            names,
+        })
+    }
+
+    // warns about each font used by the styles of the given ass/ssa subtitles file that isn't among these fonts
+    // players display those styles with a fallback font, unless the font is installed on the viewer's system
+    // other subtitles formats have no styles, so they're never missing fonts
+    pub fn warn_missing(&self, subtitles_file: &Path) -> Result<()> {
+        let bytes = std::fs::read(subtitles_file)
+            .with_context(|| format!("could not read subtitles file {subtitles_file:#?}"))?;
+        if !matches!(
+            sniff_subtitles_format(&bytes),
+            Some(SubtitlesFormat::ASS | SubtitlesFormat::SSA)
+        ) {
+            return Ok(());
+        }
+        let (text, _) = detect_encoding(&bytes)
+            .unwrap_or(UTF_8)
+            .decode_with_bom_removal(&bytes);
+        for font in referenced_fonts(&text) {
+            if !self.names.contains(&font.to_lowercase()) {
+                log::warn!(
+                    "{subtitles_file:#?} uses the font {font:?}, which isn't in {0:#?}; players without it will use a fallback font",
+                    self.directory
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+// the mime type that matroska players expect for the given font file, or None if it isn't a font file
+pub fn mime_type(font_file: &Path) -> Option<&'static str> {
+    let extension = font_file.extension()?.to_string_lossy().to_lowercase();
+    match extension.as_str() {
+        "ttf" | "ttc" => Some("application/x-truetype-font"),
+        "otf" | "otc" => Some("application/vnd.ms-opentype"),
+        _ => None,
+    }
+}
+
+// the names recorded in the name table of a truetype/opentype font file: its family, full, and typographic family names
+// collections (.ttc/.otc) contain several fonts, whose names are all returned
+// font files that can't be parsed have no names
+// font file specification: https://learn.microsoft.com/en-us/typography/opentype/spec/otff
+fn font_names(bytes: &[u8]) -> Vec<String> {
+    let font_offsets = if bytes.starts_with(b"ttcf") {
+        let count = read_u32(bytes, 8).unwrap_or(0) as usize;
+        (0..count)
+            .filter_map(|index| read_u32(bytes, 12 + 4 * index))
+            .map(|offset| offset as usize)
+            .collect()
+    } else {
+        vec![0]
+    };
+    font_offsets
+        .into_iter()
+        .flat_map(|offset| name_table_names(bytes, offset).unwrap_or_default())
+        .collect()
+}
+
+fn name_table_names(bytes: &[u8], font_offset: usize) -> Option<Vec<String>> {
+    // the table directory follows the font's 12 byte header, with a 16 byte record for each table
+    let table_count = read_u16(bytes, font_offset + 4)? as usize;
+    let name_record = (0..table_count)
+        .map(|index| font_offset + 12 + 16 * index)
+        .find(|record| bytes.get(*record..*record + 4) == Some(b"name".as_slice()))?;
+    let table = read_u32(bytes, name_record + 8)? as usize;
+
+    let name_count = read_u16(bytes, table + 2)? as usize;
+    let storage = table + read_u16(bytes, table + 4)? as usize;
+    let mut names = vec![];
+    for index in 0..name_count {
+        let record = table + 6 + 12 * index;
+        let platform = read_u16(bytes, record)?;
+        // 1 is the family name, 4 is the full name, and 16 is the typographic family name
+        if ![1, 4, 16].contains(&read_u16(bytes, record + 6)?) {
+            continue;
+        }
+        let length = read_u16(bytes, record + 8)? as usize;
+        let start = storage + read_u16(bytes, record + 10)? as usize;
+        let raw = bytes.get(start..start + length)?;
+        let name = match platform {
+            // unicode and windows names are utf-16be
+            0 | 3 => String::from_utf16_lossy(
+                &raw.chunks_exact(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                    .collect::<Vec<_>>(),
+            ),
+            // macintosh names are mac roman, which matches ascii for the characters font names use
+            _ => String::from_utf8_lossy(raw).to_string(),
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    Some(names)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a font that contains only a name table with the given (platform, name id, name) records, to be placed at font_offset in its file
+    fn font(font_offset: usize, records: &[(u16, u16, &str)]) -> Vec<u8> {
+        let encoded = records
+            .iter()
+            .map(|(platform, _, name)| match platform {
+                0 | 3 => name.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+                _ => name.as_bytes().to_vec(),
+            })
+            .collect::<Vec<Vec<u8>>>();
+        let table = font_offset + 12 + 16;
+
+        let mut bytes = vec![0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        bytes.extend(b"name");
+        bytes.extend([0; 4]);
+        bytes.extend((table as u32).to_be_bytes());
+        bytes.extend([0; 4]);
+
+        bytes.extend(0u16.to_be_bytes());
+        bytes.extend((records.len() as u16).to_be_bytes());
+        bytes.extend((6 + 12 * records.len() as u16).to_be_bytes());
+        let mut offset = 0;
+        for ((platform, name_id, _), name) in records.iter().zip(&encoded) {
+            for field in [*platform, 0, 0, *name_id, name.len() as u16, offset] {
+                bytes.extend(field.to_be_bytes());
+            }
+            offset += name.len() as u16;
+        }
+        bytes.extend(encoded.concat());
+        bytes
+    }
+
+    #[test]
+    fn font_names_reads_family_and_full_names() {
+        let bytes = font(
+            0,
+            &[
+                (3, 1, "Noto Sans"),
+                (3, 2, "Bold"),
+                (3, 4, "Noto Sans Bold"),
+                (1, 1, "Noto Sans"),
+                (1, 16, "Noto"),
+            ],
+        );
+        assert_eq!(font_names(&bytes), ["Noto Sans", "Noto Sans Bold", "Noto"]);
+    }
+
+    #[test]
+    fn font_names_reads_every_font_in_a_collection() {
+        let mut bytes = b"ttcf".to_vec();
+        bytes.extend([0, 1, 0, 0]);
+        bytes.extend(2u32.to_be_bytes());
+        let first = font(20, &[(3, 1, "MS Gothic")]);
+        let second_offset = 20 + first.len();
+        bytes.extend(20u32.to_be_bytes());
+        bytes.extend((second_offset as u32).to_be_bytes());
+        bytes.extend(first);
+        bytes.extend(font(second_offset, &[(3, 1, "MS PGothic")]));
+        assert_eq!(font_names(&bytes), ["MS Gothic", "MS PGothic"]);
+    }
+
+    #[test]
+    fn font_names_is_empty_for_files_that_arent_fonts() {
+        assert!(font_names(b"").is_empty());
+        assert!(font_names(b"not a font at all").is_empty());
+        assert!(font_names(b"ttcf\0\x01\0\0\0\0\0\x05").is_empty());
+    }
+}
@@ -0,0 +1,27 @@
+// this file contains functions for detecting the language of subtitles from their text
+
+use srtlib::Subtitles;
+use whatlang::Detector;
+
+use crate::core::modify::strip_html_string;
+
+// below this many characters of text, detection is too unreliable to be useful
+const MIN_TEXT_LENGTH: usize = 100;
+
+// detects the language of the given subtitles from the text of their cues
+// returns the ISO 639-3 code of the language (e.g., "eng"), or None if the text is too short or the language couldn't be reliably detected
+pub fn detect(subtitles: &Subtitles) -> Option<String> {
+    let text = subtitles
+        .into_iter()
+        .map(|subtitle| strip_html_string(&subtitle.text))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if text.chars().filter(|c| c.is_alphabetic()).count() < MIN_TEXT_LENGTH {
+        return None;
+    }
+    let info = Detector::new().detect(&text)?;
+    if !info.is_reliable() {
+        return None;
+    }
+    Some(info.lang().code().to_string())
+}
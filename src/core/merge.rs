@@ -1,55 +1,298 @@
 use anyhow::Result;
 use srtlib::{Subtitle, Subtitles};
 
-pub fn merge(primary: &Subtitles, secondary: &Subtitles) -> Result<Subtitles> {
-    // TODO: check for existing {\an8}, etc and ensure that subtitles do not overlap
+use itertools::Itertools;
 
-    let mut merged = Subtitles::new();
-    for subtitle in primary.into_iter() {
-        merged.push(subtitle.clone());
+use crate::core::data::{Alignment, MergeStyle};
+use crate::core::modify::renumber;
+use crate::core::time::{millis_to_timestamp, timestamp_to_millis};
+
+// combines primary and secondary subtitles, displaying the primary subtitles at the bottom and the secondary subtitles at the top
+// or, if stacked, displaying the primary line above the secondary line
+pub fn merge(primary: &Subtitles, secondary: &Subtitles, style: MergeStyle) -> Result<Subtitles> {
+    merge_many(
+        vec![(primary, Alignment::BOTTOM), (secondary, Alignment::TOP)],
+        style,
+    )
+}
+
+// combines any number of subtitle tracks in the given style
+// with the top-bottom style, each track is displayed at its given alignment
+// with the stacked style, alignments are ignored, and the text of the tracks is combined in the order given
+pub fn merge_many(tracks: Vec<(&Subtitles, Alignment)>, style: MergeStyle) -> Result<Subtitles> {
+    match style {
+        MergeStyle::TOP_BOTTOM => merge_aligned(tracks),
+        MergeStyle::STACKED => merge_stacked(tracks),
     }
+}
+
+fn merge_aligned(tracks: Vec<(&Subtitles, Alignment)>) -> Result<Subtitles> {
+    // TODO: ensure that subtitles do not overlap
 
-    for subtitle in secondary.into_iter() {
-        const PREFIX: &str = r"{\an8}"; // places the subtitle at the top of the video instead of the bottom
-        let mut altered_subtitle = subtitle.clone();
-        altered_subtitle.text = format!("{PREFIX}{0}", altered_subtitle.text);
-        merged.push(altered_subtitle);
+    // each subtitle is tagged with the index of its track, for use as a tiebreaker when sorting
+    let mut tagged: Vec<(Subtitle, usize)> = vec![];
+    for (track_index, (subtitles, alignment)) in tracks.into_iter().enumerate() {
+        for subtitle in subtitles.into_iter() {
+            let mut altered_subtitle = subtitle.clone();
+            altered_subtitle.text = align(&altered_subtitle.text, alignment);
+            tagged.push((altered_subtitle, track_index));
+        }
     }
 
-    let mut merged_vec = merged.to_vec();
     // sort the subtitles by their start time
-    merged_vec.sort_by_key(|s| s.start_time);
+    // subtitles with the same start time are ordered by track, then by their original order (the sort is stable)
+    // so that the output is deterministic
+    tagged.sort_by_key(|(s, track_index)| (s.start_time, *track_index));
+    let mut merged_vec = tagged.into_iter().map(|(s, _)| s).collect::<Vec<_>>();
     // assign their numerical order according to their start time
-    for (index, subtitle) in merged_vec.iter_mut().enumerate() {
-        subtitle.num = index;
-    }
+    renumber(&mut merged_vec);
 
     let merged = Subtitles::new_from_vec(merged_vec);
 
     Ok(merged)
 }
 
-fn modify_positioning(sub: &mut Subtitle, primary: bool) -> Result<()> {
-    // ass/ssa specification: http://www.tcax.org/docs/ass-specs.htm
-    // in particular:
-
-    // \a<alignment>            <alignment> is a number specifying the onscreen alignment/positioning of a subtitle.
-    // A value of 1 specifies a left-justified subtitle
-    // A value of 2 specifies a centered subtitle
-    // A value of 3 specifies a right-justified subtitle
-    // Adding 4 to the value specifies a "Toptitle"
-    // Adding 8 to the value specifies a "Midtitle"
-    // 0 or nothing resets to the style default (which is usually 2)
-
-    // eg. {\a1}This is a left-justified subtitle
-    //       {\a2}This is a centered subtitle
-    //       {\a3}This is a right-justified subtitle
-    //       {\a5}This is a left-justified toptitle
-    //       {\a11}This is a right-justified midtitle
-    // Only the first appearance counts.
-
-    // \an<alignment>         numpad layout
-    // Only the first appearance counts.
-    todo!();
-    Ok(())
+// splits subtitles combined in the top-bottom style back into the subtitles displayed at the given alignment, and the rest
+// the alignment's tag is removed from the subtitles it's split from, so that the two parts can be merged again
+// e.g., splitting at the top returns the secondary subtitles, then the primary subtitles
+pub fn split(subtitles: &Subtitles, alignment: Alignment) -> (Subtitles, Subtitles) {
+    let tag = alignment.tag();
+    let (mut aligned, mut rest): (Vec<Subtitle>, Vec<Subtitle>) = subtitles
+        .clone()
+        .into_iter()
+        .map(|mut subtitle| {
+            let is_aligned = if tag.is_empty() {
+                // bottom subtitles are untagged, so any subtitle without another alignment's tag is at the bottom
+                !subtitle.text.starts_with(r"{\an")
+            } else {
+                match unalign(&subtitle.text, alignment) {
+                    Some(text) => {
+                        subtitle.text = text;
+                        true
+                    }
+                    None => false,
+                }
+            };
+            (subtitle, is_aligned)
+        })
+        .partition_map(|(subtitle, is_aligned)| {
+            if is_aligned {
+                itertools::Either::Left(subtitle)
+            } else {
+                itertools::Either::Right(subtitle)
+            }
+        });
+    // each part is missing the subtitles of the other
+    renumber(&mut aligned);
+    renumber(&mut rest);
+    (
+        Subtitles::new_from_vec(aligned),
+        Subtitles::new_from_vec(rest),
+    )
+}
+
+// places the subtitle text at the given alignment
+// if the text already starts with an override block (e.g., {\pos(10,10)} from ass subtitles), the alignment is merged into it,
+// replacing any alignment it already has, rather than prepending a second block with conflicting positioning
+// braces that don't start with a backslash aren't override tags (e.g., {Laughs} in srt subtitles), so they're left as text
+fn align(text: &str, alignment: Alignment) -> String {
+    let tag = alignment.tag();
+    let Some((tags, rest)) = text
+        .strip_prefix('{')
+        .filter(|text| text.starts_with('\\'))
+        .and_then(|text| text.split_once('}'))
+    else {
+        return format!("{tag}{text}");
+    };
+    let alignment_tags = tag.trim_start_matches('{').trim_end_matches('}');
+    let other_tags = tags
+        .split('\\')
+        .enumerate()
+        // the first part is whatever precedes the first tag, which is kept as is
+        .filter(|(i, tag)| *i == 0 || !is_alignment_tag(tag))
+        .map(|(_, tag)| tag)
+        .join("\\");
+    if alignment_tags.is_empty() && other_tags.is_empty() {
+        return rest.to_string();
+    }
+    format!("{{{alignment_tags}{other_tags}}}{rest}")
+}
+
+// the reverse of align, returning the subtitle text without the given alignment's tag, or None if it isn't at that alignment
+// override tags that were merged with the alignment's tag are kept
+fn unalign(text: &str, alignment: Alignment) -> Option<String> {
+    let rest = text.strip_prefix(alignment.tag().strip_suffix('}')?)?;
+    if let Some(rest) = rest.strip_prefix('}') {
+        Some(rest.to_string())
+    } else if rest.starts_with('\\') {
+        Some(format!("{{{rest}"))
+    } else {
+        None
+    }
+}
+
+// whether the override tag (without its backslash) sets the alignment, i.e., \anN (numpad layout) or the legacy \aN
+fn is_alignment_tag(tag: &str) -> bool {
+    tag.strip_prefix("an")
+        .or_else(|| tag.strip_prefix('a'))
+        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+// splits the timeline at every point a subtitle starts or ends, and displays one cue for each span in which any subtitle is displayed
+// the cue's text is the text of each displayed subtitle, one per line, in track order
+fn merge_stacked(tracks: Vec<(&Subtitles, Alignment)>) -> Result<Subtitles> {
+    let tracks = tracks
+        .into_iter()
+        .map(|(subtitles, _)| {
+            let mut cues = subtitles
+                .into_iter()
+                .map(|s| {
+                    (
+                        timestamp_to_millis(&s.start_time),
+                        timestamp_to_millis(&s.end_time),
+                        s.text.clone(),
+                    )
+                })
+                .collect_vec();
+            cues.sort_by_key(|(start, _, _)| *start);
+            cues
+        })
+        .collect_vec();
+
+    let boundaries = tracks
+        .iter()
+        .flatten()
+        .flat_map(|(start, end, _)| [*start, *end])
+        .sorted()
+        .dedup()
+        .collect_vec();
+
+    let mut stacked: Vec<Subtitle> = vec![];
+    for (span_start, span_end) in boundaries.into_iter().tuple_windows() {
+        let text = tracks
+            .iter()
+            .flat_map(|cues| {
+                cues.iter()
+                    .filter(|(start, end, _)| *start <= span_start && *end >= span_end)
+                    .map(|(_, _, text)| text.trim_end())
+            })
+            .join("\n");
+        if text.is_empty() {
+            continue;
+        }
+        // adjacent spans showing the same text are displayed as a single cue
+        if let Some(previous) = stacked.last_mut() {
+            if previous.text == text && timestamp_to_millis(&previous.end_time) == span_start {
+                previous.end_time = millis_to_timestamp(span_end);
+                continue;
+            }
+        }
+        stacked.push(Subtitle::new(
+            stacked.len() + 1,
+            millis_to_timestamp(span_start),
+            millis_to_timestamp(span_end),
+            text,
+        ));
+    }
+
+    Ok(Subtitles::new_from_vec(stacked))
+}
+
+// concatenates subtitles that were split into multiple parts (e.g., CD1/CD2) into a single set of subtitles
+// each part is shifted later by its offset in milliseconds
+// if a part's offset is None, it's shifted so that it starts where the previous part's last subtitle ends
+pub fn concat(parts: Vec<(Subtitles, Option<i64>)>) -> Result<Subtitles> {
+    let mut concatenated: Vec<Subtitle> = vec![];
+    let mut previous_end: i64 = 0;
+
+    for (subtitles, offset) in parts {
+        let offset = offset.unwrap_or(previous_end);
+        for mut subtitle in subtitles {
+            let start = timestamp_to_millis(&subtitle.start_time) + offset;
+            let end = timestamp_to_millis(&subtitle.end_time) + offset;
+            subtitle.start_time = millis_to_timestamp(start);
+            subtitle.end_time = millis_to_timestamp(end);
+            previous_end = previous_end.max(end);
+            concatenated.push(subtitle);
+        }
+    }
+
+    // assign their numerical order according to their position in the combined file
+    renumber(&mut concatenated);
+
+    Ok(Subtitles::new_from_vec(concatenated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subtitles(cues: &[(i64, i64, &str)]) -> Subtitles {
+        Subtitles::new_from_vec(
+            cues.iter()
+                .enumerate()
+                .map(|(index, (start, end, text))| {
+                    Subtitle::new(
+                        index + 1,
+                        millis_to_timestamp(*start),
+                        millis_to_timestamp(*end),
+                        text.to_string(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn merge_puts_primary_first_when_cues_start_together() {
+        let primary = subtitles(&[(1000, 2000, "primary one"), (3000, 4000, "primary two")]);
+        let secondary = subtitles(&[(1000, 2500, "secondary one"), (3000, 3500, "secondary two")]);
+        let merged = merge(&primary, &secondary, MergeStyle::TOP_BOTTOM)
+            .unwrap()
+            .to_vec();
+
+        let texts = merged.iter().map(|s| s.text.as_str()).collect_vec();
+        assert_eq!(
+            texts,
+            [
+                "primary one",
+                r"{\an8}secondary one",
+                "primary two",
+                r"{\an8}secondary two"
+            ]
+        );
+        let nums = merged.iter().map(|s| s.num).collect_vec();
+        assert_eq!(nums, (1..=merged.len()).collect_vec());
+    }
+
+    #[test]
+    fn merge_adds_alignment_to_an_existing_override_block() {
+        let primary = subtitles(&[(1000, 2000, "Hello")]);
+        let secondary = subtitles(&[(1000, 2000, r"{\pos(10,10)}Hi")]);
+        let merged = merge(&primary, &secondary, MergeStyle::TOP_BOTTOM).unwrap();
+
+        let texts = merged
+            .clone()
+            .to_vec()
+            .into_iter()
+            .map(|s| s.text)
+            .collect_vec();
+        assert_eq!(texts, ["Hello", r"{\an8\pos(10,10)}Hi"]);
+        assert_eq!(texts[1].matches('{').count(), 1);
+
+        let (top, bottom) = split(&merged, Alignment::TOP);
+        assert_eq!(top.to_vec()[0].text, r"{\pos(10,10)}Hi");
+        assert_eq!(bottom.to_vec()[0].text, "Hello");
+    }
+
+    #[test]
+    fn merge_leaves_braced_text_that_isnt_an_override_block() {
+        let primary = subtitles(&[(1000, 2000, "Hello")]);
+        let secondary = subtitles(&[(1000, 2000, "{Laughs} hi")]);
+        let merged = merge(&primary, &secondary, MergeStyle::TOP_BOTTOM).unwrap();
+        assert_eq!(merged.clone().to_vec()[1].text, r"{\an8}{Laughs} hi");
+
+        let (top, _) = split(&merged, Alignment::TOP);
+        assert_eq!(top.to_vec()[0].text, "{Laughs} hi");
+    }
 }
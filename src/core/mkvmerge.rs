@@ -1,32 +1,178 @@
 use anyhow::{anyhow, Context, Result};
-use srtlib::Subtitles;
-use std::{path::Path, process::Command};
+use itertools::Itertools;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
 
-use crate::core::data::{pretty_cmd, pretty_output, TMP_DIRECTORY};
+use crate::core::data::{
+    default_tmp_directory, extra_tool_args, hash_path, pretty_cmd, pretty_output, tool_command,
+    SubtitlesTrack,
+};
+use crate::core::fonts;
 
-use super::data::hash_string;
+// the subset of `mkvmerge -J` output that we use
+#[derive(Deserialize, Debug)]
+struct Identification {
+    tracks: Vec<IdentifiedTrack>,
+}
+
+#[derive(Deserialize, Debug)]
+struct IdentifiedTrack {
+    id: u32,
+    #[serde(rename = "type")]
+    track_type: String,
+    properties: IdentifiedTrackProperties,
+}
+
+#[derive(Deserialize, Debug)]
+struct IdentifiedTrackProperties {
+    language: Option<String>,
+    language_ietf: Option<String>,
+}
 
-pub fn add_subtitles_track(
+fn identify(video_file: &Path) -> Result<Identification> {
+    let mut command = tool_command("mkvmerge");
+    command
+        .arg("-J") // identify the file, output as json
+        .arg(video_file);
+
+    log::debug!("{0}", pretty_cmd(&command));
+    let output = command.output()?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "command was not successfully executed:\n{0}\n{1}",
+            pretty_cmd(&command),
+            pretty_output(&output)
+        ));
+    }
+    log::trace!("{0}", pretty_output(&output));
+
+    serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("could not parse mkvmerge identification of {video_file:#?}"))
+}
+
+// returns the mkvmerge track IDs of the subtitle tracks in the given video that have the given language
+// the language code may be either ISO 639-2 (e.g., "eng") or IETF BCP 47 (e.g., "en")
+pub fn subtitle_tracks_with_language(video_file: &Path, language_code: &str) -> Result<Vec<u32>> {
+    let identification = identify(video_file)?;
+    Ok(identification
+        .tracks
+        .iter()
+        .filter(|track| track.track_type == "subtitles")
+        .filter(|track| {
+            track.properties.language.as_deref() == Some(language_code)
+                || track.properties.language_ietf.as_deref() == Some(language_code)
+        })
+        .map(|track| track.id)
+        .collect())
+}
+
+// adds the given subtitles files to the video as new tracks, in the order given
+// when replace_language is set, existing subtitle tracks with the same language code as a new track are removed from the video
+// so that the newly added track is the only one with that language
+// players list tracks in the order they're muxed, so the new tracks are placed either before or after the video's existing subtitle tracks
+// the video's other tracks (video, audio, etc) always come first
+// the given font files are attached to the output, for ass subtitles that use them
+// returns the number of subtitles tracks the output should contain
+pub fn add_subtitles_tracks(
     video_file: &Path,
-    subtitles_file: &Path,
-    language_code: Option<&str>,
-    track_name: &str,
+    new_tracks: &[SubtitlesTrack],
+    font_files: &[PathBuf],
+    replace_language: bool,
+    new_tracks_first: bool,
     output_path: &Path,
-) -> Result<()> {
-    let mut command = Command::new("mkvmerge");
-    if let Some(code) = language_code {
-        command
-            .arg("--language") // add the language code
-            .arg(format!("0:{code}"));
+) -> Result<usize> {
+    let identification = identify(video_file)?;
+    let mut excluded_tracks: Vec<u32> = vec![];
+    if replace_language {
+        for code in new_tracks
+            .iter()
+            .filter_map(|t| t.metadata.language.as_deref())
+        {
+            let existing_tracks = subtitle_tracks_with_language(video_file, code)?;
+            if !existing_tracks.is_empty() {
+                log::info!(
+                    "removing existing {code} subtitle tracks {existing_tracks:?} from {video_file:#?}"
+                );
+            }
+            excluded_tracks.extend(existing_tracks);
+        }
     }
+
+    // track order entries are <file index>:<track id>, where the video is file 0 and each new track is its own file
+    let (existing_subtitles, other_tracks): (Vec<_>, Vec<_>) = identification
+        .tracks
+        .iter()
+        .filter(|track| !excluded_tracks.contains(&track.id))
+        .partition(|track| track.track_type == "subtitles");
+    let expected_subtitle_tracks = existing_subtitles.len() + new_tracks.len();
+    let other_tracks = other_tracks.iter().map(|track| format!("0:{0}", track.id));
+    let existing_subtitles = existing_subtitles
+        .iter()
+        .map(|track| format!("0:{0}", track.id))
+        .collect::<Vec<_>>();
+    let added_subtitles = (1..=new_tracks.len())
+        .map(|file_index| format!("{file_index}:0"))
+        .collect::<Vec<_>>();
+    let track_order = if new_tracks_first {
+        other_tracks
+            .chain(added_subtitles)
+            .chain(existing_subtitles)
+            .join(",")
+    } else {
+        other_tracks
+            .chain(existing_subtitles)
+            .chain(added_subtitles)
+            .join(",")
+    };
+
+    let mut command = tool_command("mkvmerge");
     command
         .arg("-o") // specify the output path
-        .arg(output_path)
-        .arg(video_file)// input the video file
-        .arg("--track-name") // name the track
-        .arg(format!("0:{track_name}"))
-        .arg(subtitles_file)// input the subtitles file
-        ;
+        .arg(output_path);
+    if !excluded_tracks.is_empty() {
+        command
+            .arg("--subtitle-tracks") // exclude the existing tracks from the video
+            .arg(format!("!{0}", excluded_tracks.iter().join(",")));
+    }
+    command.arg(video_file); // input the video file
+    for track in new_tracks {
+        // the options below apply to the subtitles file, since they come directly before it
+        let metadata = &track.metadata;
+        if let Some(code) = &metadata.language {
+            command
+                .arg("--language") // add the language code
+                .arg(format!("0:{code}"));
+        }
+        if metadata.default {
+            command
+                .arg("--default-track") // mark the track as default
+                .arg("0:yes");
+        }
+        if metadata.forced {
+            command
+                .arg("--forced-track") // mark the track as forced
+                .arg("0:yes");
+        }
+        command
+            .arg("--track-name") // name the track
+            .arg(format!("0:{0}", metadata.name))
+            .arg(track.path); // input the subtitles file
+    }
+    for font_file in font_files {
+        if let Some(mime_type) = fonts::mime_type(font_file) {
+            command
+                .arg("--attachment-mime-type") // players only load attachments with a font mime type as fonts
+                .arg(mime_type);
+        }
+        command
+            .arg("--attach-file") // attach the font file
+            .arg(font_file);
+    }
+    command
+        .arg("--track-order") // order the tracks in the output
+        .arg(track_order)
+        .args(extra_tool_args("mkvmerge"));
 
     log::debug!("{0}", pretty_cmd(&command));
     let output = command.output()?;
@@ -40,5 +186,111 @@ pub fn add_subtitles_track(
     }
     log::trace!("{0}", pretty_output(&output));
 
+    Ok(expected_subtitle_tracks)
+}
+
+// as set_default_subtitles_track_in, using the default temporary directory
+pub fn set_default_subtitles_track(
+    video_file: &Path,
+    subtitle_track: Option<u32>,
+    output_path: &Path,
+) -> Result<()> {
+    set_default_subtitles_track_in(
+        default_tmp_directory(),
+        video_file,
+        subtitle_track,
+        output_path,
+    )
+}
+
+// makes the given subtitles track the only default subtitles track of the video, writing the result to the output path
+// the track is its index among the video's subtitles tracks, or None to clear the default flag of every subtitles track
+// mkvpropedit changes the flags without remuxing, which is much faster; if it isn't installed, the video is remuxed with mkvmerge instead
+// the output path may be the video itself, to edit it in place, in which case the remuxed video is written to the given temporary directory first
+pub fn set_default_subtitles_track_in(
+    tmp_dir: &Path,
+    video_file: &Path,
+    subtitle_track: Option<u32>,
+    output_path: &Path,
+) -> Result<()> {
+    let identification = identify(video_file)?;
+    let subtitle_ids = identification
+        .tracks
+        .iter()
+        .filter(|track| track.track_type == "subtitles")
+        .map(|track| track.id)
+        .collect::<Vec<_>>();
+    if let Some(track) = subtitle_track {
+        if track as usize >= subtitle_ids.len() {
+            return Err(anyhow!(
+                "{video_file:#?} has {0} subtitles tracks, so there is no subtitles track {track} (tracks are numbered from 0)",
+                subtitle_ids.len()
+            ));
+        }
+    }
+    let in_place =
+        output_path.exists() && video_file.canonicalize()? == output_path.canonicalize()?;
+
+    if tool_command("mkvpropedit")
+        .arg("--version")
+        .output()
+        .is_ok()
+    {
+        // mkvpropedit only edits files in place, so the video is copied to the output first
+        if !in_place {
+            std::fs::copy(video_file, output_path)?;
+        }
+        let mut command = tool_command("mkvpropedit");
+        command.arg(output_path);
+        for index in 0..subtitle_ids.len() {
+            let default = subtitle_track == Some(index as u32);
+            command
+                .arg("--edit") // select the nth subtitles track, counting from 1
+                .arg(format!("track:s{0}", index + 1))
+                .arg("--set") // set (or clear) its default flag
+                .arg(format!("flag-default={0}", default as u8));
+        }
+        command.args(extra_tool_args("mkvpropedit"));
+        return run(&mut command);
+    }
+
+    log::info!("mkvpropedit was not found, remuxing {video_file:#?} with mkvmerge instead");
+    // mkvmerge can't write to its input, so editing in place writes to a temporary file that then replaces the video
+    let remux_path = if in_place {
+        tmp_dir.join(format!("default_{0}.mkv", hash_path(video_file)))
+    } else {
+        output_path.to_path_buf()
+    };
+    let mut command = tool_command("mkvmerge");
+    command
+        .arg("-o") // specify the output path
+        .arg(&remux_path);
+    for (index, id) in subtitle_ids.iter().enumerate() {
+        let default = subtitle_track == Some(index as u32);
+        command
+            .arg("--default-track") // set (or clear) the track's default flag
+            .arg(format!("{id}:{0}", if default { "yes" } else { "no" }));
+    }
+    command.arg(video_file).args(extra_tool_args("mkvmerge"));
+    run(&mut command)?;
+    if in_place {
+        std::fs::copy(&remux_path, output_path)?;
+        std::fs::remove_file(&remux_path)?;
+    }
+    Ok(())
+}
+
+fn run(command: &mut std::process::Command) -> Result<()> {
+    log::debug!("{0}", pretty_cmd(command));
+    let output = command.output()?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "command was not successfully executed:\n{0}\n{1}",
+            pretty_cmd(command),
+            pretty_output(&output)
+        ));
+    }
+    log::trace!("{0}", pretty_output(&output));
     Ok(())
 }
@@ -1,7 +1,18 @@
+pub mod ass;
+pub mod assign;
+pub mod check;
 pub mod data;
+pub mod diff;
+pub mod doctor;
+pub mod export;
 pub mod ffmpeg;
+pub mod fonts;
+pub mod lang;
 pub mod log;
 pub mod merge;
 pub mod mkvmerge;
 pub mod modify;
+pub mod mux;
+pub mod search;
 pub mod sync;
+pub mod time;
@@ -1,37 +1,996 @@
 // this file contains functions to modify subtitles files
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use ego_tree::iter::Edge;
+use encoding_rs::WINDOWS_1252;
+use itertools::Itertools;
+use regex::Regex;
 use scraper::Html;
-use srtlib::Subtitles;
+use serde::{Deserialize, Serialize};
+use srtlib::{Subtitle, Subtitles};
+
+use crate::core::data::ScaleAnchor;
+use crate::core::time::{millis_to_timestamp, timestamp_to_millis};
 
 // strips HTML tags from subtitles, removing custom fonts, sizes, and colors
 pub fn strip_html(subs: &mut Subtitles) -> Result<()> {
+    strip_html_keeping(subs, &[])
+}
+
+// strips HTML tags from subtitles, except for the given tags (e.g., "i" to keep italics)
+pub fn strip_html_keeping(subs: &mut Subtitles, kept_tags: &[&str]) -> Result<()> {
     for subtitle in subs.into_iter() {
-        subtitle.text = strip_html_string(&subtitle.text);
+        subtitle.text = strip_html_string_keeping(&subtitle.text, kept_tags);
     }
     Ok(())
 }
 
-fn strip_html_string(string: &str) -> String {
+// removes bracketed information such as sound effects and speaker names, e.g., [music], (laughs)
+// subtitles that are left without any text are removed entirely
+pub fn remove_bracketed_info(subs: &mut Subtitles) -> Result<()> {
+    let mut cleaned = vec![];
+    for subtitle in subs.clone() {
+        let mut subtitle = subtitle;
+        subtitle.text = remove_bracketed_string(&subtitle.text);
+        if !subtitle.text.is_empty() {
+            cleaned.push(subtitle);
+        }
+    }
+    // assign their numerical order again, since some subtitles may have been removed
+    renumber(&mut cleaned);
+    *subs = Subtitles::new_from_vec(cleaned);
+    Ok(())
+}
+
+// performs all of the cleanup operations: strips html and removes bracketed information
+pub fn clean_subtitles(subs: &mut Subtitles) -> Result<()> {
+    strip_html(subs)?;
+    remove_bracketed_info(subs)?;
+    Ok(())
+}
+
+fn remove_bracketed_string(string: &str) -> String {
+    // drop lines that are left empty, or with only a dialogue dash
+    remove_brackets(string)
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && *line != "-")
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// removes the bracketed parts of the string, including nested brackets
+fn remove_brackets(string: &str) -> String {
+    // curly braces are intentionally excluded, since they contain positioning tags such as {\an8}
+    const BRACKETS: [(char, char); 4] = [('[', ']'), ('(', ')'), ('【', '】'), ('（', '）')];
+
+    let mut result = String::new();
+    let mut open_brackets: Vec<char> = vec![];
+    // the position of the outermost open bracket, whose text is put back if it's never closed
+    let mut outermost_open = 0;
+    for (index, c) in string.char_indices() {
+        if let Some((_, close)) = BRACKETS.iter().find(|(open, _)| *open == c) {
+            if open_brackets.is_empty() {
+                outermost_open = index;
+            }
+            open_brackets.push(*close);
+        } else if open_brackets.last() == Some(&c) {
+            open_brackets.pop();
+        } else if open_brackets.is_empty() {
+            result.push(c);
+        }
+    }
+
+    // a bracket that's never closed (e.g., a typo, or a cue split mid-aside) is kept as text, along with everything after it
+    // brackets after it that are closed are still removed
+    if let Some(open) = string[outermost_open..]
+        .chars()
+        .next()
+        .filter(|_| !open_brackets.is_empty())
+    {
+        result.push(open);
+        result.push_str(&remove_brackets(
+            &string[outermost_open + open.len_utf8()..],
+        ));
+    }
+    result
+}
+
+// strips HTML tags from a single string
+// entities (e.g., &amp; or &#39;) are decoded to the characters they stand for, and non-breaking spaces become regular spaces
+pub fn strip_html_string(string: &str) -> String {
+    strip_html_string_keeping(string, &[])
+}
+
+// strips HTML tags from a single string, except for the given tags, which are written back without their attributes
+pub fn strip_html_string_keeping(string: &str, kept_tags: &[&str]) -> String {
+    let is_kept = |element: &scraper::node::Element| {
+        kept_tags
+            .iter()
+            .any(|tag| tag.eq_ignore_ascii_case(element.name()))
+    };
+
     let mut strings = vec![];
     let fragment = Html::parse_fragment(string);
-    for node in fragment.tree {
-        if let scraper::node::Node::Text(text) = node {
-            strings.push(text.text.to_string());
+    for edge in fragment.tree.root().traverse() {
+        match edge {
+            Edge::Open(node) => match node.value() {
+                // the parser has already decoded entities in the text, including &nbsp; to U+00A0
+                // which players may render as a stray character, so it's replaced with a regular space
+                scraper::node::Node::Text(text) => strings.push(text.text.replace('\u{a0}', " ")),
+                scraper::node::Node::Element(element) if is_kept(element) => {
+                    strings.push(format!("<{0}>", element.name()))
+                }
+                _ => {}
+            },
+            Edge::Close(node) => {
+                if let scraper::node::Node::Element(element) = node.value() {
+                    if is_kept(element) {
+                        strings.push(format!("</{0}>", element.name()));
+                    }
+                }
+            }
         }
     }
     strings.join("")
 }
 
+// a cue is dialogue between several speakers if its first line starts with a dash, and at least one other line does too, e.g.,
+//     - Where are you going?
+//     - Home.
+// each line starting with a dash starts a new speaker, and lines that don't continue the previous speaker's line
+// a leading override block (e.g., {\an8}) positions the whole cue, so it applies to every speaker
+
+// splits each dialogue cue into one cue per speaker, without dashes, all displayed at the same time as the original cue
+// this is the reverse of join_dialogue
+pub fn split_dialogue(subtitles: &Subtitles) -> Result<Subtitles> {
+    let mut split = vec![];
+    for subtitle in subtitles.clone().to_vec() {
+        match speaker_lines(&subtitle.text) {
+            Some(lines) => split.extend(lines.into_iter().map(|text| {
+                let mut speaker = subtitle.clone();
+                speaker.text = text;
+                speaker
+            })),
+            None => split.push(subtitle),
+        }
+    }
+    renumber(&mut split);
+    Ok(Subtitles::new_from_vec(split))
+}
+
+// joins consecutive cues displayed at exactly the same time and position into a single dialogue cue, one dashed line per cue
+// existing dialogue cues are split first, so that dashes are normalized to "- " and never doubled
+// cues can't be told apart from dialogue by their text, so any simultaneous cues are joined (e.g., a sign displayed with a line)
+// this is the reverse of split_dialogue
+pub fn join_dialogue(subtitles: &Subtitles) -> Result<Subtitles> {
+    let mut joined: Vec<Subtitle> = vec![];
+    for group in &split_dialogue(subtitles)?
+        .to_vec()
+        .into_iter()
+        .chunk_by(|s| {
+            (
+                s.start_time,
+                s.end_time,
+                override_block(&s.text).0.to_string(),
+            )
+        })
+    {
+        let ((_, _, block), group) = group;
+        let mut group = group.collect::<Vec<_>>();
+        if group.len() == 1 {
+            joined.append(&mut group);
+            continue;
+        }
+        let lines = group
+            .iter()
+            .map(|s| format!("- {0}", override_block(&s.text).1))
+            .join("\n");
+        let mut subtitle = group.swap_remove(0);
+        subtitle.text = format!("{block}{lines}");
+        joined.push(subtitle);
+    }
+    renumber(&mut joined);
+    Ok(Subtitles::new_from_vec(joined))
+}
+
+// the text of each speaker of a dialogue cue, with their dashes removed, or None if the cue isn't dialogue
+fn speaker_lines(text: &str) -> Option<Vec<String>> {
+    let (block, body) = override_block(text);
+    let lines = body.lines().collect::<Vec<_>>();
+    let is_dashed = |line: &str| line.trim_start().starts_with('-');
+    if !lines.first().is_some_and(|line| is_dashed(line))
+        || lines.iter().filter(|line| is_dashed(line)).count() < 2
+    {
+        return None;
+    }
+    let mut speakers: Vec<String> = vec![];
+    for line in lines {
+        match speakers.last_mut() {
+            Some(speaker) if !is_dashed(line) => {
+                speaker.push('\n');
+                speaker.push_str(line);
+            }
+            _ => speakers.push(format!(
+                "{block}{0}",
+                line.trim_start().trim_start_matches('-').trim_start()
+            )),
+        }
+    }
+    Some(speakers)
+}
+
+// splits the subtitle text into its leading override block (e.g., {\an8}), if any, and the rest of the text
+fn override_block(text: &str) -> (&str, &str) {
+    match text.find('}') {
+        Some(end) if text.starts_with('{') => text.split_at(end + 1),
+        _ => ("", text),
+    }
+}
+
+// shifts the subtitles later by the given number of seconds (or earlier, if negative), rounded to the nearest millisecond
 pub fn shift_seconds(subtitles: &Subtitles, seconds: f32) -> Result<Subtitles> {
+    // f32 can't represent most fractions of a second exactly (e.g., 1.9999 is 1.99989998...), so it's rounded in f64
+    shift_millis(subtitles, (seconds as f64 * 1000.0).round() as i64)
+}
+
+// shifts the subtitles later by the given number of milliseconds (or earlier, if negative)
+// subtitles shifted before the start of the video are clamped to zero, since srt timestamps cannot be negative
+pub fn shift_millis(subtitles: &Subtitles, millis: i64) -> Result<Subtitles> {
     let mut shifted_subs = subtitles.clone().to_vec();
-    let iseconds = seconds as i32;
-    let imillis = ((seconds - iseconds as f32) * 1000.0) as i32;
 
     for subtitle in shifted_subs.iter_mut() {
-        subtitle.add_seconds(iseconds);
-        subtitle.add_milliseconds(imillis);
+        subtitle.start_time =
+            millis_to_timestamp(timestamp_to_millis(&subtitle.start_time) + millis);
+        subtitle.end_time = millis_to_timestamp(timestamp_to_millis(&subtitle.end_time) + millis);
     }
 
     Ok(Subtitles::new_from_vec(shifted_subs))
 }
+
+// patterns matching the credits that fan-subs commonly place at the start or end of the subtitles
+// these are deliberately conservative, since a false positive removes real dialogue
+pub fn default_credit_patterns() -> Vec<String> {
+    [
+        // urls
+        r"https?://",
+        r"\bwww\.",
+        r"\b[\w-]+\.(com|net|org|tv|io|info)\b",
+        // e.g., "subtitles by", "synced and corrected by", "translation:"
+        r"\b(subtitles?|subs|subbed|translat(ed|ion|ions|or|ors)|sync(ed|hronized)?|resync(ed)?|correct(ed|ions)|encoded|ripped|timing|timed)\s*(and\s+\w+\s+)?(by\b|:)",
+        // well-known subtitle sites
+        r"\b(opensubtitles|addic7ed|subscene|podnapisi)\b",
+        // release group tags, e.g., [HorribleSubs]
+        r"^\s*\[[^\]]*(subs?|fansubs?)\]\s*$",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+// removes credits (cues matching any of the given patterns) from the start and end of the subtitles
+// only consecutive matching cues at the very start or end are removed, so that matching dialogue elsewhere is kept
+pub fn strip_credits(subtitles: &Subtitles, patterns: &[Regex]) -> Result<Subtitles> {
+    let mut stripped = subtitles.clone().to_vec();
+    stripped.sort_by_key(|s| s.start_time);
+    let is_credit = |subtitle: &Subtitle| patterns.iter().any(|p| p.is_match(&subtitle.text));
+
+    let leading = stripped.iter().take_while(|s| is_credit(s)).count();
+    stripped.drain(..leading);
+    let trailing = stripped.iter().rev().take_while(|s| is_credit(s)).count();
+    stripped.truncate(stripped.len() - trailing);
+
+    renumber(&mut stripped);
+    Ok(Subtitles::new_from_vec(stripped))
+}
+
+// shifts the subtitles earlier so that the first subtitle starts at the given lead time (in milliseconds)
+// subtitles that already start within the lead time are left unchanged
+pub fn zero_base(subtitles: &Subtitles, keep_lead_ms: i64) -> Result<Subtitles> {
+    let first_start = subtitles
+        .clone()
+        .to_vec()
+        .iter()
+        .map(|subtitle| timestamp_to_millis(&subtitle.start_time))
+        .min();
+    let Some(first_start) = first_start else {
+        return Ok(subtitles.clone());
+    };
+    let offset_ms = (first_start - keep_lead_ms).max(0);
+    shift_millis(subtitles, -offset_ms)
+}
+
+// keeps only the subtitles that are displayed within the window from start_ms to end_ms (in milliseconds)
+// subtitles that are only partially within the window are clipped to it
+// if rebase is true, the subtitles are shifted earlier so that the start of the window becomes 00:00:00
+pub fn trim(subtitles: &Subtitles, start_ms: i64, end_ms: i64, rebase: bool) -> Result<Subtitles> {
+    if end_ms <= start_ms {
+        return Err(anyhow!(
+            "the end of the window ({0}ms) must be after its start ({1}ms)",
+            end_ms,
+            start_ms
+        ));
+    }
+    let offset = if rebase { start_ms } else { 0 };
+    let mut trimmed = vec![];
+    for mut subtitle in subtitles.clone() {
+        let start = timestamp_to_millis(&subtitle.start_time);
+        let end = timestamp_to_millis(&subtitle.end_time);
+        if end <= start_ms || start >= end_ms {
+            continue;
+        }
+        subtitle.start_time = millis_to_timestamp(start.max(start_ms) - offset);
+        subtitle.end_time = millis_to_timestamp(end.min(end_ms) - offset);
+        trimmed.push(subtitle);
+    }
+    trimmed.sort_by_key(|s| s.start_time);
+    renumber(&mut trimmed);
+    Ok(Subtitles::new_from_vec(trimmed))
+}
+
+// stretches (factor > 1) or compresses (factor < 1) the timeline of the subtitles, multiplying the time of each subtitle from the anchor by the factor
+// this corrects subtitles that gradually drift out of sync, which a constant shift can't
+pub fn scale(subtitles: &Subtitles, factor: f64, anchor: ScaleAnchor) -> Result<Subtitles> {
+    if !factor.is_finite() || factor <= 0.0 {
+        return Err(anyhow!(
+            "the scale factor must be a positive number, got {factor}"
+        ));
+    }
+    let anchor_ms = match anchor {
+        ScaleAnchor::ZERO => 0,
+        ScaleAnchor::FIRST => subtitles
+            .clone()
+            .to_vec()
+            .iter()
+            .map(|subtitle| timestamp_to_millis(&subtitle.start_time))
+            .min()
+            .unwrap_or(0),
+    };
+    let scale_ms = |millis: i64| anchor_ms + ((millis - anchor_ms) as f64 * factor).round() as i64;
+
+    let mut scaled = subtitles.clone().to_vec();
+    for subtitle in scaled.iter_mut() {
+        subtitle.start_time =
+            millis_to_timestamp(scale_ms(timestamp_to_millis(&subtitle.start_time)));
+        subtitle.end_time = millis_to_timestamp(scale_ms(timestamp_to_millis(&subtitle.end_time)));
+    }
+    Ok(Subtitles::new_from_vec(scaled))
+}
+
+// a pair of subtitles displayed at the same position, where the earlier one ends after the later one starts
+pub struct Overlap {
+    pub earlier: Subtitle,
+    pub later: Subtitle,
+}
+
+impl Overlap {
+    pub fn overlap_ms(&self) -> i64 {
+        timestamp_to_millis(&self.earlier.end_time) - timestamp_to_millis(&self.later.start_time)
+    }
+}
+
+// finds subtitles that overlap the next subtitle displayed at the same position
+// subtitles at different positions (e.g., the top and bottom tracks of dual subtitles) are expected to overlap, so they're ignored
+pub fn find_overlaps(subtitles: &Subtitles) -> Vec<Overlap> {
+    let mut sorted = subtitles.clone().to_vec();
+    sorted.sort_by_key(|s| s.start_time);
+    (0..sorted.len())
+        .filter_map(|index| {
+            let next_index = next_at_same_position(&sorted, index)?;
+            let (earlier, later) = (&sorted[index], &sorted[next_index]);
+            if earlier.end_time > later.start_time {
+                Some(Overlap {
+                    earlier: earlier.clone(),
+                    later: later.clone(),
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// truncates each subtitle that overlaps the next subtitle displayed at the same position, so that it ends when the next one starts
+// subtitles that start at the same time as the next one are left unchanged, since truncating them would leave nothing to display
+pub fn resolve_overlaps(subtitles: &Subtitles) -> Result<Subtitles> {
+    let mut resolved = subtitles.clone().to_vec();
+    resolved.sort_by_key(|s| s.start_time);
+    for index in 0..resolved.len() {
+        let Some(next_index) = next_at_same_position(&resolved, index) else {
+            continue;
+        };
+        let next_start = resolved[next_index].start_time;
+        let subtitle = &mut resolved[index];
+        if subtitle.end_time > next_start && subtitle.start_time < next_start {
+            subtitle.end_time = next_start;
+        }
+    }
+
+    renumber(&mut resolved);
+    Ok(Subtitles::new_from_vec(resolved))
+}
+
+// returns the index of the next subtitle after the given one (in a slice sorted by start time) that is displayed at the same position
+fn next_at_same_position(sorted: &[Subtitle], index: usize) -> Option<usize> {
+    let position = alignment(&sorted[index].text);
+    (index + 1..sorted.len()).find(|next| alignment(&sorted[*next].text) == position)
+}
+
+// returns the numpad alignment of the subtitle text, as given by an {\anN} override tag at its start
+// subtitles without an alignment tag are displayed at the bottom center, i.e., 2
+fn alignment(text: &str) -> char {
+    text.strip_prefix('{')
+        .and_then(|rest| rest.split_once('}'))
+        .and_then(|(tags, _)| tags.split_once("\\an"))
+        .and_then(|(_, rest)| rest.chars().next())
+        .filter(|c| c.is_ascii_digit())
+        .unwrap_or('2')
+}
+
+// removes subtitles that are displayed for less than the given duration
+pub fn filter_min_duration(subtitles: &Subtitles, min_duration_ms: i64) -> Result<Subtitles> {
+    let mut filtered = subtitles
+        .clone()
+        .to_vec()
+        .into_iter()
+        .filter(|subtitle| duration_millis(subtitle) >= min_duration_ms)
+        .collect::<Vec<_>>();
+    renumber(&mut filtered);
+    Ok(Subtitles::new_from_vec(filtered))
+}
+
+// extends subtitles that are displayed for less than the given duration, rather than removing them
+// a subtitle is never extended past the start of the next subtitle, so it may still end up shorter than the minimum
+pub fn extend_min_duration(subtitles: &Subtitles, min_duration_ms: i64) -> Result<Subtitles> {
+    let mut extended = subtitles.clone().to_vec();
+    extended.sort_by_key(|s| s.start_time);
+    let next_starts = extended
+        .iter()
+        .skip(1)
+        .map(|s| Some(timestamp_to_millis(&s.start_time)))
+        .chain([None])
+        .collect::<Vec<_>>();
+
+    for (subtitle, next_start) in extended.iter_mut().zip(next_starts) {
+        if duration_millis(subtitle) >= min_duration_ms {
+            continue;
+        }
+        let start = timestamp_to_millis(&subtitle.start_time);
+        let mut end = start + min_duration_ms;
+        if let Some(next_start) = next_start {
+            end = end.min(next_start);
+        }
+        // never shorten a subtitle, even if it already overlaps the next one
+        end = end.max(timestamp_to_millis(&subtitle.end_time));
+        subtitle.end_time = millis_to_timestamp(end);
+    }
+
+    renumber(&mut extended);
+    Ok(Subtitles::new_from_vec(extended))
+}
+
+// extends each subtitle until the start of the next subtitle, so that there are no blank gaps between them
+// a subtitle is extended by at most the given duration, and the last subtitle is left unchanged
+pub fn fill_gaps(subtitles: &Subtitles, max_extend_ms: i64) -> Result<Subtitles> {
+    let mut filled = subtitles.clone().to_vec();
+    filled.sort_by_key(|s| s.start_time);
+    let next_starts = filled
+        .iter()
+        .skip(1)
+        .map(|s| Some(timestamp_to_millis(&s.start_time)))
+        .chain([None])
+        .collect::<Vec<_>>();
+
+    for (subtitle, next_start) in filled.iter_mut().zip(next_starts) {
+        let Some(next_start) = next_start else {
+            continue;
+        };
+        let end = timestamp_to_millis(&subtitle.end_time);
+        // never shorten a subtitle, even if it already overlaps the next one
+        let filled_end = next_start.min(end + max_extend_ms).max(end);
+        subtitle.end_time = millis_to_timestamp(filled_end);
+    }
+
+    renumber(&mut filled);
+    Ok(Subtitles::new_from_vec(filled))
+}
+
+// the number of subtitles changed by enforce_cps, and how many of those are still read too quickly
+pub struct CpsReport {
+    pub adjusted: usize,
+    pub unfixable: usize,
+}
+
+// extends subtitles whose reading speed exceeds the given number of characters per second, so that they're displayed long enough to read
+// each such subtitle is displayed for at least the given minimum duration, and is never extended past the start of the next subtitle
+// subtitles that are still too fast because the next subtitle starts too soon are counted as unfixable
+pub fn enforce_cps(
+    subtitles: &Subtitles,
+    max_cps: f64,
+    min_duration_ms: i64,
+) -> Result<(Subtitles, CpsReport)> {
+    let mut enforced = subtitles.clone().to_vec();
+    enforced.sort_by_key(|s| s.start_time);
+    let next_starts = enforced
+        .iter()
+        .skip(1)
+        .map(|s| Some(timestamp_to_millis(&s.start_time)))
+        .chain([None])
+        .collect::<Vec<_>>();
+
+    let mut report = CpsReport {
+        adjusted: 0,
+        unfixable: 0,
+    };
+    for (subtitle, next_start) in enforced.iter_mut().zip(next_starts) {
+        // line breaks and formatting aren't read, so they don't count towards the reading speed
+        let characters = strip_html_string(&subtitle.text)
+            .chars()
+            .filter(|c| *c != '\n' && *c != '\r')
+            .count();
+        let required_ms =
+            ((characters as f64 / max_cps * 1000.0).ceil() as i64).max(min_duration_ms);
+        if duration_millis(subtitle) >= required_ms {
+            continue;
+        }
+        let start = timestamp_to_millis(&subtitle.start_time);
+        let mut end = start + required_ms;
+        if let Some(next_start) = next_start {
+            end = end.min(next_start);
+        }
+        // never shorten a subtitle, even if it already overlaps the next one
+        end = end.max(timestamp_to_millis(&subtitle.end_time));
+        if end > timestamp_to_millis(&subtitle.end_time) {
+            subtitle.end_time = millis_to_timestamp(end);
+            report.adjusted += 1;
+        }
+        if end - start < required_ms {
+            report.unfixable += 1;
+        }
+    }
+
+    renumber(&mut enforced);
+    Ok((Subtitles::new_from_vec(enforced), report))
+}
+
+// shortens subtitles that are displayed for longer than the given duration, e.g., due to a missing end time
+// each such subtitle ends after the maximum duration, or at the start of the next subtitle, whichever is earlier
+pub fn clamp_max_duration(subtitles: &Subtitles, max_duration_ms: i64) -> Result<Subtitles> {
+    let mut clamped = subtitles.clone().to_vec();
+    clamped.sort_by_key(|s| s.start_time);
+    let next_starts = clamped
+        .iter()
+        .skip(1)
+        .map(|s| Some(timestamp_to_millis(&s.start_time)))
+        .chain([None])
+        .collect::<Vec<_>>();
+
+    for (subtitle, next_start) in clamped.iter_mut().zip(next_starts) {
+        if duration_millis(subtitle) <= max_duration_ms {
+            continue;
+        }
+        let start = timestamp_to_millis(&subtitle.start_time);
+        let mut end = start + max_duration_ms;
+        if let Some(next_start) = next_start {
+            // a subtitle sharing its start time with the next one is left at the maximum duration
+            if next_start > start {
+                end = end.min(next_start);
+            }
+        }
+        subtitle.end_time = millis_to_timestamp(end);
+    }
+
+    renumber(&mut clamped);
+    Ok(Subtitles::new_from_vec(clamped))
+}
+
+// the text transformations performed by normalize_text, each of which is opt-in
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NormalizeOptions {
+    // converts full-width ASCII characters (e.g., ＡＢＣ！) and the ideographic space to their half-width forms
+    pub full_width: bool,
+    // converts curly quotes to straight quotes
+    pub smart_quotes: bool,
+    // collapses runs of whitespace within a line into a single space, and trims each line
+    pub whitespace: bool,
+}
+
+pub fn normalize_text(subs: &mut Subtitles, options: &NormalizeOptions) -> Result<()> {
+    for subtitle in subs.into_iter() {
+        subtitle.text = normalize_string(&subtitle.text, options);
+    }
+    Ok(())
+}
+
+fn normalize_string(string: &str, options: &NormalizeOptions) -> String {
+    let mut normalized: String = string
+        .chars()
+        .map(|c| match c {
+            // the full-width forms of '!' through '~' are offset by a constant from their ASCII counterparts
+            '\u{FF01}'..='\u{FF5E}' if options.full_width => {
+                char::from_u32(c as u32 - 0xFEE0).unwrap_or(c)
+            }
+            '\u{3000}' if options.full_width => ' ',
+            '‘' | '’' | '‚' | '‛' if options.smart_quotes => '\'',
+            '“' | '”' | '„' | '‟' if options.smart_quotes => '"',
+            _ => c,
+        })
+        .collect();
+
+    if options.whitespace {
+        normalized = normalized
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    normalized
+}
+
+// where a substitution rule is allowed to match
+// substitutions are always scoped to words, since blindly replacing characters would corrupt valid text
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OcrRuleContext {
+    // the rule only matches an entire word, e.g., "l" -> "I"
+    Word,
+    // the rule only matches the start of a word whose remaining letters are all uppercase, e.g., "lNSIDE" -> "INSIDE"
+    UppercaseWordStart,
+    // the rule matches anywhere inside a word whose other characters are all uppercase letters, e.g., "N0" -> "NO"
+    InsideUppercaseWord,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OcrRule {
+    pub find: String,
+    pub replace: String,
+    pub context: OcrRuleContext,
+}
+
+impl OcrRule {
+    fn new(find: &str, replace: &str, context: OcrRuleContext) -> Self {
+        OcrRule {
+            find: find.to_string(),
+            replace: replace.to_string(),
+            context,
+        }
+    }
+
+    fn apply(&self, word: &str) -> Option<String> {
+        match self.context {
+            OcrRuleContext::Word => (word == self.find).then(|| self.replace.clone()),
+            OcrRuleContext::UppercaseWordStart => {
+                let rest = word.strip_prefix(self.find.as_str())?;
+                let mut letters = rest.chars().filter(|c| c.is_alphabetic()).peekable();
+                (letters.peek().is_some() && letters.all(|c| c.is_uppercase()))
+                    .then(|| format!("{0}{rest}", self.replace))
+            }
+            OcrRuleContext::InsideUppercaseWord => {
+                if !word.contains(self.find.as_str()) {
+                    return None;
+                }
+                let others = word.replace(self.find.as_str(), "");
+                (!others.is_empty()
+                    && others
+                        .chars()
+                        .all(|c| c.is_alphabetic() && c.is_uppercase()))
+                .then(|| word.replace(self.find.as_str(), &self.replace))
+            }
+        }
+    }
+}
+
+// a conservative set of substitutions for errors commonly produced by OCR
+pub fn default_ocr_rules() -> Vec<OcrRule> {
+    use OcrRuleContext::*;
+    vec![
+        OcrRule::new("l", "I", Word),
+        OcrRule::new("l'm", "I'm", Word),
+        OcrRule::new("l'll", "I'll", Word),
+        OcrRule::new("l've", "I've", Word),
+        OcrRule::new("l'd", "I'd", Word),
+        OcrRule::new("lt", "It", Word),
+        OcrRule::new("lt's", "It's", Word),
+        OcrRule::new("ls", "Is", Word),
+        OcrRule::new("tbe", "the", Word),
+        OcrRule::new("l", "I", UppercaseWordStart),
+        OcrRule::new("0", "O", InsideUppercaseWord),
+    ]
+}
+
+// applies the given substitution rules to every word of every subtitle
+// the first rule that matches a word is applied, and surrounding punctuation is preserved
+pub fn ocr_fix(subs: &mut Subtitles, rules: &[OcrRule]) -> Result<()> {
+    for subtitle in subs.into_iter() {
+        subtitle.text = subtitle
+            .text
+            .lines()
+            .map(|line| {
+                line.split(' ')
+                    .map(|word| ocr_fix_word(word, rules))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+    Ok(())
+}
+
+fn ocr_fix_word(word: &str, rules: &[OcrRule]) -> String {
+    // separate leading and trailing punctuation (e.g., quotes, commas, dialogue dashes) from the word itself
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '\'';
+    let trimmed_start = word.trim_start_matches(|c: char| !is_word_char(c));
+    let core = trimmed_start.trim_end_matches(|c: char| !is_word_char(c));
+    if core.is_empty() {
+        return word.to_string();
+    }
+    let prefix = &word[..word.len() - trimmed_start.len()];
+    let suffix = &trimmed_start[core.len()..];
+
+    match rules.iter().find_map(|rule| rule.apply(core)) {
+        Some(fixed) => format!("{prefix}{fixed}{suffix}"),
+        None => word.to_string(),
+    }
+}
+
+// reverses mojibake caused by utf-8 text being decoded as windows-1252 (or latin-1) and encoded as utf-8 again, e.g., "Ã©" for "é"
+// a subtitle is only changed if the reversed text is valid utf-8 and contains fewer signs of mojibake than the original
+// returns the number of subtitles that were changed
+pub fn fix_mojibake(subs: &mut Subtitles) -> Result<usize> {
+    let mut fixed = 0;
+    for subtitle in subs.into_iter() {
+        if let Some(text) = reverse_mojibake(&subtitle.text) {
+            subtitle.text = text;
+            fixed += 1;
+        }
+    }
+    Ok(fixed)
+}
+
+fn reverse_mojibake(string: &str) -> Option<String> {
+    let original_score = mojibake_score(string);
+    if original_score == 0 {
+        return None;
+    }
+    // recover the bytes that were wrongly decoded, one byte per character
+    let mut bytes = vec![];
+    for c in string.chars() {
+        bytes.push(mojibake_byte(c)?);
+    }
+    let reversed = String::from_utf8(bytes).ok()?;
+    (mojibake_score(&reversed) < original_score).then_some(reversed)
+}
+
+// the byte that the given character was decoded from, if it was decoded as windows-1252 or latin-1
+fn mojibake_byte(c: char) -> Option<u8> {
+    // latin-1 maps every byte to the code point of the same value, which includes the c1 control characters
+    if (c as u32) < 0x100 {
+        return Some(c as u32 as u8);
+    }
+    let mut buffer = [0; 4];
+    let (bytes, _, had_errors) = WINDOWS_1252.encode(c.encode_utf8(&mut buffer));
+    match (had_errors, bytes.as_ref()) {
+        (false, [byte]) => Some(*byte),
+        _ => None,
+    }
+}
+
+// counts the signs of mojibake in the given text: replacement characters, c1 control characters,
+// and characters that look like the lead byte of a utf-8 sequence followed by a continuation byte
+fn mojibake_score(string: &str) -> usize {
+    let chars = string.chars().collect::<Vec<_>>();
+    let odd_chars = chars
+        .iter()
+        .filter(|c| **c == '\u{FFFD}' || ('\u{80}'..='\u{9F}').contains(*c))
+        .count();
+    let odd_pairs = chars
+        .windows(2)
+        .filter(|pair| {
+            ('\u{C2}'..='\u{F4}').contains(&pair[0])
+                && mojibake_byte(pair[1]).is_some_and(|byte| (0x80..=0xBF).contains(&byte))
+        })
+        .count();
+    odd_chars + odd_pairs
+}
+
+fn duration_millis(subtitle: &Subtitle) -> i64 {
+    timestamp_to_millis(&subtitle.end_time) - timestamp_to_millis(&subtitle.start_time)
+}
+
+// assigns each subtitle its numerical order in the file, starting from 1
+// every operation that removes, adds, or reorders subtitles calls this, and subtitles are renumbered again when written
+// so that written files never have duplicate or missing numbers
+pub fn renumber(subtitles: &mut [Subtitle]) {
+    for (index, subtitle) in subtitles.iter_mut().enumerate() {
+        subtitle.num = index + 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::time::parse_timestamp;
+
+    fn subtitles(cues: &[(usize, &str, &str, &str)]) -> Subtitles {
+        Subtitles::new_from_vec(
+            cues.iter()
+                .map(|(num, start, end, text)| {
+                    Subtitle::new(
+                        *num,
+                        millis_to_timestamp(parse_timestamp(start).unwrap()),
+                        millis_to_timestamp(parse_timestamp(end).unwrap()),
+                        text.to_string(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn timings(subtitles: &Subtitles) -> Vec<(String, String)> {
+        subtitles
+            .clone()
+            .to_vec()
+            .iter()
+            .map(|s| (s.start_time.to_string(), s.end_time.to_string()))
+            .collect()
+    }
+
+    fn shifted(seconds: f32) -> Vec<(String, String)> {
+        let subtitles = subtitles(&[
+            (1, "00:00:01,000", "00:00:02,000", "one"),
+            (2, "00:00:10,250", "00:00:11,000", "two"),
+        ]);
+        timings(&shift_seconds(&subtitles, seconds).unwrap())
+    }
+
+    fn pairs(timings: &[(&str, &str)]) -> Vec<(String, String)> {
+        timings
+            .iter()
+            .map(|(start, end)| (start.to_string(), end.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn shift_seconds_rounds_to_the_nearest_millisecond() {
+        assert_eq!(
+            shifted(1.9999),
+            pairs(&[
+                ("00:00:03,000", "00:00:04,000"),
+                ("00:00:12,250", "00:00:13,000")
+            ])
+        );
+        assert_eq!(
+            shifted(2.5),
+            pairs(&[
+                ("00:00:03,500", "00:00:04,500"),
+                ("00:00:12,750", "00:00:13,500")
+            ])
+        );
+    }
+
+    #[test]
+    fn shift_seconds_shifts_earlier_when_negative() {
+        assert_eq!(
+            shifted(-0.5),
+            pairs(&[
+                ("00:00:00,500", "00:00:01,500"),
+                ("00:00:09,750", "00:00:10,500")
+            ])
+        );
+    }
+
+    #[test]
+    fn shift_seconds_clamps_at_zero() {
+        assert_eq!(
+            shifted(-1.5),
+            pairs(&[
+                ("00:00:00,000", "00:00:00,500"),
+                ("00:00:08,750", "00:00:09,500")
+            ])
+        );
+    }
+
+    #[test]
+    fn chained_operations_number_subtitles_contiguously() {
+        let mut subtitles = subtitles(&[
+            (5, "00:00:01,000", "00:00:02,000", "[music]"),
+            (5, "00:00:03,000", "00:00:05,000", "Hello"),
+            (9, "00:00:06,000", "00:00:06,200", "Hm"),
+            (9, "00:00:07,000", "00:00:09,000", "Goodbye"),
+        ]);
+        remove_bracketed_info(&mut subtitles).unwrap();
+        let shifted = shift_seconds(&subtitles, 1.0).unwrap();
+        let filtered = filter_min_duration(&shifted, 500).unwrap().to_vec();
+
+        let texts = filtered.iter().map(|s| s.text.as_str()).collect_vec();
+        assert_eq!(texts, ["Hello", "Goodbye"]);
+        let nums = filtered.iter().map(|s| s.num).collect_vec();
+        assert_eq!(nums, (1..=filtered.len()).collect_vec());
+    }
+
+    #[test]
+    fn strip_html_decodes_entities() {
+        assert_eq!(strip_html_string("Tom &amp; Jerry"), "Tom & Jerry");
+        assert_eq!(strip_html_string("it&#39;s"), "it's");
+        assert_eq!(strip_html_string("<i>it&#39;s</i>"), "it's");
+    }
+
+    #[test]
+    fn strip_html_replaces_non_breaking_spaces() {
+        assert_eq!(strip_html_string("a&nbsp;b"), "a b");
+    }
+
+    #[test]
+    fn unmatched_brackets_keep_the_text_after_them() {
+        assert_eq!(
+            remove_bracketed_string("I said (quietly"),
+            "I said (quietly"
+        );
+        assert_eq!(
+            remove_bracketed_string("[Music] I said (quietly [whispering]"),
+            "I said (quietly"
+        );
+        assert_eq!(
+            remove_bracketed_string("a stray [ bracket"),
+            "a stray [ bracket"
+        );
+        assert_eq!(remove_bracketed_string("（小声で"), "（小声で");
+        assert_eq!(remove_bracketed_string("(Laughs) hi"), "hi");
+    }
+
+    fn texts(subtitles: &Subtitles) -> Vec<String> {
+        subtitles
+            .clone()
+            .to_vec()
+            .into_iter()
+            .map(|s| s.text)
+            .collect()
+    }
+
+    #[test]
+    fn joining_split_dialogue_restores_the_dashed_cue() {
+        let dialogue = subtitles(&[
+            (
+                1,
+                "00:00:01,000",
+                "00:00:03,000",
+                "- Where are you going?\n- Home.",
+            ),
+            (
+                2,
+                "00:00:04,000",
+                "00:00:05,000",
+                "{\\an8}- Wait!\n- For what?\nIt's late.",
+            ),
+            (3, "00:00:06,000", "00:00:07,000", "- Not dialogue"),
+        ]);
+        let split = split_dialogue(&dialogue).unwrap();
+        assert_eq!(split.len(), 5);
+
+        let joined = join_dialogue(&split).unwrap();
+        assert_eq!(texts(&joined), texts(&dialogue));
+        assert_eq!(timings(&joined), timings(&dialogue));
+    }
+
+    #[test]
+    fn splitting_joined_dialogue_restores_the_simultaneous_cues() {
+        let simultaneous = subtitles(&[
+            (1, "00:00:01,000", "00:00:03,000", "Where are you going?"),
+            (2, "00:00:01,000", "00:00:03,000", "Home.\nIt's late."),
+            (3, "00:00:04,000", "00:00:05,000", "Alone"),
+        ]);
+        let joined = join_dialogue(&simultaneous).unwrap();
+        assert_eq!(
+            texts(&joined),
+            ["- Where are you going?\n- Home.\nIt's late.", "Alone"]
+        );
+
+        let split = split_dialogue(&joined).unwrap();
+        assert_eq!(texts(&split), texts(&simultaneous));
+        assert_eq!(timings(&split), timings(&simultaneous));
+        let nums = split.to_vec().iter().map(|s| s.num).collect::<Vec<_>>();
+        assert_eq!(nums, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+use crate::core::data::{Muxer, SubtitlesTrack};
+use crate::core::ffmpeg::{self, number_of_subtitle_streams};
+use crate::core::mkvmerge;
+
+// adds the given subtitles files to the video as new tracks with the given muxer, in the order given
+// both muxers support each track's language, name, and default and forced flags, and behave the same otherwise
+// except that extra mkvmerge arguments (--mkvmerge-args) are only given to mkvmerge
+// when replace_language is set, existing subtitle tracks with the same language code as a new track are removed from the video
+// the new tracks are placed either before or after the video's existing subtitle tracks
+// when verify is set, the output is checked with ffprobe afterwards, since the muxer may silently drop a track (e.g., for malformed srt files)
+// the given font files are attached to the output, which only mkvmerge supports
+#[allow(clippy::too_many_arguments)]
+pub fn add_subtitles_tracks(
+    muxer: Muxer,
+    video_file: &Path,
+    new_tracks: &[SubtitlesTrack],
+    font_files: &[PathBuf],
+    replace_language: bool,
+    new_tracks_first: bool,
+    verify: bool,
+    output_path: &Path,
+) -> Result<()> {
+    if !font_files.is_empty() && !matches!(muxer, Muxer::MKVMERGE) {
+        return Err(anyhow!(
+            "fonts can only be attached by mkvmerge, but the muxer is {0}",
+            muxer.tool()
+        ));
+    }
+    let expected_subtitle_tracks = match muxer {
+        Muxer::MKVMERGE => mkvmerge::add_subtitles_tracks(
+            video_file,
+            new_tracks,
+            font_files,
+            replace_language,
+            new_tracks_first,
+            output_path,
+        )?,
+        Muxer::FFMPEG => ffmpeg::add_subtitles_tracks(
+            video_file,
+            new_tracks,
+            replace_language,
+            new_tracks_first,
+            output_path,
+        )?,
+    };
+
+    if verify {
+        verify_subtitle_tracks(output_path, expected_subtitle_tracks, muxer)?;
+    }
+
+    Ok(())
+}
+
+// checks that the given video contains the expected number of subtitles tracks
+fn verify_subtitle_tracks(video_file: &Path, expected: usize, muxer: Muxer) -> Result<()> {
+    let found = number_of_subtitle_streams(video_file)? as usize;
+    if found != expected {
+        return Err(anyhow!(
+            "expected {expected} subtitles tracks in {video_file:#?} after muxing, but found {found}\n{0} may have dropped a track, check that the subtitles files are valid",
+            muxer.tool()
+        ));
+    }
+    log::debug!("verified that {video_file:#?} contains {found} subtitles tracks");
+    Ok(())
+}
@@ -0,0 +1,15 @@
+// this file contains functions for searching the text of subtitles
+
+use regex::Regex;
+use srtlib::{Subtitle, Subtitles};
+
+// returns the subtitles whose text matches the given pattern, in order of their start time
+pub fn grep(subtitles: &Subtitles, pattern: &Regex) -> Vec<Subtitle> {
+    let mut matches = subtitles
+        .into_iter()
+        .filter(|subtitle| pattern.is_match(&subtitle.text))
+        .cloned()
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|subtitle| subtitle.start_time);
+    matches
+}
@@ -1,50 +1,248 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use serde::Serialize;
 use srtlib::Subtitles;
-use std::{hash, path::Path, process::Command};
+use std::iter::zip;
+use std::path::Path;
+use std::process::Output;
+use std::time::Instant;
 
-use crate::core::data::{pretty_cmd, pretty_output};
+use crate::core::data::{extra_tool_args, pretty_cmd, pretty_output, tool_command};
+use crate::core::modify::shift_millis;
+use crate::core::time::timestamp_to_millis;
 
-use super::data::{hash_subtitles, SyncTool, TMP_DIRECTORY};
+use super::data::{default_tmp_directory, hash_path, hash_subtitles, SyncTool};
 
-pub fn sync(reference: &Subtitles, unsynced: &Subtitles, method: &SyncTool) -> Result<Subtitles> {
-    match method {
-        SyncTool::FFSUBSYNC => sync_ffsubsync(reference, unsynced),
+// describes how a set of subtitles was synced, e.g., for auditing the results of a batch
+#[derive(Serialize, Debug, Clone)]
+pub struct SyncReport {
+    // the tool that succeeded
+    pub tool: SyncTool,
+    // the median amount the subtitles were shifted by, which is positive when they were shifted later
+    pub offset_ms: i64,
+    // the score of the alignment, if the tool reports one; higher is better
+    pub score: Option<f64>,
+    // how long the sync took, including any tools that failed
+    pub duration_ms: u128,
+}
+
+// as sync_in, using the default temporary directory
+pub fn sync(
+    reference: &Subtitles,
+    unsynced: &Subtitles,
+    tools: &[SyncTool],
+) -> Result<(Subtitles, SyncReport)> {
+    sync_in(default_tmp_directory(), reference, unsynced, tools)
+}
+
+// syncs the unsynced subtitles to the reference, trying each of the given tools in order until one succeeds
+// the files given to the tools are written to the given temporary directory
+pub fn sync_in(
+    tmp_dir: &Path,
+    reference: &Subtitles,
+    unsynced: &Subtitles,
+    tools: &[SyncTool],
+) -> Result<(Subtitles, SyncReport)> {
+    sync_with_tools(unsynced, tools, |tool| {
+        sync_with_tool(tmp_dir, reference, unsynced, tool)
+    })
+}
+
+// as sync_to_video_in, using the default temporary directory
+pub fn sync_to_video(
+    video_file: &Path,
+    unsynced: &Subtitles,
+    tools: &[SyncTool],
+) -> Result<(Subtitles, SyncReport)> {
+    sync_to_video_in(default_tmp_directory(), video_file, unsynced, tools)
+}
+
+// syncs the unsynced subtitles to the audio of the given video, for when there are no reference subtitles to sync to
+// this is slower than syncing to subtitles, since the tool has to extract and analyze the audio
+pub fn sync_to_video_in(
+    tmp_dir: &Path,
+    video_file: &Path,
+    unsynced: &Subtitles,
+    tools: &[SyncTool],
+) -> Result<(Subtitles, SyncReport)> {
+    sync_with_tools(unsynced, tools, |tool| match tool {
+        SyncTool::FFSUBSYNC => sync_ffsubsync_to_video(tmp_dir, video_file, unsynced),
+    })
+}
+
+// tries each of the given tools in order until one succeeds, reporting how the subtitles were synced
+fn sync_with_tools(
+    unsynced: &Subtitles,
+    tools: &[SyncTool],
+    sync_with_tool: impl Fn(&SyncTool) -> Result<(Subtitles, Option<f64>)>,
+) -> Result<(Subtitles, SyncReport)> {
+    let start = Instant::now();
+    let mut errors = vec![];
+    for tool in tools {
+        match sync_with_tool(tool) {
+            Ok((synced, score)) => {
+                let report = SyncReport {
+                    tool: *tool,
+                    offset_ms: median_offset(unsynced, &synced),
+                    score,
+                    duration_ms: start.elapsed().as_millis(),
+                };
+                return Ok((synced, report));
+            }
+            Err(error) => {
+                log::warn!("syncing with {tool:?} failed, trying the next tool if any: {error}");
+                errors.push(format!("{tool:?}: {error}"));
+            }
+        }
     }
+
+    Err(anyhow!("no sync tool succeeded:\n{0}", errors.join("\n")))
 }
 
-fn sync_ffsubsync(reference: &Subtitles, unsynced: &Subtitles) -> Result<Subtitles> {
+// as quick_sync_in, using the default temporary directory
+pub fn quick_sync(
+    reference: &Subtitles,
+    unsynced: &Subtitles,
+    tools: &[SyncTool],
+    sample_ms: i64,
+) -> Result<(Subtitles, SyncReport)> {
+    quick_sync_in(
+        default_tmp_directory(),
+        reference,
+        unsynced,
+        tools,
+        sample_ms,
+    )
+}
+
+// syncs using only the subtitles that start within the first sample_ms of both sets of subtitles, which is much faster
+// the offset found for the sample is then applied to all of the unsynced subtitles
+// this assumes the unsynced subtitles are off by a constant offset, and won't correct drift (e.g., from a different framerate)
+pub fn quick_sync_in(
+    tmp_dir: &Path,
+    reference: &Subtitles,
+    unsynced: &Subtitles,
+    tools: &[SyncTool],
+    sample_ms: i64,
+) -> Result<(Subtitles, SyncReport)> {
+    let sample = |subtitles: &Subtitles| {
+        let mut sampled = subtitles
+            .clone()
+            .to_vec()
+            .into_iter()
+            .filter(|s| timestamp_to_millis(&s.start_time) < sample_ms)
+            .collect::<Vec<_>>();
+        sampled.sort_by_key(|s| s.start_time);
+        sampled
+    };
+    let reference_sample = sample(reference);
+    let unsynced_sample = sample(unsynced);
+    if reference_sample.is_empty() || unsynced_sample.is_empty() {
+        return Err(anyhow!(
+            "there are no subtitles in the first {sample_ms}ms to sync with, try a longer sample"
+        ));
+    }
+
+    // the report's offset is the median offset of the sample, which is applied to all of the subtitles
+    let (_, report) = sync_in(
+        tmp_dir,
+        &Subtitles::new_from_vec(reference_sample),
+        &Subtitles::new_from_vec(unsynced_sample),
+        tools,
+    )?;
+    log::info!("quick sync found an offset of {0}ms", report.offset_ms);
+
+    let synced = shift_millis(unsynced, report.offset_ms)?;
+    Ok((synced, report))
+}
+
+// the median difference between the start times of the subtitles before and after syncing
+// the sync tools shift each subtitle, rather than adding or removing any, so the subtitles can be matched by their order
+// the median is used so that a few subtitles that were moved individually don't skew the offset
+fn median_offset(before: &Subtitles, after: &Subtitles) -> i64 {
+    let sorted = |subtitles: &Subtitles| {
+        let mut sorted = subtitles.clone().to_vec();
+        sorted.sort_by_key(|s| s.start_time);
+        sorted
+    };
+    let mut offsets = zip(sorted(before), sorted(after))
+        .map(|(before, after)| {
+            timestamp_to_millis(&after.start_time) - timestamp_to_millis(&before.start_time)
+        })
+        .collect::<Vec<_>>();
+    if offsets.is_empty() {
+        return 0;
+    }
+    offsets.sort();
+    offsets[offsets.len() / 2]
+}
+
+fn sync_with_tool(
+    tmp_dir: &Path,
+    reference: &Subtitles,
+    unsynced: &Subtitles,
+    tool: &SyncTool,
+) -> Result<(Subtitles, Option<f64>)> {
+    match tool {
+        SyncTool::FFSUBSYNC => sync_ffsubsync(tmp_dir, reference, unsynced),
+    }
+}
+
+fn sync_ffsubsync(
+    tmp_dir: &Path,
+    reference: &Subtitles,
+    unsynced: &Subtitles,
+) -> Result<(Subtitles, Option<f64>)> {
     let reference_hash = hash_subtitles(reference);
-    let reference_file = TMP_DIRECTORY
-        .get()
-        .unwrap()
-        .join(format!("sync_ref_{reference_hash}.srt"));
+    let reference_file = tmp_dir.join(format!("sync_ref_{reference_hash}.srt"));
     reference.write_to_file(&reference_file, None)?;
 
+    run_ffsubsync(
+        tmp_dir,
+        &reference_file,
+        &["--reference-encoding", "utf-8"],
+        reference_hash,
+        unsynced,
+    )
+}
+
+fn sync_ffsubsync_to_video(
+    tmp_dir: &Path,
+    video_file: &Path,
+    unsynced: &Subtitles,
+) -> Result<(Subtitles, Option<f64>)> {
+    // ffsubsync extracts the audio of a video reference itself, so there's no reference encoding to give
+    run_ffsubsync(tmp_dir, video_file, &[], hash_path(video_file), unsynced)
+}
+
+// syncs the unsynced subtitles to the given reference file, which may be subtitles or a video
+// the reference hash names the temporary output, so that syncing the same subtitles to different references doesn't collide
+fn run_ffsubsync(
+    tmp_dir: &Path,
+    reference_file: &Path,
+    reference_args: &[&str],
+    reference_hash: u64,
+    unsynced: &Subtitles,
+) -> Result<(Subtitles, Option<f64>)> {
     let unsynced_hash = hash_subtitles(unsynced);
-    let unsynced_file = TMP_DIRECTORY
-        .get()
-        .unwrap()
-        .join(format!("unsynced_{unsynced_hash}.srt"));
+    let unsynced_file = tmp_dir.join(format!("unsynced_{unsynced_hash}.srt"));
     unsynced.write_to_file(&unsynced_file, None)?;
 
-    let tmp_file = TMP_DIRECTORY
-        .get()
-        .unwrap()
-        .join(format!("sync_out_{reference_hash}_{unsynced_hash}.srt"));
+    let tmp_file = tmp_dir.join(format!("sync_out_{reference_hash}_{unsynced_hash}.srt"));
 
-    let mut command = Command::new("ffsubsync");
+    let mut command = tool_command("ffsubsync");
     command
         .arg(reference_file.as_os_str())
         .arg("-i")
         .arg(unsynced_file.as_os_str())
         .arg("--encoding") // ensure we use only utf-8 for encoding because the encoding inference gets it wrong sometimes
         .arg("utf-8")
-        .arg("--reference-encoding")
-        .arg("utf-8")
+        .args(reference_args)
         .arg("--output-encoding")
         .arg("utf-8")
         .arg("-o")
-        .arg(tmp_file.as_os_str());
+        .arg(tmp_file.as_os_str())
+        .args(extra_tool_args("ffsubsync"));
     log::debug!("{0}", pretty_cmd(&command));
     let output = command.output()?;
 
@@ -58,5 +256,65 @@ fn sync_ffsubsync(reference: &Subtitles, unsynced: &Subtitles) -> Result<Subtitl
     log::trace!("{0}", pretty_output(&output));
     let subtitles = Subtitles::parse_from_file(tmp_file, None)?;
 
-    Ok(subtitles)
+    Ok((subtitles, ffsubsync_score(&output)))
+}
+
+// ffsubsync logs the score of the alignment it chose, e.g., "score: 12345.678"
+fn ffsubsync_score(output: &Output) -> Option<f64> {
+    let logs = format!(
+        "{0}\n{1}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Regex::new(r"score: ([-\d.]+)")
+        .unwrap()
+        .captures_iter(&logs)
+        .last()
+        .and_then(|captures| captures[1].parse().ok())
+}
+
+// as sync_score_in, using the default temporary directory
+pub fn sync_score(video_file: &Path, subtitles_file: &Path) -> Result<f64> {
+    sync_score_in(default_tmp_directory(), video_file, subtitles_file)
+}
+
+// scores how well the given subtitles file fits the audio of the given video, by syncing them with ffsubsync
+// higher scores mean a better fit, so the scores of one subtitles file against several videos can be compared to find its video
+// scores of different subtitles files aren't comparable with each other
+// ffsubsync's synced output is written to the given temporary directory
+pub fn sync_score_in(tmp_dir: &Path, video_file: &Path, subtitles_file: &Path) -> Result<f64> {
+    let tmp_file = tmp_dir.join(format!(
+        "score_{0}_{1}.srt",
+        hash_path(video_file),
+        hash_path(subtitles_file)
+    ));
+
+    let mut command = tool_command("ffsubsync");
+    command
+        .arg(video_file.as_os_str()) // use the video's audio as the reference
+        .arg("-i")
+        .arg(subtitles_file.as_os_str())
+        .arg("-o")
+        .arg(tmp_file.as_os_str())
+        .args(extra_tool_args("ffsubsync"));
+    log::debug!("{0}", pretty_cmd(&command));
+    let output = command.output()?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "command was not successfully executed:\n{0}\n{1}",
+            pretty_cmd(&command),
+            pretty_output(&output)
+        ));
+    }
+    log::trace!("{0}", pretty_output(&output));
+
+    let score = ffsubsync_score(&output).with_context(|| {
+        format!(
+            "could not find the sync score in the output of:\n{0}",
+            pretty_cmd(&command)
+        )
+    })?;
+    log::debug!("{subtitles_file:#?} scored {score} against {video_file:#?}");
+    Ok(score)
 }
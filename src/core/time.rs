@@ -0,0 +1,88 @@
+// this file contains functions for parsing and converting timestamps
+
+use anyhow::{anyhow, Context, Result};
+use srtlib::Timestamp;
+
+// parses a timestamp of the form HH:MM:SS.mmm into a number of milliseconds
+// leading components may be omitted (e.g., 01:30.5 or 90.5), and either '.' or ',' may separate the milliseconds
+pub fn parse_timestamp(timestamp: &str) -> Result<i64> {
+    let trimmed = timestamp.trim();
+    let (hms, fraction) = match trimmed.rsplit_once(['.', ',']) {
+        Some((hms, fraction)) => (hms, Some(fraction)),
+        None => (trimmed, None),
+    };
+
+    let components: Vec<&str> = hms.split(':').collect();
+    if components.len() > 3 {
+        return Err(anyhow!(
+            "timestamp {timestamp:?} has too many components, expected HH:MM:SS.mmm"
+        ));
+    }
+
+    let mut total_seconds: i64 = 0;
+    for (index, component) in components.iter().enumerate() {
+        let value: i64 = component
+            .parse()
+            .with_context(|| format!("could not parse {component:?} in timestamp {timestamp:?}"))?;
+        // every component except the leading one must be a valid minute/second count
+        if index > 0 && value >= 60 {
+            return Err(anyhow!(
+                "component {component:?} in timestamp {timestamp:?} must be less than 60"
+            ));
+        }
+        total_seconds = total_seconds * 60 + value;
+    }
+
+    let millis = match fraction {
+        Some(fraction) => {
+            if fraction.is_empty()
+                || fraction.len() > 3
+                || !fraction.chars().all(|c| c.is_ascii_digit())
+            {
+                return Err(anyhow!(
+                    "fractional part {fraction:?} in timestamp {timestamp:?} must be 1-3 digits"
+                ));
+            }
+            // pad to three digits so that .5 is 500ms rather than 5ms
+            format!("{fraction:0<3}").parse::<i64>()?
+        }
+        None => 0,
+    };
+
+    Ok(total_seconds * 1000 + millis)
+}
+
+// parses a duration into a number of milliseconds
+// accepts a number of milliseconds or seconds with a unit suffix (e.g., 500ms or 1.5s), or a timestamp as accepted by parse_timestamp
+pub fn parse_duration(duration: &str) -> Result<i64> {
+    let trimmed = duration.trim();
+    if let Some(millis) = trimmed.strip_suffix("ms") {
+        return millis
+            .trim()
+            .parse::<i64>()
+            .with_context(|| format!("could not parse milliseconds in duration {duration:?}"));
+    }
+    if let Some(seconds) = trimmed.strip_suffix('s') {
+        return parse_timestamp(seconds)
+            .with_context(|| format!("could not parse seconds in duration {duration:?}"));
+    }
+    parse_timestamp(trimmed)
+}
+
+// converts an srt timestamp to a number of milliseconds
+pub fn timestamp_to_millis(timestamp: &Timestamp) -> i64 {
+    let (hours, minutes, seconds, millis) = timestamp.get();
+    ((hours as i64 * 60 + minutes as i64) * 60 + seconds as i64) * 1000 + millis as i64
+}
+
+// converts a number of milliseconds to an srt timestamp
+// negative values are clamped to zero, since srt timestamps cannot be negative
+pub fn millis_to_timestamp(millis: i64) -> Timestamp {
+    let millis = millis.max(0);
+    Timestamp::new(
+        (millis / 3_600_000) as u8,
+        (millis / 60_000 % 60) as u8,
+        (millis / 1000 % 60) as u8,
+        (millis % 1000) as u16,
+    )
+}